@@ -11,6 +11,7 @@
 //! Run this with:
 //! ```
 //! cargo run --example auto_boxed_future
+//! cargo run --example auto_boxed_future -- --all
 //! ```
 //!
 //! Then in another terminal:
@@ -21,9 +22,16 @@
 //! In tokio-console, look for:
 //! - "auto-boxed-future" warnings
 //! - Task details showing the future was auto-boxed
+//!
+//! The scenarios spawn through `diagnostics::spawn`, which logs the futures
+//! over 1 KB with `RUST_LOG=warn`.
 
+use std::process::ExitCode;
 use std::time::Duration;
 
+use tokio_console_demo::diagnostics;
+use tokio_console_demo::scenario::{Registry, Scenario};
+
 // Very large struct that causes auto-boxing when used in spawned tasks
 #[derive(Clone)]
 #[allow(dead_code)] // Some fields intentionally unused for size demonstration
@@ -81,7 +89,7 @@ fn spawn_with_large_closure() {
     let data2 = VeryLargeStruct::new();
 
     // ❌ tokio::spawn will auto-box this because it's too large
-    tokio::spawn(async move {
+    diagnostics::spawn("auto-boxed-future/large-closure", async move {
         loop {
             tokio::time::sleep(Duration::from_millis(100)).await;
             let _ = data1.compute() + data2.compute();
@@ -91,6 +99,8 @@ fn spawn_with_large_closure() {
 }
 
 // ✅ GOOD: Use Box to explicitly control boxing
+// `cargo test --example auto_boxed_future` keeps it that way
+#[diagnostics::max_future_size(1024)]
 async fn good_explicit_box_task() {
     println!("[GOOD] Explicitly boxed task started");
 
@@ -198,6 +208,15 @@ mod size_demo {
             std::mem::size_of::<Box<VeryLargeStruct>>()
         );
         println!("Tokio auto-box threshold: ~2048 bytes");
+        // The futures themselves, as spawned: creating them runs nothing
+        println!(
+            "bad_auto_boxed_task() future: {} bytes",
+            std::mem::size_of_val(&bad_auto_boxed_task())
+        );
+        println!(
+            "good_explicit_box_task() future: {} bytes",
+            std::mem::size_of_val(&good_explicit_box_task())
+        );
         println!(
             "Our struct: {} KB",
             std::mem::size_of::<VeryLargeStruct>() / 1024
@@ -206,74 +225,103 @@ mod size_demo {
     }
 }
 
-fn main() {
-    console_subscriber::init();
-
-    let runtime = tokio::runtime::Runtime::new().unwrap();
-    runtime.block_on(async {
-        println!("=== Auto-Boxed Future Examples ===");
-        println!("This demonstrates futures that get auto-boxed by Tokio.");
-        println!("Connect with: tokio-console");
-        println!("Look for 'auto-boxed-future' warnings!\n");
-
-        size_demo::show_sizes();
-
-        // Scenario 1: Auto-boxed due to large state
-        println!("[Scenario 1] Large state causing auto-boxing (BAD)");
-        tokio::spawn(bad_auto_boxed_task());
-        tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // println!("\n[Scenario 2] Explicitly boxed data (GOOD)");
-        // tokio::spawn(good_explicit_box_task());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // // Scenario 2: Large closures
-        // println!("\n[Scenario 3] Large closures causing auto-boxing (BAD)");
-        // for _ in 0..3 {
-        //     spawn_with_large_closure();
-        //     tokio::time::sleep(Duration::from_millis(200)).await;
-        // }
-
-        // println!("\n[Scenario 4] Shared data with Arc (GOOD)");
-        // tokio::spawn(good_shared_data_task());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // // Scenario 3: Complex nested
-        // println!("\n[Scenario 5] Complex nested async (BAD)");
-        // tokio::spawn(bad_complex_nested());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // println!("\n[Scenario 6] Minimal state (GOOD)");
-        // tokio::spawn(good_minimal_state());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // // Normal tasks
-        // for i in 0..2 {
-        //     tokio::spawn(async move {
-        //         loop {
-        //             tokio::time::sleep(Duration::from_secs(2)).await;
-        //             println!("[Normal] Task {} (small future)", i);
-        //         }
-        //     });
-        // }
-
-        // Status monitoring
-        let mut tick = 0;
-        loop {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-            tick += 1;
-
-            println!("\n╔════════════════════════════════════════╗");
-            println!("║  Status Update #{}                    ║", tick);
-            println!("╠════════════════════════════════════════╣");
-            println!("║ Check tokio-console for:               ║");
-            println!("║ • auto-boxed-future warnings           ║");
-            println!("║ • Tasks marked as auto-boxed           ║");
-            println!("║ • Performance impact of auto-boxing    ║");
-            println!("║                                        ║");
-            println!("║ BAD tasks: Will show auto-box warnings ║");
-            println!("║ GOOD tasks: Should not be auto-boxed   ║");
-            println!("╚════════════════════════════════════════╝\n");
-        }
-    });
+// Normal tasks for comparison
+async fn normal_tasks() {
+    for i in 0..2 {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                println!("[Normal] Task {} (small future)", i);
+            }
+        });
+    }
+}
+
+// Scenarios spawn the task themselves: boxing it for the registry would hide
+// the size tokio sees
+/// The scenarios of this example, also run by `tests/console_warnings.rs`
+pub fn registry() -> Registry {
+    Registry::new(
+        "=== Auto-Boxed Future Examples ===\n\
+         This demonstrates futures that get auto-boxed by Tokio.\n\
+         Connect with: tokio-console\n\
+         Look for 'auto-boxed-future' warnings!\n\
+         BAD tasks: Will show auto-box warnings\n\
+         GOOD tasks: Should not be auto-boxed\n",
+    )
+    .scenario(
+        Scenario::new("auto-boxed-future/sizes", "Future size information", || {
+            Box::pin(async { size_demo::show_sizes() })
+        })
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "auto-boxed-future/large-state",
+            "Large state causing auto-boxing (BAD)",
+            || {
+                Box::pin(async {
+                    diagnostics::spawn("auto-boxed-future/large-state", bad_auto_boxed_task());
+                })
+            },
+        )
+        .by_default(),
+    )
+    .scenario(Scenario::new(
+        "auto-boxed-future/explicit-box",
+        "Explicitly boxed data (GOOD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("auto-boxed-future/explicit-box", good_explicit_box_task());
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "auto-boxed-future/large-closure",
+        "Large closures causing auto-boxing (BAD)",
+        || {
+            Box::pin(async {
+                for _ in 0..3 {
+                    spawn_with_large_closure();
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "auto-boxed-future/shared-arc",
+        "Shared data with Arc (GOOD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("auto-boxed-future/shared-arc", good_shared_data_task());
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "auto-boxed-future/complex-nested",
+        "Complex nested async (BAD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("auto-boxed-future/complex-nested", bad_complex_nested());
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "auto-boxed-future/minimal-state",
+        "Minimal state (GOOD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("auto-boxed-future/minimal-state", good_minimal_state());
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "auto-boxed-future/normal",
+        "Normal tasks for comparison",
+        || Box::pin(normal_tasks()),
+    ))
+}
+
+fn main() -> ExitCode {
+    registry().main()
 }
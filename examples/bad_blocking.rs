@@ -7,6 +7,7 @@
 //! Run this with:
 //! ```
 //! cargo run --example bad_blocking
+//! cargo run --example bad_blocking -- --scenario bad-blocking/spawn-blocking
 //! ```
 //!
 //! Then in another terminal:
@@ -20,115 +21,102 @@
 //! - "Never yielded" warnings
 //! - Other tasks being starved
 
+use std::process::ExitCode;
 use std::time::Duration;
 
-fn main() {
-    console_subscriber::init();
-
-    let runtime = tokio::runtime::Runtime::new().unwrap();
-    runtime.block_on(async {
-        println!("=== Bad Blocking Examples ===");
-        println!("This demonstrates EXTREMELY BAD async patterns!");
-        println!("Watch tokio-console for:");
-        println!("- High Busy percentage");
-        println!("- Long Poll times");
-        println!("- Never yielded warnings");
-        println!("- Task starvation\n");
-
-        // ❌ BAD: Long synchronous operations
-        tokio::spawn(async {
-            println!("[Bad Task 3] Mixed blocking patterns...");
-
-            let mut count = 0;
-            loop {
-                // Some async work
-                tokio::time::sleep(Duration::from_millis(100)).await;
-
-                println!("[Bad Task 3] Starting mixed blocking operations...");
-
-                // ❌ BAD: Mix of blocking operations
-                for i in 0..5 {
-                    // Blocking CPU work
-                    let mut sum = 0u64;
-                    for j in 0..200_000_000 {
-                        sum = sum.wrapping_add(j);
-                    }
-
-                    // Blocking I/O
-                    std::thread::sleep(Duration::from_millis(500));
-
-                    println!("[Bad Task 3] Iteration {} done", i);
-                    // Notice: NO await points during the work!
-                }
+use tokio_console_demo::scenario::{Registry, Scenario};
 
-                println!("[Bad Task 3] All blocking work done");
-                count += 1;
-                println!("[Bad Task 3] Count: {}", count);
-            }
-        });
-
-        // // ✅ GOOD: Using spawn_blocking for CPU-intensive work
-        // tokio::spawn(async {
-        //     println!("[Good Task] Proper handling of blocking operations...");
-
-        //     let mut count = 0;
-        //     loop {
-        //         // Some async work
-        //         tokio::time::sleep(Duration::from_millis(100)).await;
-
-        //         println!("[Good Task] Starting blocking operations...");
-
-        //         // ✅ GOOD: Move blocking work to dedicated thread pool
-        //         tokio::task::spawn_blocking(|| {
-        //             for i in 0..5 {
-        //                 // Blocking CPU work
-        //                 let mut sum = 0u64;
-        //                 for j in 0..200_000_000 {
-        //                     sum = sum.wrapping_add(j);
-        //                 }
-
-        //                 // Blocking I/O
-        //                 std::thread::sleep(Duration::from_millis(500));
-
-        //                 println!("[Good Task] Iteration {} done", i);
-        //             }
-
-        //             println!("[Good Task] All blocking work done");
-        //         })
-        //         .await
-        //         .unwrap();
-
-        //         // Update count AFTER blocking work completes
-        //         count += 1;
-        //         println!("[Good Task] Count: {}", count);
-        //     }
-        // });
-
-        // Monitoring task
-        tokio::spawn(async {
-            let mut report_count = 0;
-            loop {
-                tokio::time::sleep(Duration::from_secs(10)).await;
-                report_count += 1;
-
-                println!("\n╔════════════════════════════════════════╗");
-                println!("║  Status Report #{}                    ║", report_count);
-                println!("╠════════════════════════════════════════╣");
-                println!("║  Check tokio-console for:              ║");
-                println!("║  • Busy % > 50% (should be < 1%)      ║");
-                println!("║  • Poll times in SECONDS (should be µs)║");
-                println!("║  • Never yielded warnings             ║");
-                println!("║  • Good tasks being starved           ║");
-                println!("╚════════════════════════════════════════╝\n");
+// ❌ BAD: Long synchronous operations
+async fn mixed_blocking() {
+    println!("[Bad Task 3] Mixed blocking patterns...");
+
+    let mut count = 0;
+    loop {
+        // Some async work
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        println!("[Bad Task 3] Starting mixed blocking operations...");
+
+        // ❌ BAD: Mix of blocking operations
+        for i in 0..5 {
+            // Blocking CPU work
+            let mut sum = 0u64;
+            for j in 0..200_000_000 {
+                sum = sum.wrapping_add(j);
             }
-        });
 
-        // Keep running
-        println!("\n🔥 Running BAD async code...");
-        println!("💡 Open tokio-console to see the disaster!\n");
+            // Blocking I/O
+            std::thread::sleep(Duration::from_millis(500));
 
-        loop {
-            tokio::time::sleep(Duration::from_secs(60)).await;
+            println!("[Bad Task 3] Iteration {} done", i);
+            // Notice: NO await points during the work!
         }
-    });
+
+        println!("[Bad Task 3] All blocking work done");
+        count += 1;
+        println!("[Bad Task 3] Count: {}", count);
+    }
+}
+
+// ✅ GOOD: Using spawn_blocking for CPU-intensive work
+async fn spawn_blocking() {
+    println!("[Good Task] Proper handling of blocking operations...");
+
+    let mut count = 0;
+    loop {
+        // Some async work
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        println!("[Good Task] Starting blocking operations...");
+
+        // ✅ GOOD: Move blocking work to dedicated thread pool
+        tokio::task::spawn_blocking(|| {
+            for i in 0..5 {
+                // Blocking CPU work
+                let mut sum = 0u64;
+                for j in 0..200_000_000 {
+                    sum = sum.wrapping_add(j);
+                }
+
+                // Blocking I/O
+                std::thread::sleep(Duration::from_millis(500));
+
+                println!("[Good Task] Iteration {} done", i);
+            }
+
+            println!("[Good Task] All blocking work done");
+        })
+        .await
+        .unwrap();
+
+        // Update count AFTER blocking work completes
+        count += 1;
+        println!("[Good Task] Count: {}", count);
+    }
+}
+
+fn main() -> ExitCode {
+    Registry::new(
+        "=== Bad Blocking Examples ===\n\
+         This demonstrates EXTREMELY BAD async patterns!\n\
+         Watch tokio-console for:\n\
+         - Busy % > 50% (should be < 1%)\n\
+         - Poll times in SECONDS (should be µs)\n\
+         - Never yielded warnings\n\
+         - Good tasks being starved\n",
+    )
+    .scenario(
+        Scenario::new(
+            "bad-blocking/mixed-blocking",
+            "CPU work and std::thread::sleep inside a task (BAD)",
+            || Box::pin(mixed_blocking()),
+        )
+        .by_default(),
+    )
+    .scenario(Scenario::new(
+        "bad-blocking/spawn-blocking",
+        "The same work moved to spawn_blocking (GOOD)",
+        || Box::pin(spawn_blocking()),
+    ))
+    .main()
 }
@@ -12,13 +12,34 @@
 //! ```
 //! tokio-console
 //! ```
+//!
+//! Or, without tokio-console, dump where every task is stuck (Linux only):
+//! ```
+//! RUSTFLAGS="--cfg tokio_unstable" cargo run --features tokio_unstable --example hanging_task
+//! curl http://localhost:6060/debug/tasks
+//! ```
+//!
+//! The tasks are also spawned through `diagnostics::spawn`, whose watchdog
+//! reports them after 5 seconds idle, with what they were awaiting. Run with
+//! `RUST_LOG=warn` to see the reports, or list them:
+//! ```
+//! curl http://localhost:6060/debug/async/idle
+//! curl http://localhost:6060/debug/async/locks
+//! curl http://localhost:6060/debug/async/channels
+//! ```
 
 use std::future::pending;
 use std::time::Duration;
-use tokio::sync::{mpsc, oneshot};
+use tokio_console_demo::diagnostics::{self, awaiting, mpsc, oneshot, DiagnosticsConfig};
+use tokio_console_demo::profiling::ProfilingService;
+use tokio_console_demo::scenario;
 
 fn main() {
-    console_subscriber::init();
+    scenario::init_subscriber();
+    DiagnosticsConfig::default()
+        .idle_threshold(Duration::from_secs(5))
+        .idle_threshold_for("status", Duration::MAX)
+        .install();
 
     let runtime = tokio::runtime::Runtime::new().unwrap();
     runtime.block_on(async {
@@ -26,12 +47,20 @@ fn main() {
         println!("This demonstrates tasks that hang forever and never complete.");
         println!("Connect with: tokio-console");
         println!("Look for tasks with continuously growing Idle time!");
+        println!("Or dump them: curl http://localhost:6060/debug/tasks");
+        println!("Or list them: curl http://localhost:6060/debug/async/idle");
         println!();
 
+        // Serves /debug/tasks (needs the tokio_unstable feature)
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:6060")
+            .await
+            .unwrap();
+        tokio::spawn(ProfilingService::default().serve(listener));
+
         // Scenario 1: Using pending() - the most obvious hanging task
-        tokio::spawn(async {
+        diagnostics::spawn("task-1-pending", async {
             println!("Task 1: Using pending() - will hang forever");
-            pending::<()>().await;
+            awaiting("pending()", pending::<()>()).await;
             println!("This will NEVER print!");
         });
 
@@ -39,9 +68,9 @@ fn main() {
         let (_tx, mut rx) = mpsc::channel::<String>(10);
         // Note: We keep _tx alive but never send anything
 
-        tokio::spawn(async move {
+        diagnostics::spawn("task-2-mpsc", async move {
             println!("Task 2: Waiting for channel message that never comes...");
-            match rx.recv().await {
+            match awaiting("mpsc recv", rx.recv()).await {
                 Some(msg) => println!("Received: {}", msg),
                 None => println!("Channel closed"),
             }
@@ -51,16 +80,16 @@ fn main() {
         // Scenario 3: Waiting for oneshot that never sends
         let (_tx, rx) = oneshot::channel::<i32>();
 
-        tokio::spawn(async move {
+        diagnostics::spawn("task-3-oneshot", async move {
             println!("Task 3: Waiting for oneshot signal...");
-            match rx.await {
+            match awaiting("oneshot recv", rx).await {
                 Ok(value) => println!("Received value: {}", value),
                 Err(_) => println!("Sender dropped"),
             }
         });
 
         // Scenario 4: Joining a task that runs forever
-        let infinite_task = tokio::spawn(async {
+        let infinite_task = diagnostics::spawn("task-4-infinite", async {
             let mut counter = 0u64;
             loop {
                 tokio::time::sleep(Duration::from_secs(2)).await;
@@ -69,27 +98,31 @@ fn main() {
             }
         });
 
-        tokio::spawn(async move {
+        diagnostics::spawn("task-4-join", async move {
             println!("Task 4: Waiting to join infinite task...");
-            let _ = infinite_task.await;
+            let _ = awaiting("join task-4-infinite", infinite_task).await;
             println!("Infinite task completed (will never happen)");
         });
 
         // Scenario 5: Deadlock-like situation with channels
-        let (tx1, mut rx1) = mpsc::channel::<String>(1);
-        let (tx2, mut rx2) = mpsc::channel::<String>(1);
+        // The diagnostics channels report the wait-for cycle once each task
+        // claims the sender it keeps for its reply
+        let (tx1, mut rx1) = mpsc::named::<String>("to-5a", 1);
+        let (tx2, mut rx2) = mpsc::named::<String>("to-5b", 1);
 
-        tokio::spawn(async move {
+        diagnostics::spawn("task-5a", async move {
+            tx2.claim();
             println!("Task 5a: Waiting for message from Task 5b...");
-            if let Some(msg) = rx1.recv().await {
+            if let Some(msg) = awaiting("mpsc recv from 5b", rx1.recv()).await {
                 println!("5a received: {}", msg);
                 let _ = tx2.send("Reply from 5a".to_string()).await;
             }
         });
 
-        tokio::spawn(async move {
+        diagnostics::spawn("task-5b", async move {
+            tx1.claim();
             println!("Task 5b: Waiting for message from Task 5a...");
-            if let Some(msg) = rx2.recv().await {
+            if let Some(msg) = awaiting("mpsc recv from 5a", rx2.recv()).await {
                 println!("5b received: {}", msg);
                 let _ = tx1.send("Reply from 5b".to_string()).await;
             }
@@ -97,36 +130,59 @@ fn main() {
         // Neither task sends first, so both hang forever!
 
         // Scenario 6: Waiting with no timeout on slow operation
-        tokio::spawn(async {
+        diagnostics::spawn("task-6-request", async {
             println!("Task 6: Simulating hung HTTP request (no timeout)...");
             // In real code, this might be a network request that hangs
-            pending::<()>().await;
+            awaiting("HTTP response", pending::<()>()).await;
             println!("Request completed (never happens)");
         });
 
         //Scenario 7: Lock/synchronization issue
+        // diagnostics::Mutex reports the guard held across pending()
         use std::sync::Arc;
-        use tokio::sync::Mutex;
+        use tokio_console_demo::diagnostics::Mutex;
 
-        let data = Arc::new(Mutex::new(0));
+        let data = Arc::new(Mutex::named("data", 0));
         let data_clone = data.clone();
 
-        tokio::spawn(async move {
+        diagnostics::spawn("task-7a", async move {
             println!("Task 7a: Acquiring lock and holding it...");
             let _guard = data.lock().await;
             println!("Task 7a: Lock acquired, now hanging...");
-            pending::<()>().await; // Hold lock forever!
+            awaiting("pending() with the lock held", pending::<()>()).await; // Hold lock forever!
         });
 
-        tokio::spawn(async move {
+        diagnostics::spawn("task-7b", async move {
             tokio::time::sleep(Duration::from_millis(100)).await;
             println!("Task 7b: Trying to acquire lock...");
-            let _guard = data_clone.lock().await;
+            let _guard = awaiting("data lock", data_clone.lock()).await;
             println!("Task 7b: Lock acquired! (will never happen)");
         });
 
+        // Scenario 8: Deadlock, two tasks locking the same mutexes in
+        // opposite orders. Reported as a wait-for cycle between 8a and 8b
+        let first = Arc::new(Mutex::named("first", ()));
+        let second = Arc::new(Mutex::named("second", ()));
+
+        let (first_clone, second_clone) = (first.clone(), second.clone());
+        diagnostics::spawn("task-8a", async move {
+            let _first = first_clone.lock().await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            println!("Task 8a: Holding first, locking second...");
+            let _second = second_clone.lock().await;
+            println!("Task 8a: Got both (will never happen)");
+        });
+
+        diagnostics::spawn("task-8b", async move {
+            let _second = second.lock().await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            println!("Task 8b: Holding second, locking first...");
+            let _first = first.lock().await;
+            println!("Task 8b: Got both (will never happen)");
+        });
+
         // Monitoring task to print status
-        tokio::spawn(async {
+        diagnostics::spawn("status", async {
             let mut tick = 0;
             loop {
                 tokio::time::sleep(Duration::from_secs(10)).await;
@@ -0,0 +1,186 @@
+//! A healthy baseline application
+//!
+//! Everything here follows the rules the other examples break: tasks yield
+//! often, wakers are kept, futures are small, locks are held briefly and
+//! blocking work goes to the blocking pool. Use it to learn what "normal"
+//! looks like in tokio-console, and as the clean reference in CI.
+//!
+//! Run this with:
+//! ```
+//! cargo run --example healthy
+//! ```
+//!
+//! Then in another terminal:
+//! ```
+//! tokio-console
+//! # or: exits with 0 when no lint fires
+//! cargo run --bin console-lint -- --duration 10
+//! ```
+//!
+//! In tokio-console, look for:
+//! - No warnings at all
+//! - Busy time a tiny fraction of Total time for every task
+//! - Self-wake % at or near 0
+
+use std::collections::HashMap;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot, Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tokio_console_demo::scenario::{Registry, Scenario};
+use tokio_console_demo::workload::hash_work;
+
+/// A request and where to send its response
+struct Request {
+    id: u64,
+    respond_to: oneshot::Sender<u64>,
+}
+
+// ✅ Request/response over channels, handled by a small worker pool
+async fn request_response() {
+    let (tx, mut rx) = mpsc::channel::<Request>(64);
+
+    let mut workers = Vec::new();
+    for worker in 0..3 {
+        // ✅ Each worker owns its receiver, so no lock is held across `recv`
+        let (worker_tx, mut worker_rx) = mpsc::channel::<Request>(16);
+        workers.push(worker_tx);
+        tokio::spawn(async move {
+            while let Some(request) = worker_rx.recv().await {
+                let _ = request.respond_to.send(hash_work(1_000));
+                if request.id.is_multiple_of(100) {
+                    println!("[Worker {}] Handled request {}", worker, request.id);
+                }
+            }
+        });
+    }
+
+    // ✅ The dispatcher owns the request queue and deals requests round-robin
+    tokio::spawn(async move {
+        for worker in workers.iter().cycle() {
+            let Some(request) = rx.recv().await else {
+                break;
+            };
+            if worker.send(request).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut id = 0u64;
+        loop {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            id += 1;
+            let (respond_to, response) = oneshot::channel();
+            if tx.send(Request { id, respond_to }).await.is_err() {
+                break;
+            }
+            let _ = response.await;
+        }
+    });
+}
+
+// ✅ Bounded fan-out: a batch of short tasks, at most 4 at a time
+async fn fan_out() {
+    let permits = Arc::new(Semaphore::new(4));
+    let mut batch = 0u64;
+    loop {
+        batch += 1;
+        let mut tasks = JoinSet::new();
+        for item in 0..16u64 {
+            let permits = permits.clone();
+            tasks.spawn(async move {
+                let _permit = permits.acquire().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                hash_work(100 + item)
+            });
+        }
+        let done = tasks.join_all().await.len();
+        if batch.is_multiple_of(10) {
+            println!("[Fan-out] Batch {} done ({} items)", batch, done);
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+// ✅ Shared state behind a mutex, never held across an await
+async fn shared_state() {
+    let counters = Arc::new(Mutex::new(HashMap::<u64, u64>::new()));
+
+    for writer in 0..2u64 {
+        let counters = counters.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                *counters.lock().await.entry(writer).or_default() += 1;
+            }
+        });
+    }
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            let snapshot = counters.lock().await.clone();
+            println!("[State] Counters: {:?}", snapshot);
+        }
+    });
+}
+
+// ✅ Occasional blocking work, on the blocking pool
+async fn blocking_work() {
+    loop {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let result = tokio::task::spawn_blocking(|| {
+            std::thread::sleep(Duration::from_millis(200));
+            hash_work(10_000)
+        })
+        .await
+        .unwrap();
+        println!("[Blocking] Result {}", result);
+    }
+}
+
+fn main() -> ExitCode {
+    Registry::new(
+        "=== Healthy Application ===\n\
+         A baseline with none of the problems shown by the other examples.\n\
+         Connect with: tokio-console\n\
+         Expect no warnings!\n",
+    )
+    .scenario(
+        Scenario::new(
+            "healthy/request-response",
+            "Worker pool answering requests over channels",
+            || Box::pin(request_response()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "healthy/fan-out",
+            "Batches of short tasks bounded by a semaphore",
+            || Box::pin(fan_out()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "healthy/shared-state",
+            "Mutex-protected counters, locked briefly",
+            || Box::pin(shared_state()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "healthy/blocking-work",
+            "Occasional work on spawn_blocking",
+            || Box::pin(blocking_work()),
+        )
+        .by_default(),
+    )
+    .main()
+}
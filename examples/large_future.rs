@@ -11,6 +11,7 @@
 //! Run this with:
 //! ```
 //! cargo run --example large_future
+//! cargo run --example large_future -- --all
 //! ```
 //!
 //! Then in another terminal:
@@ -21,9 +22,16 @@
 //! In tokio-console, look for:
 //! - "large-future" warnings
 //! - Future size information in task details
+//!
+//! The scenarios spawn through `diagnostics::spawn`, which logs the futures
+//! over 1 KB with `RUST_LOG=warn`.
 
+use std::process::ExitCode;
 use std::time::Duration;
 
+use tokio_console_demo::diagnostics;
+use tokio_console_demo::scenario::{Registry, Scenario};
+
 // Large struct that will be held across await points
 struct LargeData {
     buffer1: [u8; 10_000], // 10 KB
@@ -144,19 +152,22 @@ async fn good_flattened() {
 }
 
 // ❌ BAD: Holding large buffers across many await points
+// Each buffer held across an await becomes part of the future: 2 x 700
+// bytes is over tokio-console's 1 KB warning, yet small enough that tokio
+// does not box it
 async fn bad_many_buffers() {
     println!("[BAD] Task with many buffers started");
 
     loop {
-        let buffer1 = vec![0u8; 50_000]; // 50 KB
-        let buffer2 = vec![0u8; 50_000]; // 50 KB
+        let buffer1 = [1u8; 700];
+        let buffer2 = [2u8; 700];
 
         // Many await points while holding large buffers
         tokio::time::sleep(Duration::from_millis(10)).await;
-        let _ = buffer1.len();
+        let _ = std::hint::black_box(&buffer1).len();
 
         tokio::time::sleep(Duration::from_millis(10)).await;
-        let _ = buffer2.len();
+        let _ = std::hint::black_box(&buffer2).len();
 
         tokio::time::sleep(Duration::from_millis(10)).await;
         println!("[BAD] Buffers: {} + {}", buffer1.len(), buffer2.len());
@@ -166,22 +177,24 @@ async fn bad_many_buffers() {
 }
 
 // ✅ GOOD: Process and drop buffers promptly
+// `cargo test --example large_future` keeps it that way
+#[diagnostics::max_future_size(1024)]
 async fn good_prompt_drop() {
     println!("[GOOD] Task with prompt drops started");
 
     loop {
         // Process buffer1 and drop immediately
         let len1 = {
-            let buffer1 = vec![0u8; 50_000];
+            let buffer1 = [1u8; 700];
             tokio::time::sleep(Duration::from_millis(10)).await;
-            buffer1.len()
+            std::hint::black_box(&buffer1).len()
         }; // buffer1 dropped here
 
         // Process buffer2 and drop immediately
         let len2 = {
-            let buffer2 = vec![0u8; 50_000];
+            let buffer2 = [2u8; 700];
             tokio::time::sleep(Duration::from_millis(10)).await;
-            buffer2.len()
+            std::hint::black_box(&buffer2).len()
         }; // buffer2 dropped here
 
         tokio::time::sleep(Duration::from_millis(10)).await;
@@ -191,69 +204,96 @@ async fn good_prompt_drop() {
     }
 }
 
-fn main() {
-    console_subscriber::init();
-
-    let runtime = tokio::runtime::Runtime::new().unwrap();
-    runtime.block_on(async {
-        println!("=== Large Future Examples ===");
-        println!("This demonstrates futures that occupy large stack space.");
-        println!("Connect with: tokio-console");
-        println!("Look for 'large-future' warnings!\n");
-
-        // Scenario 1: Large data on stack
-        // println!("[Scenario 1] Large data held across await points (BAD)");
-        // tokio::spawn(bad_large_future_task());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // println!("\n[Scenario 2] Boxed data on heap (GOOD)");
-        // tokio::spawn(good_boxed_data_task());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // // Scenario 2: Deeply nested
-        // println!("\n[Scenario 3] Deeply nested futures (BAD)");
-        // for _ in 0..5 {
-        //     tokio::spawn(bad_deeply_nested());
-        //     tokio::time::sleep(Duration::from_millis(200)).await;
-        // }
-
-        // println!("\n[Scenario 4] Flattened operations (GOOD)");
-        // tokio::spawn(good_flattened());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // Scenario 3: Many buffers
-        println!("\n[Scenario 5] Holding many buffers (BAD)");
-        tokio::spawn(bad_many_buffers());
-        tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // println!("\n[Scenario 6] Prompt buffer drops (GOOD)");
-        // tokio::spawn(good_prompt_drop());
-        // tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // Normal tasks for comparison
-        for i in 0..2 {
-            tokio::spawn(async move {
-                loop {
-                    tokio::time::sleep(Duration::from_secs(2)).await;
-                    println!("[Normal] Task {} running", i);
+// Normal tasks for comparison
+async fn normal_tasks() {
+    for i in 0..2 {
+        diagnostics::spawn("large-future/normal", async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                println!("[Normal] Task {} running", i);
+            }
+        });
+    }
+}
+
+/// The scenarios of this example, also run by `tests/console_warnings.rs`
+///
+/// Scenarios spawn the task themselves: the spawned future's size is the point.
+pub fn registry() -> Registry {
+    Registry::new(
+        "=== Large Future Examples ===\n\
+         This demonstrates futures that occupy large stack space.\n\
+         Connect with: tokio-console\n\
+         Look for 'large-future' warnings and the future size in task details!\n",
+    )
+    .scenario(Scenario::new(
+        "large-future/stack-data",
+        "Large data held across await points (BAD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("large-future/stack-data", bad_large_future_task());
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "large-future/boxed-data",
+        "Boxed data on heap (GOOD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("large-future/boxed-data", good_boxed_data_task());
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "large-future/deeply-nested",
+        "Deeply nested futures (BAD)",
+        || {
+            Box::pin(async {
+                for _ in 0..5 {
+                    diagnostics::spawn("large-future/deeply-nested", bad_deeply_nested());
+                    tokio::time::sleep(Duration::from_millis(200)).await;
                 }
-            });
-        }
+            })
+        },
+    ))
+    .scenario(Scenario::new(
+        "large-future/flattened",
+        "Flattened operations (GOOD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("large-future/flattened", good_flattened());
+            })
+        },
+    ))
+    .scenario(
+        Scenario::new(
+            "large-future/many-buffers",
+            "Holding many buffers (BAD)",
+            || {
+                Box::pin(async {
+                    diagnostics::spawn("large-future/many-buffers", bad_many_buffers());
+                })
+            },
+        )
+        .by_default(),
+    )
+    .scenario(Scenario::new(
+        "large-future/prompt-drop",
+        "Prompt buffer drops (GOOD)",
+        || {
+            Box::pin(async {
+                diagnostics::spawn("large-future/prompt-drop", good_prompt_drop());
+            })
+        },
+    ))
+    .scenario(
+        Scenario::new("large-future/normal", "Normal tasks for comparison", || {
+            Box::pin(normal_tasks())
+        })
+        .by_default(),
+    )
+}
 
-        // Status monitoring
-        let mut tick = 0;
-        loop {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-            tick += 1;
-
-            println!("\n╔══════════════════════════════════╗");
-            println!("║  Status Update #{}              ║", tick);
-            println!("╠══════════════════════════════════╣");
-            println!("║ Check tokio-console for:         ║");
-            println!("║ • Large-future warnings          ║");
-            println!("║ • Future size in task details    ║");
-            println!("║ • Stack usage comparison         ║");
-            println!("╚══════════════════════════════════╝\n");
-        }
-    });
+fn main() -> ExitCode {
+    registry().main()
 }
@@ -0,0 +1,150 @@
+//! Example of long-running tasks that are perfectly fine
+//!
+//! A task that lives for the whole process is not a problem by itself:
+//! background workers, periodic jobs and connection loops are meant to run
+//! forever. What matters is that each *poll* is short. None of these tasks
+//! should trigger a tokio-console warning, in contrast with
+//! `never_yielded` and `bad_blocking`.
+//!
+//! Run this with:
+//! ```
+//! cargo run --example long_running
+//! ```
+//!
+//! Then in another terminal:
+//! ```
+//! tokio-console
+//! # or, in CI: expect a clean report
+//! cargo run --bin console-lint -- --duration 10
+//! ```
+//!
+//! In tokio-console, look for:
+//! - Total time growing forever, but Busy time staying tiny
+//! - Many polls, each of them short
+//! - No warnings
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tokio_console_demo::scenario::{Registry, Scenario};
+use tokio_console_demo::workload::hash_work;
+
+// ✅ A periodic job: idle between ticks
+async fn periodic() {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut runs = 0u64;
+    loop {
+        interval.tick().await;
+        runs += 1;
+        let _ = hash_work(1_000);
+        if runs.is_multiple_of(10) {
+            println!("[Periodic] {} runs so far", runs);
+        }
+    }
+}
+
+// ✅ A background worker draining a queue, fed by a producer
+async fn worker() {
+    let (tx, mut rx) = mpsc::channel::<u64>(100);
+
+    tokio::spawn(async move {
+        let mut job = 0u64;
+        loop {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            job += 1;
+            if tx.send(job).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        // Lives as long as the producer does, parked in recv() in between
+        while let Some(job) = rx.recv().await {
+            let _ = hash_work(1_000);
+            if job.is_multiple_of(50) {
+                println!("[Worker] Processed job {}", job);
+            }
+        }
+    });
+}
+
+// ✅ A long CPU job split into short polls
+async fn chunked_cpu() {
+    println!("[Chunked] Starting a job that takes minutes in total");
+
+    let started = Instant::now();
+    let mut chunks = 0u64;
+    loop {
+        // Each chunk takes well under a millisecond...
+        let _ = hash_work(1_000);
+        chunks += 1;
+
+        if chunks.is_multiple_of(100_000) {
+            println!(
+                "[Chunked] {} chunks after {:.0?}",
+                chunks,
+                started.elapsed()
+            );
+        }
+
+        // ...and the task yields after every one of them
+        tokio::task::yield_now().await;
+    }
+}
+
+// ✅ A long blocking job on the blocking pool, where it belongs
+async fn blocking_pool() {
+    loop {
+        tokio::task::spawn_blocking(|| {
+            println!("[Blocking] Long synchronous job started");
+            std::thread::sleep(Duration::from_secs(5));
+            println!("[Blocking] Long synchronous job done");
+        })
+        .await
+        .unwrap();
+    }
+}
+
+fn main() -> ExitCode {
+    Registry::new(
+        "=== Long-Running Examples ===\n\
+         These tasks run forever, and none of them is a problem.\n\
+         Connect with: tokio-console\n\
+         Expect no warnings: Total time grows, Busy time stays small.\n",
+    )
+    .scenario(
+        Scenario::new(
+            "long-running/periodic",
+            "Periodic job driven by an interval",
+            || Box::pin(periodic()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "long-running/worker",
+            "Background worker draining a channel",
+            || Box::pin(worker()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "long-running/chunked-cpu",
+            "Endless CPU job yielding between chunks",
+            || Box::pin(chunked_cpu()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "long-running/blocking-pool",
+            "Long synchronous job on spawn_blocking",
+            || Box::pin(blocking_pool()),
+        )
+        .by_default(),
+    )
+    .main()
+}
@@ -6,6 +6,7 @@
 //! Run this with:
 //! ```
 //! cargo run --example mixed_issues
+//! cargo run --example mixed_issues -- --all
 //! ```
 //!
 //! Then in another terminal:
@@ -13,85 +14,92 @@
 //! tokio-console
 //! ```
 
+use std::process::ExitCode;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{mpsc, Notify};
+use tokio::sync::Notify;
 
-fn main() {
-    console_subscriber::init();
+use tokio_console_demo::scenario::{Registry, Scenario};
 
-    let runtime = tokio::runtime::Runtime::new().unwrap();
-    runtime.block_on(async {
-        println!("Starting mixed issues example...");
-        println!("This demonstrates multiple problems occurring together.");
-        println!("Connect with: tokio-console");
-        println!("Try to identify and distinguish different issues!");
-        println!();
-
-        // // Issue 1: Self-waking task
-        // let notify = Arc::new(Notify::new());
-        // let notify_clone = notify.clone();
-        // tokio::spawn(async move {
-        //     loop {
-        //         notify_clone.notified().await;
-        //         tokio::time::sleep(Duration::from_millis(5)).await;
-        //         notify_clone.notify_one(); // Bad: waking itself
-        //     }
-        // });
-        // notify.notify_one();
-
-        // Issue 2: Never yields (busy loop)
-        println!("[Issue 2] Spawning never-yielding task...");
-        tokio::spawn(async {
-            let mut counter = 0u64;
-            let mut iteration = 0u64;
-            loop {
-                for _ in 0..500_000 {
-                    counter = counter.wrapping_add(1);
-                }
-                iteration += 1;
+// Issue 1: Self-waking task
+async fn self_waking() {
+    let notify = Arc::new(Notify::new());
+    let notify_clone = notify.clone();
+    tokio::spawn(async move {
+        loop {
+            notify_clone.notified().await;
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            notify_clone.notify_one(); // Bad: waking itself
+        }
+    });
+    notify.notify_one();
+}
 
-                // Print every 10,000 iterations to see it's running
-                if iteration % 10_000 == 0 {
-                    println!(
-                        "[Issue 2] Still running... iteration {}, counter {}",
-                        iteration, counter
-                    );
-                }
-                // No await point!
+// Issue 2: Never yields (busy loop)
+async fn never_yields() {
+    tokio::spawn(async {
+        let mut counter = 0u64;
+        let mut iteration = 0u64;
+        loop {
+            for _ in 0..500_000 {
+                counter = counter.wrapping_add(1);
             }
-        });
+            iteration += 1;
 
-        // Some healthy tasks for comparison
-        for i in 0..2 {
-            tokio::spawn(async move {
-                loop {
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                    println!("Healthy task {}: all good!", i);
-                }
-            });
+            // Print every 10,000 iterations to see it's running
+            if iteration.is_multiple_of(10_000) {
+                println!(
+                    "[Issue 2] Still running... iteration {}, counter {}",
+                    iteration, counter
+                );
+            }
+            // No await point!
         }
+    });
+}
 
-        // Monitoring task
-        tokio::spawn(async {
-            let mut tick = 0;
+// Some healthy tasks for comparison
+async fn healthy() {
+    for i in 0..2 {
+        tokio::spawn(async move {
             loop {
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                tick += 1;
-                println!("\n=== Status Update #{} ===", tick);
-                println!("Check tokio-console to identify:");
-                println!("- Tasks with high self-wake %");
-                println!("- Tasks that never yield");
-                println!("- Lost waker warnings");
-                println!("- Long poll times");
-                println!("- Task count growth");
-                println!("========================\n");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                println!("Healthy task {}: all good!", i);
             }
         });
+    }
+}
 
-        // Keep the program running
-        loop {
-            tokio::time::sleep(Duration::from_secs(60)).await;
-        }
-    });
+fn main() -> ExitCode {
+    Registry::new(
+        "Starting mixed issues example...\n\
+         This demonstrates multiple problems occurring together.\n\
+         Connect with: tokio-console\n\
+         Try to identify and distinguish different issues:\n\
+         - Tasks with high self-wake %\n\
+         - Tasks that never yield\n\
+         - Long poll times\n",
+    )
+    .scenario(Scenario::new(
+        "mixed-issues/self-waking",
+        "Task waking itself through a Notify",
+        || Box::pin(self_waking()),
+    ))
+    .scenario(
+        Scenario::new(
+            "mixed-issues/never-yields",
+            "Busy loop without await points",
+            || Box::pin(never_yields()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "mixed-issues/healthy",
+            "Healthy tasks for comparison",
+            || Box::pin(healthy()),
+        )
+        .by_default(),
+    )
+    .main()
 }
@@ -0,0 +1,163 @@
+//! Example demonstrating the "never-yielded" issue
+//!
+//! A task that never reaches an `.await` which actually returns `Pending`
+//! keeps its worker thread for as long as it runs. tokio cannot preempt it,
+//! so every other task scheduled on that worker starves.
+//!
+//! tokio-console warns when a task has been inside its *first* poll for
+//! longer than a second: it has never yielded back to the runtime.
+//!
+//! Run this with:
+//! ```
+//! cargo run --example never_yielded
+//! cargo run --example never_yielded -- --all
+//! ```
+//!
+//! Then in another terminal:
+//! ```
+//! tokio-console
+//! ```
+//!
+//! In tokio-console, look for:
+//! - "never yielded" warnings on the BAD tasks
+//! - Busy time equal to Total time for those tasks
+//! - The GOOD tasks doing the same work with many short polls
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use tokio_console_demo::scenario::{Registry, Scenario};
+use tokio_console_demo::workload::hash_work;
+
+// ❌ BAD: Spin forever without an await point
+async fn busy_loop() {
+    println!("[BAD] Busy loop started, it will never yield");
+
+    let mut rounds = 0u64;
+    loop {
+        let _ = hash_work(10_000);
+        rounds += 1;
+
+        if rounds.is_multiple_of(1_000) {
+            println!("[BAD] Busy loop still spinning ({} rounds)", rounds);
+        }
+        // No await: the worker thread is never handed back
+    }
+}
+
+// ❌ BAD: A long synchronous computation before the first await
+async fn long_first_poll() {
+    println!("[BAD] Crunching for 3s before the first await...");
+
+    // Looks harmless because the task awaits afterwards, but the first
+    // poll holds the worker for the whole computation
+    let started = Instant::now();
+    let mut rounds = 0u64;
+    while started.elapsed() < Duration::from_secs(3) {
+        let _ = hash_work(10_000);
+        rounds += 1;
+    }
+    println!("[BAD] First poll finally done after {} rounds", rounds);
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+// ✅ GOOD: The same busy loop, yielding between chunks of work
+async fn yield_now() {
+    println!("[GOOD] Cooperative loop started");
+
+    let mut rounds = 0u64;
+    loop {
+        let _ = hash_work(10_000);
+        rounds += 1;
+
+        if rounds.is_multiple_of(1_000) {
+            println!("[GOOD] Cooperative loop ({} rounds)", rounds);
+        }
+
+        // ✅ Give other tasks on this worker a turn
+        tokio::task::yield_now().await;
+    }
+}
+
+// ✅ GOOD: Move the long computation to the blocking pool
+async fn spawn_blocking() {
+    println!("[GOOD] Handing the 3s computation to spawn_blocking");
+
+    loop {
+        // ✅ Blocking tasks are expected to run without yielding
+        let rounds = tokio::task::spawn_blocking(|| {
+            let started = Instant::now();
+            let mut rounds = 0u64;
+            while started.elapsed() < Duration::from_secs(3) {
+                let _ = hash_work(10_000);
+                rounds += 1;
+            }
+            rounds
+        })
+        .await
+        .unwrap();
+
+        println!("[GOOD] Blocking computation done after {} rounds", rounds);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+// Starved tasks show up as late ticks
+async fn heartbeat() {
+    let mut last = Instant::now();
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let late = last.elapsed().saturating_sub(Duration::from_millis(500));
+        if late > Duration::from_millis(50) {
+            println!("[Heartbeat] Tick {:?} late, a worker is being hogged", late);
+        }
+        last = Instant::now();
+    }
+}
+
+fn main() -> ExitCode {
+    Registry::new(
+        "=== Never-Yielded Examples ===\n\
+         This demonstrates tasks that never give their worker thread back.\n\
+         Connect with: tokio-console\n\
+         Look for 'never yielded' warnings!\n",
+    )
+    .scenario(
+        Scenario::new(
+            "never-yielded/busy-loop",
+            "Spinning forever without an await (BAD)",
+            || Box::pin(busy_loop()),
+        )
+        .by_default(),
+    )
+    .scenario(Scenario::new(
+        "never-yielded/long-first-poll",
+        "3s of CPU work before the first await (BAD)",
+        || Box::pin(long_first_poll()),
+    ))
+    .scenario(Scenario::new(
+        "never-yielded/yield-now",
+        "Busy loop with yield_now between chunks (GOOD)",
+        || Box::pin(yield_now()),
+    ))
+    .scenario(
+        Scenario::new(
+            "never-yielded/spawn-blocking",
+            "Long computation on the blocking pool (GOOD)",
+            || Box::pin(spawn_blocking()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "never-yielded/heartbeat",
+            "Timer reporting late ticks caused by starvation",
+            || Box::pin(heartbeat()),
+        )
+        .by_default(),
+    )
+    .main()
+}
@@ -2,6 +2,8 @@
 //!
 //! This example demonstrates how to integrate pprof-rs with an HTTP server
 //! to provide profiling endpoints that can be accessed via HTTP requests.
+//! The profiling routes come from `tokio_console_demo::profiling::ProfilingService`;
+//! this example only adds demo routes (status page, CPU work, allocations) in front.
 //!
 //! Run this with:
 //! ```bash
@@ -17,16 +19,34 @@
 //! - GET  http://localhost:8080/                      - Status page
 //! - GET  http://localhost:8080/work                  - Trigger CPU-intensive work
 //! - POST http://localhost:8080/allocate?mb=<n>       - Allocate persistent memory
+//! - POST http://localhost:8080/workload/cpu?seconds=<n> - Generate synthetic CPU load in the background
+//! - POST http://localhost:8080/workload/memory?seconds=<n> - Hold synthetic heap allocations in the background
 //! - POST http://localhost:8080/profile/cpu           - Get CPU profile (protobuf format)
+//! - GET  http://localhost:8080/profile/cpu/flamegraph - Get CPU flamegraph (SVG, open in a browser)
 //! - POST http://localhost:8080/profile/memory        - Get heap memory profile (jemalloc, protobuf)
+//! - GET  http://localhost:8080/profile/memory/flamegraph - Get live heap flamegraph (SVG)
+//! - POST http://localhost:8080/profile/memory/snapshots?name=<id> - Store a heap snapshot
+//! - GET  http://localhost:8080/profile/memory/diff?base=<id>&target=<id> - Heap growth between snapshots
+//! - GET  http://localhost:8080/profile/memory/sampling - Heap sampling state; POST to change it at runtime
+//! - GET  http://localhost:8080/stats/memory          - jemalloc allocator statistics (JSON)
+//! - GET  http://localhost:8080/stats/runtime         - tokio scheduler metrics (JSON)
+//! - GET  http://localhost:8080/metrics               - Prometheus metrics (text exposition)
+//! - GET  http://localhost:8080/debug/tasks           - Async backtrace of every task (--features tokio_unstable)
+//! - GET  http://localhost:8080/debug/pprof/          - Go net/http/pprof compatible routes
+//! - GET  http://localhost:8080/profile/cpu/continuous - Rolling CPU profile (CONTINUOUS_PROFILING=1)
 //!
 //! Example usage:
 //! ```bash
-//! # CPU Profiling
+//! # CPU Profiling (samples the process as-is; add load first if it is idle)
+//! curl -X POST "http://localhost:8080/workload/cpu?seconds=30"
 //! curl -X POST http://localhost:8080/profile/cpu > cpu_profile.pb
 //! curl -X POST "http://localhost:8080/profile/cpu?seconds=30" > cpu_profile.pb
 //! go tool pprof -http=:9000 cpu_profile.pb
 //!
+//! # CPU flamegraph without Go tooling (options: title, min_width, reverse, palette)
+//! curl "http://localhost:8080/profile/cpu/flamegraph?seconds=10&palette=rust" > cpu.svg
+//! curl "http://localhost:8080/profile/cpu/flamegraph?seconds=10&reverse=true" > icicle.svg
+//!
 //! # Memory Profiling (requires jemalloc profiling enabled)
 //! # First, allocate some persistent memory
 //! curl -X POST "http://localhost:8080/allocate?mb=100"
@@ -35,6 +55,38 @@
 //! curl -X POST http://localhost:8080/profile/memory > heap_profile.pb
 //! go tool pprof -http=:9001 heap_profile.pb
 //!
+//! # Leak hunting: snapshot, exercise the app, snapshot again, diff
+//! curl -X POST "http://localhost:8080/profile/memory/snapshots?name=before"
+//! curl -X POST "http://localhost:8080/allocate?mb=50"
+//! curl -X POST "http://localhost:8080/profile/memory/snapshots?name=after"
+//! curl "http://localhost:8080/profile/memory/diff?base=before&target=after" > heap_diff.pb
+//! go tool pprof -top heap_diff.pb
+//!
+//! # Sample every allocation for a while, then go back to the default rate
+//! curl -X POST "http://localhost:8080/profile/memory/sampling/reset?lg_prof_sample=0"
+//! curl -X POST "http://localhost:8080/profile/memory/sampling/reset?lg_prof_sample=19"
+//! curl -X POST "http://localhost:8080/profile/memory/sampling?active=false"
+//!
+//! # Scheduler health: queue depths, busy time per worker
+//! # (build with RUSTFLAGS="--cfg tokio_unstable" for steal/poll counts and local queues)
+//! curl http://localhost:8080/stats/runtime
+//!
+//! # Prometheus scrape (HTTP requests, profiling sessions, jemalloc, tokio)
+//! curl http://localhost:8080/metrics
+//!
+//! # Continuous profiling: start with CONTINUOUS_PROFILING=1, then look back in time
+//! curl http://localhost:8080/profile/cpu/continuous/windows
+//! curl "http://localhost:8080/profile/cpu/continuous?minutes=2" > last_2m.pb
+//! curl "http://localhost:8080/profile/cpu/continuous?window=3" > window_3.pb
+//! curl "http://localhost:8080/profile/cpu/continuous/flamegraph?minutes=5" > last_5m.svg
+//!
+//! # Go tooling works directly against the /debug/pprof/ routes
+//! go tool pprof "http://localhost:8080/debug/pprof/profile?seconds=10"
+//! go tool pprof http://localhost:8080/debug/pprof/heap
+//!
+//! # Or eyeball live heap bytes in a browser
+//! curl http://localhost:8080/profile/memory/flamegraph > heap.svg
+//!
 //! # IMPORTANT: Memory profiling requires _RJEM_MALLOC_CONF environment variable!
 //! # The malloc_conf in code is NOT enough - you MUST set the env var:
 //! _RJEM_MALLOC_CONF=prof:true,prof_active:true,lg_prof_sample:19 \
@@ -54,11 +106,14 @@ use hyper::service::service_fn;
 use hyper::{body::Incoming, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
 use hyper_util::server::conn::auto::Builder;
-use pprof::protos::Message;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
+use tokio_console_demo::http::query_param;
+use tokio_console_demo::profiling::ProfilingService;
+use tokio_console_demo::stats::MemoryStats;
+use tokio_console_demo::workload::{fibonacci_work, hash_work, prime_number_work};
 
 #[cfg(not(target_env = "msvc"))]
 #[global_allocator]
@@ -76,19 +131,37 @@ struct AppState {
     request_count: Arc<Mutex<u64>>,
     // Persistent memory allocations for demonstration
     memory_pool: Arc<Mutex<Vec<Vec<u8>>>>,
+    // Profiling endpoints provided by the library
+    profiling: ProfilingService,
 }
 
 impl AppState {
     fn new() -> Self {
+        // Demo server: allow generating synthetic load on demand
+        let mut profiling = ProfilingService::builder().workload_endpoints(true);
+        if std::env::var_os("CONTINUOUS_PROFILING").is_some() {
+            // 10s windows, last 5 minutes retained
+            profiling = profiling.continuous_profiling(Duration::from_secs(10), 30);
+        }
+
         Self {
             request_count: Arc::new(Mutex::new(0)),
             memory_pool: Arc::new(Mutex::new(Vec::new())),
+            profiling: profiling.build(),
         }
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The profiling service logs through tracing
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+
     // Activate jemalloc profiling at startup
     #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
     {
@@ -101,7 +174,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         } else {
             eprintln!("⚠️  Warning: Jemalloc profiling controller not available");
             eprintln!("    Memory profiling (/profile/memory) will not work properly.");
-            eprintln!("    To enable with proper stack traces, restart with:");
+            eprintln!("    Sampling can be tuned at runtime (/profile/memory/sampling), but");
+            eprintln!("    profiling itself must be enabled at startup. Restart with:");
             eprintln!("    _RJEM_MALLOC_CONF=prof:true,lg_prof_sample:0,prof_final:false \\");
             eprintln!("    RUSTFLAGS=\"-C force-frame-pointers=yes\" \\");
             eprintln!("    cargo run --example pprof_http --release");
@@ -121,8 +195,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("  GET  /                                         - Status page");
     println!("  GET  /work                                     - Trigger CPU work");
     println!("  POST /allocate?mb=<n>                          - Allocate persistent memory");
+    println!("  POST /workload/cpu?seconds=<n>                 - Generate synthetic CPU load");
+    println!("  POST /workload/memory?seconds=<n>              - Hold synthetic allocations");
     println!("  POST /profile/cpu?seconds=<n>                  - Get CPU profile (protobuf)");
+    println!("  GET  /profile/cpu/flamegraph?seconds=<n>       - Get CPU flamegraph (SVG)");
     println!("  POST /profile/memory                           - Get heap profile (jemalloc)");
+    println!("  GET  /profile/memory/flamegraph                - Get heap flamegraph (SVG)");
+    println!("  POST /profile/memory/snapshots?name=<id>       - Store a heap snapshot");
+    println!("  GET  /profile/memory/diff?base=<id>&target=<id> - Heap growth between snapshots");
+    println!("  GET  /profile/memory/sampling                  - Heap sampling state (POST ?active=<bool>)");
+    println!("  POST /profile/memory/sampling/reset?lg_prof_sample=<n> - Reset samples, new rate");
+    println!("  GET  /stats/memory                             - jemalloc allocator stats (JSON)");
+    println!("  GET  /stats/runtime                            - tokio runtime metrics (JSON)");
+    println!("  GET  /metrics                                  - Prometheus metrics");
+    println!(
+        "  GET  /debug/tasks[?format=json]                - Task dump (--features tokio_unstable)"
+    );
+    println!("  GET  /debug/pprof/                             - Go pprof compatible routes");
+    println!("  GET  /profile/cpu/continuous?minutes=<n>       - Rolling CPU profile (CONTINUOUS_PROFILING=1)");
     println!();
 
     let state = Arc::new(AppState::new());
@@ -154,9 +244,6 @@ async fn handle_request(
     req: Request<Incoming>,
     state: Arc<AppState>,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let path = req.uri().path();
-    let query = req.uri().query();
-
     // Increment request counter
     {
         let mut count = state.request_count.lock().await;
@@ -164,14 +251,23 @@ async fn handle_request(
         println!("Request #{}: {} {}", *count, req.method(), req.uri());
     }
 
-    match (req.method(), path) {
-        (&hyper::Method::GET, "/") => Ok(handle_status(state).await),
-        (&hyper::Method::GET, "/work") => Ok(handle_work().await),
-        (&hyper::Method::POST, "/allocate") => Ok(handle_allocate(state, query).await),
-        (&hyper::Method::POST, "/profile/cpu") => Ok(handle_cpu_profile(query).await),
-        (&hyper::Method::POST, "/profile/memory") => Ok(handle_memory_profile(state).await),
-        _ => Ok(not_found()),
-    }
+    let (route, response) = match (req.method(), req.uri().path()) {
+        (&hyper::Method::GET, "/") => ("/", handle_status(Arc::clone(&state)).await),
+        (&hyper::Method::GET, "/work") => ("/work", handle_work().await),
+        (&hyper::Method::POST, "/allocate") => (
+            "/allocate",
+            handle_allocate(Arc::clone(&state), req.uri().query()).await,
+        ),
+        // Everything else (including /profile/*) is served, and counted, by the library
+        _ => return Ok(state.profiling.handle(req).await),
+    };
+
+    // Count our own routes in the same /metrics counters
+    state
+        .profiling
+        .http_metrics()
+        .record(route, response.status());
+    Ok(response)
 }
 
 /// Status endpoint - shows service information
@@ -179,6 +275,7 @@ async fn handle_status(state: Arc<AppState>) -> Response<Full<Bytes>> {
     let count = state.request_count.lock().await;
     let pool = state.memory_pool.lock().await;
     let total_mb = pool.iter().map(|v| v.len()).sum::<usize>() as f64 / 1024.0 / 1024.0;
+    let allocator_stats = allocator_stats_html();
 
     let body = format!(
         r#"<!DOCTYPE html>
@@ -204,6 +301,11 @@ async fn handle_status(state: Arc<AppState>) -> Response<Full<Bytes>> {
         Total allocated: {:.2} MB
     </div>
 
+    <div class="stats">
+        <strong>Allocator Stats (jemalloc, <a href="/stats/memory">/stats/memory</a>):</strong><br>
+        {}
+    </div>
+
     <h2>Available Endpoints</h2>
 
     <div class="endpoint">
@@ -217,12 +319,31 @@ async fn handle_status(state: Arc<AppState>) -> Response<Full<Bytes>> {
         Example: <code>curl -X POST "http://localhost:8080/allocate?mb=50"</code>
     </div>
 
+    <div class="endpoint">
+        <strong>POST /workload/cpu?seconds=&lt;n&gt;</strong><br>
+        Generate synthetic CPU load in the background for n seconds<br>
+        Example: <code>curl -X POST "http://localhost:8080/workload/cpu?seconds=10"</code>
+    </div>
+
+    <div class="endpoint">
+        <strong>POST /workload/memory?seconds=&lt;n&gt;</strong><br>
+        Hold synthetic heap allocations from several call sites for n seconds<br>
+        Example: <code>curl -X POST "http://localhost:8080/workload/memory?seconds=30"</code>
+    </div>
+
     <div class="endpoint">
         <strong>POST /profile/cpu?seconds=&lt;n&gt;</strong><br>
         Get CPU profile in protobuf format<br>
         Example: <code>curl -X POST "http://localhost:8080/profile/cpu?seconds=10" &gt; cpu_profile.pb</code>
     </div>
 
+    <div class="endpoint">
+        <strong>GET /profile/cpu/flamegraph?seconds=&lt;n&gt;</strong><br>
+        Get CPU flamegraph as interactive SVG (no Go tooling needed)<br>
+        Options: <code>title</code>, <code>min_width</code>, <code>reverse=true</code> (icicle), <code>palette</code><br>
+        Example: <a href="/profile/cpu/flamegraph?seconds=10">/profile/cpu/flamegraph?seconds=10</a>
+    </div>
+
     <div class="endpoint">
         <strong>POST /profile/memory</strong><br>
         Get heap memory profile using jemalloc<br>
@@ -230,9 +351,70 @@ async fn handle_status(state: Arc<AppState>) -> Response<Full<Bytes>> {
         Example: <code>curl -X POST http://localhost:8080/profile/memory &gt; heap_profile.pb</code>
     </div>
 
+    <div class="endpoint">
+        <strong>GET /profile/memory/flamegraph</strong><br>
+        Live heap bytes by allocation stack as interactive SVG<br>
+        Options: <code>title</code>, <code>min_width</code>, <code>reverse=true</code> (icicle), <code>palette</code><br>
+        Example: <a href="/profile/memory/flamegraph?palette=mem">/profile/memory/flamegraph?palette=mem</a>
+    </div>
+
+    <div class="endpoint">
+        <strong>POST /profile/memory/snapshots?name=&lt;id&gt;</strong><br>
+        Store a heap snapshot server-side (list them: <a href="/profile/memory/snapshots">/profile/memory/snapshots</a>)<br>
+        <strong>GET /profile/memory/diff?base=&lt;id&gt;&amp;target=&lt;id&gt;</strong><br>
+        pprof profile of the in-use bytes delta per allocation stack (positive = growth)<br>
+        Example: <code>curl "http://localhost:8080/profile/memory/diff?base=before&amp;target=after" &gt; heap_diff.pb</code>
+    </div>
+
+    <div class="endpoint">
+        <strong>GET <a href="/profile/memory/sampling">/profile/memory/sampling</a></strong><br>
+        Current jemalloc sampling state; <code>POST ?active=true|false</code> toggles <code>prof.active</code><br>
+        <strong>POST /profile/memory/sampling/reset?lg_prof_sample=&lt;n&gt;</strong><br>
+        Discard collected samples and sample every 2^n bytes from now on (no restart needed)<br>
+        Example: <code>curl -X POST "http://localhost:8080/profile/memory/sampling/reset?lg_prof_sample=0"</code>
+    </div>
+
+    <div class="endpoint">
+        <strong>GET <a href="/stats/memory">/stats/memory</a></strong><br>
+        jemalloc allocator statistics as JSON: allocated, active, resident, mapped, retained, metadata
+    </div>
+
+    <div class="endpoint">
+        <strong>GET <a href="/stats/runtime">/stats/runtime</a></strong><br>
+        tokio scheduler metrics as JSON: workers, alive tasks, queue depths, blocking threads,
+        per-worker park/steal/poll counts and busy time<br>
+        <em>Steal/poll counts, local queues and blocking threads need <code>RUSTFLAGS="--cfg tokio_unstable"</code></em>
+    </div>
+
+    <div class="endpoint">
+        <strong>GET <a href="/metrics">/metrics</a></strong><br>
+        Prometheus text exposition: HTTP requests by route/status, CPU profiling sessions,
+        jemalloc stats and tokio runtime metrics<br>
+        Example: <code>curl http://localhost:8080/metrics</code>
+    </div>
+
+    <div class="endpoint">
+        <strong>GET <a href="/debug/tasks">/debug/tasks</a></strong><br>
+        Async backtrace of every task, as text or JSON (<code>?format=json</code>)<br>
+        <em>Requires <code>RUSTFLAGS="--cfg tokio_unstable" cargo run --features tokio_unstable</code> (Linux)</em>
+    </div>
+
+    <div class="endpoint">
+        <strong>GET <a href="/debug/pprof/">/debug/pprof/</a></strong><br>
+        Go <code>net/http/pprof</code> compatible routes: profile, heap, symbol, cmdline<br>
+        Example: <code>go tool pprof "http://localhost:8080/debug/pprof/profile?seconds=10"</code>
+    </div>
+
+    <div class="endpoint">
+        <strong>GET /profile/cpu/continuous?window=&lt;id&gt;|minutes=&lt;n&gt;</strong><br>
+        CPU profile from the background ring buffer (start the server with <code>CONTINUOUS_PROFILING=1</code>)<br>
+        List windows: <a href="/profile/cpu/continuous/windows">/profile/cpu/continuous/windows</a>,
+        flamegraph: <a href="/profile/cpu/continuous/flamegraph?minutes=5">/profile/cpu/continuous/flamegraph?minutes=5</a>
+    </div>
+
     <h2>Quick Start - CPU Profiling</h2>
     <ol>
-        <li>Start some background work: <code>curl http://localhost:8080/work</code></li>
+        <li>Start some background work: <code>curl -X POST "http://localhost:8080/workload/cpu?seconds=10"</code></li>
         <li>Get a CPU profile: <code>curl -X POST "http://localhost:8080/profile/cpu?seconds=5" &gt; cpu_profile.pb</code></li>
         <li>Analyze with pprof: <code>go tool pprof -http=:9000 cpu_profile.pb</code></li>
     </ol>
@@ -242,12 +424,15 @@ async fn handle_status(state: Arc<AppState>) -> Response<Full<Bytes>> {
         <li>Allocate some memory: <code>curl -X POST "http://localhost:8080/allocate?mb=100"</code></li>
         <li>Get a heap profile: <code>curl -X POST http://localhost:8080/profile/memory &gt; heap_profile.pb</code></li>
         <li>Analyze with pprof: <code>go tool pprof -http=:9001 heap_profile.pb</code></li>
+        <li>Find growth: snapshot with <code>POST /profile/memory/snapshots?name=before</code>, allocate again,
+            snapshot <code>after</code>, then fetch <code>/profile/memory/diff?base=before&amp;target=after</code></li>
     </ol>
 </body>
 </html>"#,
         *count,
         pool.len(),
-        total_mb
+        total_mb,
+        allocator_stats
     );
 
     Response::builder()
@@ -297,6 +482,28 @@ async fn handle_work() -> Response<Full<Bytes>> {
         .unwrap()
 }
 
+/// jemalloc statistics for the status page, same numbers as `/stats/memory`
+fn allocator_stats_html() -> String {
+    let mb = |bytes: u64| bytes as f64 / 1024.0 / 1024.0;
+    match MemoryStats::read() {
+        Ok(stats) => format!(
+            "Allocated: {:.2} MB<br>\n        \
+             Active: {:.2} MB<br>\n        \
+             Resident: {:.2} MB<br>\n        \
+             Mapped: {:.2} MB<br>\n        \
+             Retained: {:.2} MB<br>\n        \
+             Metadata: {:.2} MB",
+            mb(stats.allocated),
+            mb(stats.active),
+            mb(stats.resident),
+            mb(stats.mapped),
+            mb(stats.retained),
+            mb(stats.metadata)
+        ),
+        Err(message) => format!("Unavailable: {}", message),
+    }
+}
+
 /// Allocate endpoint - allocates persistent memory for heap profiling demos
 async fn handle_allocate(state: Arc<AppState>, query: Option<&str>) -> Response<Full<Bytes>> {
     let mb = parse_mb_param(query).unwrap_or(10);
@@ -344,383 +551,9 @@ async fn handle_allocate(state: Arc<AppState>, query: Option<&str>) -> Response<
         .unwrap()
 }
 
-/// CPU profile endpoint - returns profile in protobuf format
-async fn handle_cpu_profile(query: Option<&str>) -> Response<Full<Bytes>> {
-    let seconds = parse_seconds_param(query).unwrap_or(10);
-
-    println!("Starting CPU profiling ({} seconds)...", seconds);
-    println!("Generating background CPU load during profiling...");
-
-    // Start profiling with lower frequency (100 Hz is more reliable)
-    let guard = match pprof::ProfilerGuard::new(100) {
-        Ok(guard) => guard,
-        Err(e) => {
-            eprintln!("Failed to start profiler: {}", e);
-            return error_response(format!("Failed to start profiler: {}", e));
-        }
-    };
-
-    // Spawn background tasks to generate CPU load during profiling
-    let mut handles = Vec::new();
-    for i in 0..4 {
-        let handle = tokio::spawn(async move {
-            let iterations = if i % 2 == 0 { 100000 } else { 50000 };
-            for _ in 0..iterations {
-                // Mix of different workload patterns
-                match i % 3 {
-                    0 => {
-                        let _ = fibonacci_work(30);
-                    }
-                    1 => {
-                        let _ = prime_number_work(10000);
-                    }
-                    _ => {
-                        let _ = hash_work(50000);
-                    }
-                }
-                // Small yield to let profiler sample
-                tokio::task::yield_now().await;
-            }
-        });
-        handles.push(handle);
-    }
-
-    // Wait for the specified profiling duration or until work completes
-    let work_future = async {
-        for handle in handles {
-            let _ = handle.await;
-        }
-    };
-
-    tokio::select! {
-        _ = tokio::time::sleep(Duration::from_secs(seconds)) => {
-            println!("Profiling duration completed");
-        }
-        _ = work_future => {
-            println!("Background work completed");
-        }
-    }
-
-    // Generate protobuf profile
-    match guard.report().build() {
-        Ok(report) => {
-            match report.pprof() {
-                Ok(profile) => {
-                    // Convert profile to bytes using write_to_writer
-                    let mut body = Vec::new();
-                    if let Err(e) = profile.write_to_writer(&mut body) {
-                        eprintln!("Failed to encode profile: {}", e);
-                        return error_response(format!("Failed to encode profile: {}", e));
-                    }
-
-                    if body.is_empty() {
-                        eprintln!("Warning: Generated profile is empty");
-                        return error_response("Generated profile is empty. This might be due to system limitations or insufficient CPU activity.".to_string());
-                    }
-
-                    println!("CPU profile generated successfully ({} bytes)", body.len());
-
-                    Response::builder()
-                        .status(StatusCode::OK)
-                        .header("Content-Type", "application/octet-stream")
-                        .header(
-                            "Content-Disposition",
-                            "attachment; filename=\"cpu_profile.pb\"",
-                        )
-                        .body(Full::new(Bytes::from(body)))
-                        .unwrap()
-                }
-                Err(e) => {
-                    eprintln!("Failed to generate pprof: {}", e);
-                    error_response(format!("Failed to generate pprof: {}", e))
-                }
-            }
-        }
-        Err(e) => {
-            eprintln!("Failed to build report: {}", e);
-            error_response(format!("Failed to build report: {}", e))
-        }
-    }
-}
-
-/// Memory profile endpoint - uses jemalloc heap profiling
-///
-/// This endpoint generates a true heap memory profile using jemalloc's profiling capabilities.
-/// It shows memory allocations, not CPU usage.
-#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
-async fn handle_memory_profile(state: Arc<AppState>) -> Response<Full<Bytes>> {
-    println!("Generating heap memory profile using jemalloc...");
-
-    // Check if profiling is activated
-    let prof_ctl = jemalloc_pprof::PROF_CTL.as_ref();
-    if prof_ctl.is_none() {
-        return error_response(
-            "Profiling controller not available. Ensure jemalloc is properly configured.\n\
-             Run with:\n\
-             _RJEM_MALLOC_CONF=prof:true,lg_prof_sample:0,prof_final:false \\\n\
-             RUSTFLAGS=\"-C force-frame-pointers=yes\" \\\n\
-             cargo run --example pprof_http --release"
-                .to_string(),
-        );
-    }
-
-    let prof_ctl = prof_ctl.unwrap();
-    let mut prof_ctl_guard = prof_ctl.lock().await;
-
-    // Check if profiling is active
-    if !prof_ctl_guard.activated() {
-        eprintln!("⚠️  Jemalloc profiling is not active!");
-        return error_response(
-            "Jemalloc profiling is not active.\n\
-             Restart the server with:\n\
-             _RJEM_MALLOC_CONF=prof:true,lg_prof_sample:0,prof_final:false \\\n\
-             RUSTFLAGS=\"-C force-frame-pointers=yes\" \\\n\
-             cargo run --example pprof_http --release"
-                .to_string(),
-        );
-    }
-
-    println!("Jemalloc profiling is active, proceeding with profile dump...");
-
-    // Get info about existing allocations
-    let pool = state.memory_pool.lock().await;
-    let existing_mb = pool.iter().map(|v| v.len()).sum::<usize>() as f64 / 1024.0 / 1024.0;
-    let pool_count = pool.len();
-    drop(pool);
-
-    println!(
-        "Current state: {:.2} MB allocated across {} pools",
-        existing_mb, pool_count
-    );
-
-    // Create temporary allocations to make the profile more interesting
-    // These will show up with different stack traces than the persistent allocations
-    let temp_allocations = create_demo_allocations().await;
-
-    println!("Created temporary demo allocations for profiling");
-    println!("Dumping heap profile...");
-
-    // Dump the profile while keeping all allocations alive
-    let result = prof_ctl_guard.dump_pprof();
-
-    // Keep temporary allocations alive during dump
-    let temp_size =
-        temp_allocations.iter().map(|v| v.len()).sum::<usize>() as f64 / 1024.0 / 1024.0;
-    println!("Temporary allocations: {:.2} MB", temp_size);
-
-    drop(temp_allocations);
-
-    match result {
-        Ok(pprof_data) => {
-            if pprof_data.is_empty() {
-                eprintln!("Warning: Generated heap profile is empty");
-                return error_response(
-                    "Generated heap profile is empty.\n\
-                     This might happen if no memory is allocated or jemalloc profiling is not working.\n\
-                     Try allocating memory first: curl -X POST 'http://localhost:8080/allocate?mb=50'"
-                        .to_string(),
-                );
-            }
-
-            println!(
-                "Heap profile generated successfully ({} bytes)",
-                pprof_data.len()
-            );
-            println!("Profile includes:");
-            println!(
-                "  - Persistent allocations: {:.2} MB across {} pools",
-                existing_mb, pool_count
-            );
-            println!("  - Temporary demo allocations: {:.2} MB", temp_size);
-
-            Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", "application/octet-stream")
-                .header(
-                    "Content-Disposition",
-                    "attachment; filename=\"heap_profile.pb\"",
-                )
-                .body(Full::new(Bytes::from(pprof_data)))
-                .unwrap()
-        }
-        Err(e) => {
-            eprintln!("Failed to dump heap profile: {}", e);
-            error_response(format!("Failed to dump heap profile: {}", e))
-        }
-    }
-}
-
-/// Memory profile endpoint - Windows/MSVC fallback (jemalloc not available)
-#[cfg(any(target_env = "msvc", target_os = "windows"))]
-async fn handle_memory_profile() -> Response<Full<Bytes>> {
-    error_response(
-        "Heap profiling is not available on Windows/MSVC targets. \
-         Use Linux/macOS or consider alternative tools like heaptrack or valgrind."
-            .to_string(),
-    )
-}
-
-/// Parse seconds parameter from query string
-fn parse_seconds_param(query: Option<&str>) -> Option<u64> {
-    query.and_then(|q| {
-        q.split('&')
-            .find(|param| param.starts_with("seconds="))
-            .and_then(|param| param.strip_prefix("seconds="))
-            .and_then(|value| value.parse::<u64>().ok())
-            .filter(|&seconds| seconds > 0 && seconds <= 300) // Max 5 minutes
-    })
-}
-
 /// Parse MB parameter from query string
 fn parse_mb_param(query: Option<&str>) -> Option<u64> {
-    query.and_then(|q| {
-        q.split('&')
-            .find(|param| param.starts_with("mb="))
-            .and_then(|param| param.strip_prefix("mb="))
-            .and_then(|value| value.parse::<u64>().ok())
-            .filter(|&mb| mb > 0 && mb <= 1024) // Max 1GB
-    })
-}
-
-/// 404 Not Found response
-fn not_found() -> Response<Full<Bytes>> {
-    Response::builder()
-        .status(StatusCode::NOT_FOUND)
-        .header("Content-Type", "text/plain")
-        .body(Full::new(Bytes::from("404 Not Found\n")))
-        .unwrap()
-}
-
-/// Error response
-fn error_response(message: String) -> Response<Full<Bytes>> {
-    Response::builder()
-        .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .header("Content-Type", "text/plain")
-        .body(Full::new(Bytes::from(format!("Error: {}\n", message))))
-        .unwrap()
-}
-
-// ============================================================================
-// Memory allocation helpers
-// ============================================================================
-
-/// Create demo allocations with different patterns for heap profiling
-async fn create_demo_allocations() -> Vec<Vec<u8>> {
-    let mut allocations = Vec::new();
-
-    // Pattern 1: Large blocks from different call sites
-    allocations.extend(allocate_large_blocks());
-
-    // Pattern 2: Many small allocations
-    allocations.extend(allocate_small_blocks());
-
-    // Pattern 3: String allocations
-    allocations.extend(allocate_strings());
-
-    // Pattern 4: Async task allocations
-    let mut handles = Vec::new();
-    for i in 0..4 {
-        let handle = tokio::spawn(async move { allocate_from_task(i) });
-        handles.push(handle);
-    }
-
-    for handle in handles {
-        if let Ok(allocs) = handle.await {
-            allocations.extend(allocs);
-        }
-    }
-
-    allocations
-}
-
-/// Allocate large blocks (separate function for distinct stack trace)
-fn allocate_large_blocks() -> Vec<Vec<u8>> {
-    let mut blocks = Vec::new();
-    for i in 0..10 {
-        blocks.push(vec![0xAA; (i + 1) * 1024 * 1024]);
-    }
-    blocks
-}
-
-/// Allocate many small blocks (separate function for distinct stack trace)
-fn allocate_small_blocks() -> Vec<Vec<u8>> {
-    let mut blocks = Vec::new();
-    for i in 0..1000 {
-        blocks.push(vec![0xBB; (i % 100 + 1) * 1024]);
-    }
-    blocks
-}
-
-/// Allocate string data (separate function for distinct stack trace)
-fn allocate_strings() -> Vec<Vec<u8>> {
-    let mut strings = Vec::new();
-    for i in 0..500 {
-        let s = format!(
-            "String allocation {} - This is a demo string for heap profiling visualization",
-            i
-        );
-        strings.push(s.into_bytes());
-    }
-    strings
-}
-
-/// Allocate from async task (separate function for distinct stack trace)
-fn allocate_from_task(task_id: usize) -> Vec<Vec<u8>> {
-    let mut allocations = Vec::new();
-    for i in 0..100 {
-        let size = (i + 1) * 10000 * (task_id + 1);
-        allocations.push(vec![(task_id % 256) as u8; size]);
-    }
-    allocations
-}
-
-// ============================================================================
-// CPU-intensive workload functions for testing profiling
-// ============================================================================
-
-/// Compute Fibonacci number (recursive, inefficient on purpose)
-fn fibonacci_work(n: u64) -> u64 {
-    match n {
-        0 => 0,
-        1 => 1,
-        n => fibonacci_work(n - 1) + fibonacci_work(n - 2),
-    }
-}
-
-/// Find prime numbers up to n
-fn prime_number_work(n: u64) -> Vec<u64> {
-    let mut primes = Vec::new();
-    for num in 2..=n {
-        if is_prime(num) {
-            primes.push(num);
-        }
-    }
-    primes
-}
-
-/// Check if a number is prime
-fn is_prime(n: u64) -> bool {
-    if n < 2 {
-        return false;
-    }
-    for i in 2..=(n as f64).sqrt() as u64 {
-        if n % i == 0 {
-            return false;
-        }
-    }
-    true
-}
-
-/// Hash computation work
-fn hash_work(iterations: u64) -> u64 {
-    let mut hash = 0u64;
-    for i in 0..iterations {
-        hash = hash.wrapping_mul(31).wrapping_add(i);
-        hash ^= hash >> 16;
-        hash = hash.wrapping_mul(0x85ebca6b);
-        hash ^= hash >> 13;
-        hash = hash.wrapping_mul(0xc2b2ae35);
-        hash ^= hash >> 16;
-    }
-    hash
+    query_param(query, "mb")
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|&mb| mb > 0 && mb <= 1024) // Max 1GB
 }
@@ -16,6 +16,7 @@
 //! Run this with:
 //! ```
 //! cargo run --example self_wakes
+//! cargo run --example self_wakes -- --scenario self-wakes/wake-by-ref
 //! ```
 //!
 //! Then in another terminal:
@@ -23,6 +24,10 @@
 //! tokio-console
 //! ```
 //!
+//! The tasks are spawned through `diagnostics::spawn`, which counts the
+//! self-wakes in-process too; run with `RUST_LOG=warn` to see its reports
+//! without tokio-console.
+//!
 //! In tokio-console, look for:
 //! - High "self-wake %" in the task list (red flag if >50%)
 //! - Warnings panel showing self-wake warnings
@@ -30,10 +35,14 @@
 
 use std::future::Future;
 use std::pin::Pin;
+use std::process::ExitCode;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
+
 use tokio::sync::Notify;
+use tokio_console_demo::diagnostics;
+use tokio_console_demo::scenario::{Registry, Scenario};
 
 // Custom Future that demonstrates explicit self-waking using wake_by_ref()
 struct SelfWakingFuture {
@@ -60,7 +69,7 @@ impl Future for SelfWakingFuture {
             println!("  [SelfWakingFuture] Completed after {} polls", self.count);
             Poll::Ready(self.count)
         } else {
-            if self.count % 10 == 0 {
+            if self.count.is_multiple_of(10) {
                 println!(
                     "  [SelfWakingFuture] Poll #{}, waking self immediately...",
                     self.count
@@ -128,91 +137,100 @@ impl Future for BetterYieldingFuture {
     }
 }
 
-fn main() {
-    // Initialize console subscriber for tokio-console
-    console_subscriber::init();
-
-    let runtime = tokio::runtime::Runtime::new().unwrap();
-    runtime.block_on(async {
-        println!("Starting self-wakes example...");
-        println!("This demonstrates tasks that wake themselves too frequently.");
-        println!("Connect with: tokio-console");
-        println!("Look for high self-wake percentage in the tasks view!");
-        println!();
-
-        // Scenario 1: Custom Future with explicit wake_by_ref()
-        println!("[Scenario 1] Custom Future using wake_by_ref()");
-        tokio::spawn(async {
-            println!("  Starting SelfWakingFuture (BAD pattern)...");
-            let result = SelfWakingFuture::new(100).await;
-            println!("  SelfWakingFuture completed with result: {}", result);
-        });
-
-        tokio::time::sleep(Duration::from_millis(100)).await;
-
-        // Scenario 2: Better version with proper yielding
-        println!("\n[Scenario 2] Better Future with proper sleep");
-        tokio::spawn(async {
-            println!("  Starting BetterYieldingFuture (GOOD pattern)...");
-            let result = BetterYieldingFuture::new(50).await;
-            println!("  BetterYieldingFuture completed with result: {}", result);
-        });
+// Scenario 3: Using Notify to self-wake (BAD pattern)
+async fn notify_loop() {
+    let notify = Arc::new(Notify::new());
+    let notify_clone = notify.clone();
 
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        println!();
-
-        // Scenario 3: Using Notify (common pattern that causes self-wakes)
-        println!("[Scenario 3] Using Notify to self-wake (BAD pattern)");
-        tokio::spawn(async {
-            let notify = Arc::new(Notify::new());
-            let notify_clone = notify.clone();
+    // Task that keeps waking itself
+    diagnostics::spawn("self-wakes/notify/loop", async move {
+        loop {
+            // Wait to be notified
+            notify_clone.notified().await;
 
-            // Task that keeps waking itself
-            tokio::spawn(async move {
-                loop {
-                    // Wait to be notified
-                    notify_clone.notified().await;
+            // Do some "work"
+            tokio::time::sleep(Duration::from_millis(10)).await;
 
-                    // Do some "work"
-                    tokio::time::sleep(Duration::from_millis(10)).await;
+            // Immediately wake ourselves again (BAD PATTERN!)
+            notify_clone.notify_one();
+        }
+    });
 
-                    // Immediately wake ourselves again (BAD PATTERN!)
-                    notify_clone.notify_one();
-                }
-            });
+    // Start the cycle
+    notify.notify_one();
 
-            // Start the cycle
-            notify.notify_one();
+    // Keep the task alive
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
 
-            // Keep the task alive
+// Some normal tasks for comparison
+async fn normal_tasks() {
+    for i in 0..3 {
+        diagnostics::spawn(format!("self-wakes/normal/{}", i), async move {
             loop {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                println!("Normal task {} working...", i);
             }
         });
+    }
+}
 
-        // Some normal tasks for comparison
-        for i in 0..3 {
-            tokio::spawn(async move {
-                loop {
-                    tokio::time::sleep(Duration::from_secs(2)).await;
-                    println!("Normal task {} working...", i);
-                }
-            });
-        }
+/// The scenarios of this example, also run by `tests/console_warnings.rs`
+pub fn registry() -> Registry {
+    Registry::new(
+        "=== Self-Wakes Examples ===\n\
+         This demonstrates tasks that wake themselves too frequently.\n\
+         Connect with: tokio-console\n\
+         Look for high self-wake percentage in the tasks view:\n\
+         1. wake-by-ref: very high self-wake % (close to 100%)\n\
+         2. sleep: low self-wake % (should be 0%)\n\
+         3. notify: high self-wake % (90%+)\n\
+         4. normal: 0% self-wake\n",
+    )
+    .scenario(
+        Scenario::new(
+            "self-wakes/wake-by-ref",
+            "Custom Future using wake_by_ref() (BAD)",
+            || {
+                Box::pin(async {
+                    let result = SelfWakingFuture::new(100).await;
+                    println!("  SelfWakingFuture completed with result: {}", result);
+                })
+            },
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "self-wakes/sleep",
+            "Better Future with proper sleep (GOOD)",
+            || {
+                Box::pin(async {
+                    let result = BetterYieldingFuture::new(50).await;
+                    println!("  BetterYieldingFuture completed with result: {}", result);
+                })
+            },
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new(
+            "self-wakes/notify",
+            "Using Notify to self-wake (BAD)",
+            || Box::pin(notify_loop()),
+        )
+        .by_default(),
+    )
+    .scenario(
+        Scenario::new("self-wakes/normal", "Normal tasks for comparison", || {
+            Box::pin(normal_tasks())
+        })
+        .by_default(),
+    )
+}
 
-        // Keep the program running
-        let mut tick = 0;
-        loop {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-            tick += 1;
-            println!("\n=== Status Update #{} ===", tick);
-            println!("Program still running... Check tokio-console!");
-            println!("\nExpected observations in tokio-console:");
-            println!("1. SelfWakingFuture task: Very high self-wake % (close to 100%)");
-            println!("2. BetterYieldingFuture task: Low self-wake % (should be 0%)");
-            println!("3. Notify-based task: High self-wake % (90%+)");
-            println!("4. Normal tasks: 0% self-wake");
-            println!("===================\n");
-        }
-    });
+fn main() -> ExitCode {
+    registry().main()
 }
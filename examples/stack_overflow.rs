@@ -12,8 +12,8 @@
 //! ```
 //!
 //! Expected result: Stack overflow crash
-
-use std::time::Duration;
+//!
+//! `stack_overflow_explained` measures where the stack goes, without crashing.
 
 // Scenario 1: Deep recursion with large data (WILL CRASH)
 fn deep_async_bad(depth: u32) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
@@ -31,6 +31,7 @@ fn deep_async_bad(depth: u32) -> std::pin::Pin<Box<dyn std::future::Future<Outpu
 }
 
 // Scenario 2: Deep recursion with boxed data (SAFE)
+#[allow(dead_code)] // For comparison; main runs the crashing version
 fn deep_async_good(depth: u32) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
     Box::pin(async move {
         // ✅ Box moves data to heap
@@ -0,0 +1,218 @@
+//! Why `stack_overflow` crashes, measured step by step
+//!
+//! `stack_overflow.rs` recurses through boxed async functions holding a
+//! 100 KB array and dies. The array lives inside the boxed future, on the
+//! heap, so why does the *stack* overflow? This walkthrough recurses a few
+//! levels with each variant, records the stack pointer at every level, and
+//! reports how much stack one level costs and how deep a tokio worker
+//! thread (2 MiB stack by default) could go before crashing.
+//!
+//! Two things add up: every level of the recursion is one more nested
+//! `poll` frame, and the array may be built as a temporary in that frame
+//! before it is moved to the heap.
+//!
+//! Nothing here crashes: the measurements run on a thread with a large
+//! stack and only recurse a handful of levels.
+//!
+//! Run this with:
+//! ```
+//! cargo run --example stack_overflow_explained
+//! cargo run --release --example stack_overflow_explained
+//! ```
+//!
+//! Compare debug and release: the optimizer decides which temporaries are
+//! elided, and the two builds disagree on which variant is expensive.
+
+use std::future::Future;
+use std::hint::black_box;
+use std::pin::Pin;
+
+/// Levels recursed per variant; enough for a stable average
+const LEVELS: usize = 8;
+
+/// Bytes of data each level holds, as in `stack_overflow.rs`
+const DATA: usize = 100_000;
+
+/// tokio's default worker thread stack size
+const WORKER_STACK: usize = 2 * 1024 * 1024;
+
+/// Stack the measurements run on, so even the worst variant fits
+const MEASURE_STACK: usize = 256 * 1024 * 1024;
+
+type Level<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Approximate stack pointer: the address of a local in a frame of its own
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    black_box(&marker) as *const u8 as usize
+}
+
+/// What one variant costs per recursion level
+struct Measurement {
+    /// Stack used by one level while the chain is being polled
+    stack_per_level: usize,
+    /// Heap used by one level's boxed future (0 for plain functions)
+    future_size: usize,
+}
+
+impl Measurement {
+    fn from_samples(samples: &[usize], future_size: usize) -> Self {
+        // The stack grows down on every platform tokio supports, but
+        // abs_diff keeps this honest either way
+        let total: usize = samples.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+        Self {
+            stack_per_level: total / (samples.len() - 1),
+            future_size,
+        }
+    }
+
+    fn print(&self, name: &str) {
+        let fits = WORKER_STACK
+            .checked_div(self.stack_per_level)
+            .map_or("unlimited".to_string(), |levels| levels.to_string());
+        println!(
+            "  {:<28} {:>9} B stack/level {:>9} B heap/level  ~{} levels in 2 MiB",
+            name, self.stack_per_level, self.future_size, fits
+        );
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Step 1: plain recursion keeps its locals on the stack
+// ----------------------------------------------------------------------------
+
+/// Every frame holds its own copy of the array: ~DATA bytes per level
+#[inline(never)]
+fn sync_recursion(depth: usize, samples: &mut Vec<usize>) {
+    let data = [depth as u8; DATA];
+    samples.push(stack_address());
+    if depth < LEVELS {
+        sync_recursion(depth + 1, samples);
+    }
+    black_box(&data);
+}
+
+// ----------------------------------------------------------------------------
+// Step 2: async recursion must be boxed
+// ----------------------------------------------------------------------------
+//
+// An `async fn` that awaits itself would have an infinitely large future,
+// so each level is `Box::pin`ned. Polling level N calls `poll` on level N+1,
+// so the *poll chain* still nests one stack frame per level. Small frames,
+// but `stack_overflow.rs` asks for ten million of them.
+
+/// Baseline: nothing large anywhere
+fn async_small(depth: usize, samples: &mut Vec<usize>) -> Level<'_> {
+    Box::pin(async move {
+        samples.push(stack_address());
+        if depth < LEVELS {
+            async_small(depth + 1, samples).await;
+        }
+    })
+}
+
+/// `stack_overflow.rs`'s BAD variant: the array is held across the await,
+/// so it is stored in the boxed future. Whether it is initialized in place
+/// or built in the poll frame and copied in is up to the optimizer.
+fn async_inline_array(depth: usize, samples: &mut Vec<usize>) -> Level<'_> {
+    Box::pin(async move {
+        let data = [depth as u8; DATA];
+        samples.push(stack_address());
+        if depth < LEVELS {
+            async_inline_array(depth + 1, samples).await;
+        }
+        black_box(&data);
+    })
+}
+
+/// `stack_overflow.rs`'s GOOD variant: `Box::new([..])` builds the array
+/// on the stack and then moves it to the heap, unless the optimizer elides
+/// the copy
+fn async_box_new(depth: usize, samples: &mut Vec<usize>) -> Level<'_> {
+    Box::pin(async move {
+        let data = Box::new([depth as u8; DATA]);
+        samples.push(stack_address());
+        if depth < LEVELS {
+            async_box_new(depth + 1, samples).await;
+        }
+        black_box(&data);
+    })
+}
+
+/// Allocating directly on the heap: no large temporary anywhere
+fn async_vec(depth: usize, samples: &mut Vec<usize>) -> Level<'_> {
+    Box::pin(async move {
+        let data = vec![depth as u8; DATA];
+        samples.push(stack_address());
+        if depth < LEVELS {
+            async_vec(depth + 1, samples).await;
+        }
+        black_box(&data);
+    })
+}
+
+/// Run a recursive future to completion and measure it
+fn measure_async(start: for<'a> fn(usize, &'a mut Vec<usize>) -> Level<'a>) -> Measurement {
+    let mut samples = Vec::with_capacity(LEVELS + 1);
+    // Size of one level, measured on an unpolled future that recurses no further
+    let mut scratch = Vec::new();
+    let level = start(LEVELS, &mut scratch);
+    let future_size = std::mem::size_of_val(&*level);
+    drop(level);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(start(0, &mut samples));
+    Measurement::from_samples(&samples, future_size)
+}
+
+fn walkthrough() {
+    println!("=== Stack usage per recursion level ===");
+    println!(
+        "Each level holds {} bytes of data; {} levels measured per variant.\n",
+        DATA, LEVELS
+    );
+
+    println!("Step 1: synchronous recursion");
+    let mut samples = Vec::with_capacity(LEVELS + 1);
+    sync_recursion(0, &mut samples);
+    Measurement::from_samples(&samples, 0).print("array local");
+    println!("  -> Every frame carries the array. No surprise.\n");
+
+    println!("Step 2: boxed async recursion, nothing large");
+    measure_async(async_small).print("small future");
+    println!("  -> Only the poll frames of the chain: a few hundred bytes.\n");
+
+    println!("Step 3: boxed async recursion holding the data");
+    measure_async(async_inline_array).print("[u8; N] across await");
+    measure_async(async_box_new).print("Box::new([u8; N])");
+    measure_async(async_vec).print("vec![0u8; N]");
+    println!(
+        "  -> The data ends up on the heap in every case, but it may be built\n\
+         \x20    in the poll frame first, and then every level of the poll chain\n\
+         \x20    carries the whole array. Which variant pays depends on the build\n\
+         \x20    (compare debug and release); only vec! never puts it on the stack.\n"
+    );
+
+    println!("Takeaways:");
+    println!("  1. Recursion depth x stack per poll frame must fit the worker stack");
+    println!("  2. Build large buffers directly on the heap (vec!, Vec::with_capacity)");
+    println!("  3. Bound recursion depth, or turn it into a loop over an explicit stack");
+    println!(
+        "  4. If it must recurse deeply, raise Builder::thread_stack_size \
+         (the default is 2 MiB)"
+    );
+}
+
+fn main() {
+    // A big stack so the measurements themselves cannot overflow
+    std::thread::Builder::new()
+        .name("measure".to_string())
+        .stack_size(MEASURE_STACK)
+        .spawn(walkthrough)
+        .unwrap()
+        .join()
+        .unwrap();
+}
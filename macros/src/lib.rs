@@ -0,0 +1,129 @@
+//! Procedural macros of `tokio-console-demo`, re-exported from its
+//! `diagnostics` module
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Attribute, Error, Expr, ItemFn};
+
+/// Fail a generated test when the future of an async fn is larger than the
+/// given number of bytes
+///
+/// ```ignore
+/// #[max_future_size(4096)]
+/// async fn handle(request: Request) -> Response {
+///     // ...
+/// }
+/// ```
+///
+/// The function is left as is; next to it comes a `#[test]` named
+/// `max_future_size_<fn>` that measures the future type without calling the
+/// function, and panics with the actual size when it is over the limit. A
+/// future's size is only known once the types are laid out, after any
+/// constant a compile-time assertion could use is evaluated, hence a test.
+///
+/// `#[ignore]` and `#[should_panic]` written below the attribute go to the
+/// generated test instead of the function.
+///
+/// Only for free async fns without type parameters: generic futures have a
+/// size per type, and a test cannot be put beside an associated fn. Those
+/// taking `self` or naming `Self` are rejected here; for the others, rustc
+/// rejects the `#[test]` generated inside the `impl` block.
+#[proc_macro_attribute]
+pub fn max_future_size(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let limit = parse_macro_input!(attr as Expr);
+    let mut function = parse_macro_input!(item as ItemFn);
+    let (test_attrs, attrs) = function
+        .attrs
+        .into_iter()
+        .partition(|attr| attr.path().is_ident("ignore") || attr.path().is_ident("should_panic"));
+    function.attrs = attrs;
+    match size_test(&limit, &function, &test_attrs) {
+        Ok(test) => quote!(#function #test).into(),
+        Err(error) => {
+            let error = error.to_compile_error();
+            quote!(#function #error).into()
+        }
+    }
+}
+
+fn size_test(limit: &Expr, function: &ItemFn, attrs: &[Attribute]) -> syn::Result<TokenStream2> {
+    let sig = &function.sig;
+    if sig.asyncness.is_none() {
+        return Err(Error::new(
+            sig.fn_token.span(),
+            "#[max_future_size] applies to async fns",
+        ));
+    }
+    if let Some(receiver) = sig.receiver() {
+        return Err(Error::new(
+            receiver.span(),
+            "#[max_future_size] cannot name methods; call a free async fn from this one \
+             and put the attribute on it",
+        ));
+    }
+    if has_ident(quote!(#sig), "Self") {
+        return Err(Error::new(
+            sig.span(),
+            "#[max_future_size] cannot name associated fns; call a free async fn from this \
+             one and put the attribute on it",
+        ));
+    }
+    let generic = sig
+        .generics
+        .type_params()
+        .map(|param| param.span())
+        .chain(sig.generics.const_params().map(|param| param.span()))
+        .chain(
+            sig.inputs
+                .iter()
+                .filter(|input| has_ident(quote!(#input), "impl"))
+                .map(|input| input.span()),
+        )
+        .next();
+    if let Some(span) = generic {
+        return Err(Error::new(
+            span,
+            "#[max_future_size] needs a future type of known size; generic async fns have \
+             one per instantiation",
+        ));
+    }
+
+    let name = &sig.ident;
+    let test = format_ident!("max_future_size_{}", name);
+    let args: Vec<Ident> = (0..sig.inputs.len())
+        .map(|i| format_ident!("A{}", i))
+        .collect();
+    Ok(quote! {
+        #[test]
+        #(#attrs)*
+        fn #test() {
+            // The function item is passed, not called: only its return type
+            // is looked at
+            fn future_size<Fut, #(#args),*>(_: impl FnOnce(#(#args),*) -> Fut) -> usize {
+                ::core::mem::size_of::<Fut>()
+            }
+            let limit: usize = #limit;
+            let size = future_size(#name);
+            assert!(
+                size <= limit,
+                "future of async fn {} is {} bytes, over its max_future_size of {} bytes; \
+                 drop or box the state it keeps across awaits",
+                stringify!(#name),
+                size,
+                limit
+            );
+        }
+    })
+}
+
+/// Whether `tokens` contain the keyword or identifier `ident`, e.g. `impl`
+/// in an argument `&impl AsRef<str>`
+fn has_ident(tokens: TokenStream2, ident: &str) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(found) => found == ident,
+        TokenTree::Group(group) => has_ident(group.stream(), ident),
+        _ => false,
+    })
+}
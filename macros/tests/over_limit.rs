@@ -0,0 +1,20 @@
+//! The generated test fails for a future over its limit
+
+use tokio_console_demo_macros::max_future_size;
+
+async fn yield_now() {}
+
+#[max_future_size(64)]
+#[should_panic(expected = "over its max_future_size of 64 bytes")]
+async fn holds_buffer() -> u8 {
+    let buffer = [0u8; 4096];
+    yield_now().await;
+    buffer[0]
+}
+
+#[max_future_size(64)]
+async fn drops_buffer() -> u8 {
+    let first = [0u8; 4096][0];
+    yield_now().await;
+    first
+}
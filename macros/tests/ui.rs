@@ -0,0 +1,6 @@
+//! `#[max_future_size]` rejects what its generated test cannot measure
+
+#[test]
+fn ui() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,12 @@
+use tokio_console_demo_macros::max_future_size;
+
+struct Client;
+
+impl Client {
+    #[max_future_size(1024)]
+    async fn connect() -> Self {
+        Client
+    }
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use tokio_console_demo_macros::max_future_size;
+
+#[max_future_size(1024)]
+async fn forward<T: Send>(value: T) -> T {
+    value
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use tokio_console_demo_macros::max_future_size;
+
+#[max_future_size(1024)]
+async fn greet(name: impl AsRef<str>) -> usize {
+    name.as_ref().len()
+}
+
+fn main() {}
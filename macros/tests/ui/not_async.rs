@@ -0,0 +1,6 @@
+use tokio_console_demo_macros::max_future_size;
+
+#[max_future_size(1024)]
+fn run() {}
+
+fn main() {}
@@ -0,0 +1,95 @@
+//! console-lint: tokio-console's warnings, for CI
+//!
+//! Watches a process's console-subscriber endpoint for a while and prints
+//! the lints that fired. Exits with 0 when none did, 1 when any did, and 2
+//! when the endpoint could not be watched.
+//!
+//! ```bash
+//! RUSTFLAGS="--cfg tokio_unstable" cargo run --example lost_waker &
+//! cargo run --bin console-lint -- --duration 10 --format json
+//! ```
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+use tokio_console_demo::console_lint::{
+    ConsoleCheck, Lint, Thresholds, DEFAULT_TARGET, ERROR_EXIT_CODE,
+};
+
+#[derive(Parser)]
+#[command(about = "Evaluate tokio-console lints against a running process")]
+struct Args {
+    /// console-subscriber gRPC endpoint
+    #[arg(long, default_value = DEFAULT_TARGET)]
+    target: String,
+
+    /// Seconds to collect updates for
+    #[arg(long, default_value_t = 10.0)]
+    duration: f64,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Only evaluate these lints (repeatable, or comma-separated)
+    #[arg(long = "lint", value_delimiter = ',')]
+    lints: Vec<Lint>,
+
+    /// self-wakes fires above this percentage of wakeups
+    #[arg(long, default_value_t = Thresholds::default().self_wake_percent)]
+    self_wake_percent: u64,
+
+    /// never-yielded fires after this many seconds in the first poll
+    #[arg(long, default_value_t = Thresholds::default().never_yielded.as_secs_f64())]
+    never_yielded_secs: f64,
+
+    /// large-future fires at this many bytes
+    #[arg(long, default_value_t = Thresholds::default().large_future_bytes)]
+    large_future_bytes: u64,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let (Ok(duration), Ok(never_yielded)) = (
+        Duration::try_from_secs_f64(args.duration),
+        Duration::try_from_secs_f64(args.never_yielded_secs),
+    ) else {
+        eprintln!("Error: durations must be non-negative numbers of seconds");
+        return ExitCode::from(ERROR_EXIT_CODE);
+    };
+
+    let mut check = ConsoleCheck::new(args.target)
+        .duration(duration)
+        .thresholds(Thresholds {
+            self_wake_percent: args.self_wake_percent,
+            never_yielded,
+            large_future_bytes: args.large_future_bytes,
+        });
+    if !args.lints.is_empty() {
+        check = check.lints(args.lints);
+    }
+
+    let report = match check.run().await {
+        Ok(report) => report,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            return ExitCode::from(ERROR_EXIT_CODE);
+        }
+    };
+
+    match args.format {
+        Format::Text => print!("{}", report.to_text()),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+    }
+
+    ExitCode::from(report.exit_code())
+}
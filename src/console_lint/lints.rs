@@ -0,0 +1,320 @@
+//! The tokio-console task lints, evaluated headlessly
+//!
+//! Same rules and default thresholds as the warnings shown by tokio-console.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+use super::task::TaskState;
+
+/// A task lint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Lint {
+    /// The task woke itself for more than a threshold share of its wakeups
+    SelfWakes,
+    /// The task is idle and nothing holds its waker: it will never run again
+    LostWaker,
+    /// The task has been inside its first poll for longer than a threshold
+    NeverYielded,
+    /// The spawned future is larger than a threshold
+    LargeFuture,
+    /// The runtime boxed the future when spawning because of its size
+    AutoBoxedFuture,
+}
+
+impl Lint {
+    /// Every lint, in report order
+    pub const ALL: [Lint; 5] = [
+        Lint::SelfWakes,
+        Lint::LostWaker,
+        Lint::NeverYielded,
+        Lint::LargeFuture,
+        Lint::AutoBoxedFuture,
+    ];
+
+    /// Kebab-case name used on the command line and in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::SelfWakes => "self-wakes",
+            Lint::LostWaker => "lost-waker",
+            Lint::NeverYielded => "never-yielded",
+            Lint::LargeFuture => "large-future",
+            Lint::AutoBoxedFuture => "auto-boxed-future",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Lint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lint::ALL
+            .into_iter()
+            .find(|lint| lint.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+                format!("unknown lint: {} (expected one of {})", s, names.join(", "))
+            })
+    }
+}
+
+/// Lint thresholds, defaulting to tokio-console's
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    /// `self-wakes` fires above this percentage of self-wakes (default: 50)
+    pub self_wake_percent: u64,
+    /// `never-yielded` fires after this long in the first poll (default: 1s)
+    pub never_yielded: Duration,
+    /// `large-future` fires at this many bytes (default: 1024)
+    pub large_future_bytes: u64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            self_wake_percent: 50,
+            never_yielded: Duration::from_secs(1),
+            large_future_bytes: 1024,
+        }
+    }
+}
+
+/// Check one lint against a task, returning the warning message if it fires
+pub(super) fn check(
+    lint: Lint,
+    task: &TaskState,
+    thresholds: &Thresholds,
+    now: SystemTime,
+) -> Option<String> {
+    match lint {
+        Lint::SelfWakes => {
+            let wakes = task.wakes();
+            if wakes == 0 {
+                return None;
+            }
+            let percent = task.self_wakes() * 100 / wakes;
+            (percent > thresholds.self_wake_percent).then(|| {
+                format!(
+                    "This task has woken itself for more than {}% of its total wakeups ({}%)",
+                    thresholds.self_wake_percent, percent
+                )
+            })
+        }
+        Lint::LostWaker => {
+            // Tasks that were never polled have not had a chance to register a
+            // waker, and `block_on` wakers are invisible to the subscriber
+            let lost = !task.is_blocking()
+                && !task.is_block_on()
+                && !task.is_completed()
+                && task.polls() > 0
+                && task.waker_count() == 0
+                && !task.is_running()
+                && !task.is_awakened();
+            lost.then(|| "This task has lost its waker, and will never be woken again".to_string())
+        }
+        Lint::NeverYielded => {
+            // Blocking tasks are expected to run without yielding
+            if task.is_blocking() || task.is_completed() || !task.is_running() || task.polls() > 1 {
+                return None;
+            }
+            let busy = task.busy(now);
+            (busy >= thresholds.never_yielded)
+                .then(|| format!("This task has never yielded ({:.1?})", busy))
+        }
+        Lint::LargeFuture => {
+            let size = task.size_bytes?;
+            (size >= thresholds.large_future_bytes).then(|| {
+                format!(
+                    "This task occupies a large amount of stack space ({} bytes)",
+                    size
+                )
+            })
+        }
+        Lint::AutoBoxedFuture => {
+            let original = task.original_size_bytes?;
+            Some(format!(
+                "This task's future was auto-boxed by the runtime when spawning, \
+                 due to its size (originally {} bytes, {} bytes after boxing)",
+                original,
+                task.size_bytes.unwrap_or_default()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use console_api::tasks::Stats;
+    use console_api::PollStats;
+
+    use super::*;
+
+    /// An async task polled `polls` times, the last poll from `started`
+    /// until `ended` (still inside it when `None`)
+    fn task(polls: u64, started: SystemTime, ended: Option<SystemTime>) -> TaskState {
+        TaskState {
+            id: 1,
+            task_id: Some(1),
+            kind: Some("task".to_string()),
+            stats: Some(Stats {
+                poll_stats: Some(PollStats {
+                    polls,
+                    first_poll: Some(started.into()),
+                    last_poll_started: Some(started.into()),
+                    last_poll_ended: ended.map(Into::into),
+                    busy_time: ended
+                        .and_then(|ended| ended.duration_since(started).ok())
+                        .and_then(|busy| busy.try_into().ok()),
+                }),
+                ..Stats::default()
+            }),
+            ..TaskState::default()
+        }
+    }
+
+    fn stats(task: &mut TaskState) -> &mut Stats {
+        task.stats.as_mut().unwrap()
+    }
+
+    /// Lints firing for `task` with the default thresholds
+    fn fired(task: &TaskState, now: SystemTime) -> Vec<Lint> {
+        Lint::ALL
+            .into_iter()
+            .filter(|&lint| check(lint, task, &Thresholds::default(), now).is_some())
+            .collect()
+    }
+
+    fn ago(now: SystemTime, millis: u64) -> SystemTime {
+        now - Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn idle_task_with_a_waker_is_clean() {
+        let now = SystemTime::now();
+        let mut idle = task(3, ago(now, 100), Some(ago(now, 90)));
+        stats(&mut idle).waker_clones = 1;
+        assert_eq!(fired(&idle, now), vec![]);
+    }
+
+    #[test]
+    fn self_wakes_above_the_threshold() {
+        let now = SystemTime::now();
+        let mut task = task(10, ago(now, 100), Some(ago(now, 90)));
+        stats(&mut task).waker_clones = 1;
+        stats(&mut task).wakes = 10;
+
+        stats(&mut task).self_wakes = 6;
+        assert_eq!(fired(&task, now), vec![Lint::SelfWakes]);
+        let message = check(Lint::SelfWakes, &task, &Thresholds::default(), now).unwrap();
+        assert!(message.contains("(60%)"), "{}", message);
+
+        // Exactly at the threshold does not fire
+        stats(&mut task).self_wakes = 5;
+        assert_eq!(fired(&task, now), vec![]);
+    }
+
+    #[test]
+    fn lost_waker_on_an_idle_task_without_wakers() {
+        let now = SystemTime::now();
+        let mut lost = task(2, ago(now, 100), Some(ago(now, 90)));
+        stats(&mut lost).waker_clones = 3;
+        stats(&mut lost).waker_drops = 3;
+        assert_eq!(fired(&lost, now), vec![Lint::LostWaker]);
+
+        // Woken after its last poll started: about to be polled again
+        stats(&mut lost).last_wake = Some(ago(now, 50).into());
+        assert_eq!(fired(&lost, now), vec![]);
+        stats(&mut lost).last_wake = None;
+
+        let mut completed = task(2, ago(now, 100), Some(ago(now, 90)));
+        stats(&mut completed).dropped_at = Some(ago(now, 80).into());
+        assert_eq!(fired(&completed, now), vec![]);
+
+        let mut block_on = task(2, ago(now, 100), Some(ago(now, 90)));
+        block_on.kind = Some("block_on".to_string());
+        assert_eq!(fired(&block_on, now), vec![]);
+
+        let mut never_polled = task(0, now, None);
+        stats(&mut never_polled).poll_stats = None;
+        assert_eq!(fired(&never_polled, now), vec![]);
+    }
+
+    #[test]
+    fn never_yielded_in_a_long_first_poll() {
+        let now = SystemTime::now();
+        let stuck = task(1, ago(now, 1_500), None);
+        assert_eq!(fired(&stuck, now), vec![Lint::NeverYielded]);
+
+        let short = task(1, ago(now, 200), None);
+        assert_eq!(fired(&short, now), vec![]);
+
+        // Only the first poll counts, and blocking tasks never yield
+        let later_poll = task(2, ago(now, 1_500), None);
+        assert_eq!(fired(&later_poll, now), vec![]);
+        let mut blocking = task(1, ago(now, 1_500), None);
+        blocking.kind = Some("blocking".to_string());
+        assert_eq!(fired(&blocking, now), vec![]);
+
+        // A first poll that took long but returned
+        let mut returned = task(1, ago(now, 3_000), Some(ago(now, 1_000)));
+        stats(&mut returned).waker_clones = 1;
+        assert_eq!(fired(&returned, now), vec![]);
+    }
+
+    #[test]
+    fn large_and_auto_boxed_futures() {
+        let now = SystemTime::now();
+        let mut task = task(1, ago(now, 100), Some(ago(now, 90)));
+        stats(&mut task).waker_clones = 1;
+
+        task.size_bytes = Some(1023);
+        assert_eq!(fired(&task, now), vec![]);
+        task.size_bytes = Some(1024);
+        assert_eq!(fired(&task, now), vec![Lint::LargeFuture]);
+
+        task.size_bytes = Some(8);
+        task.original_size_bytes = Some(20_000);
+        assert_eq!(fired(&task, now), vec![Lint::AutoBoxedFuture]);
+        let message = check(Lint::AutoBoxedFuture, &task, &Thresholds::default(), now).unwrap();
+        assert!(
+            message.contains("originally 20000 bytes, 8 bytes"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn thresholds_are_configurable() {
+        let now = SystemTime::now();
+        let mut task = task(1, ago(now, 300), None);
+        task.size_bytes = Some(512);
+        let thresholds = Thresholds {
+            self_wake_percent: 50,
+            never_yielded: Duration::from_millis(250),
+            large_future_bytes: 512,
+        };
+        let fired: Vec<_> = Lint::ALL
+            .into_iter()
+            .filter(|&lint| check(lint, &task, &thresholds, now).is_some())
+            .collect();
+        assert_eq!(fired, vec![Lint::NeverYielded, Lint::LargeFuture]);
+    }
+
+    #[test]
+    fn lint_names_round_trip() {
+        for lint in Lint::ALL {
+            assert_eq!(lint.name().parse::<Lint>(), Ok(lint));
+        }
+        assert!("self_wakes".parse::<Lint>().is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! Headless tokio-console: evaluate the console lints without a terminal
+//!
+//! tokio-console shows its warnings in an interactive UI, which cannot run
+//! in CI. [`ConsoleCheck`] connects to the same console-subscriber gRPC
+//! endpoint, watches task and resource updates for a while, and returns a
+//! [`Report`] of every lint that fired. The `console-lint` binary wraps it:
+//!
+//! ```bash
+//! RUSTFLAGS="--cfg tokio_unstable" cargo run --example self_wakes &
+//! cargo run --bin console-lint -- --duration 10
+//! ```
+//!
+//! The process under test must install console-subscriber
+//! (`console_subscriber::init()`) and be built with `--cfg tokio_unstable`,
+//! otherwise tokio emits no task instrumentation.
+//!
+//! A warning is reported if its lint held for a task at any update during the
+//! window, even if the task recovered or completed before the end.
+
+mod lints;
+mod report;
+mod task;
+
+pub use lints::{Lint, Thresholds};
+pub use report::{Report, Warning, ERROR_EXIT_CODE};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+use console_api::instrument::instrument_client::InstrumentClient;
+use console_api::instrument::{InstrumentRequest, Update};
+use tokio::time::Instant;
+
+use task::TaskState;
+
+/// Default console-subscriber endpoint
+pub const DEFAULT_TARGET: &str = "http://127.0.0.1:6669";
+
+/// Keep retrying the connection this long, so the checker can be started
+/// alongside the process under test
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Watch a console-subscriber endpoint and evaluate the lints
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tokio_console_demo::console_lint::ConsoleCheck;
+///
+/// # async fn run() -> Result<(), String> {
+/// let report = ConsoleCheck::new("http://127.0.0.1:6669")
+///     .duration(Duration::from_secs(5))
+///     .run()
+///     .await?;
+/// print!("{}", report.to_text());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConsoleCheck {
+    target: String,
+    duration: Duration,
+    lints: Vec<Lint>,
+    thresholds: Thresholds,
+}
+
+impl Default for ConsoleCheck {
+    fn default() -> Self {
+        Self::new(DEFAULT_TARGET)
+    }
+}
+
+impl ConsoleCheck {
+    /// Check the process serving console-subscriber at `target`
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            target: target.into(),
+            duration: Duration::from_secs(10),
+            lints: Lint::ALL.to_vec(),
+            thresholds: Thresholds::default(),
+        }
+    }
+
+    /// How long to collect updates (default: 10s)
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Lints to evaluate (default: all of them)
+    pub fn lints(mut self, lints: impl IntoIterator<Item = Lint>) -> Self {
+        self.lints = lints.into_iter().collect();
+        self
+    }
+
+    /// Lint thresholds (default: tokio-console's)
+    pub fn thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Connect, watch for the configured duration and report
+    ///
+    /// Fails if the endpoint cannot be reached or the stream breaks; a
+    /// process exiting mid-check ends the window early instead.
+    pub async fn run(self) -> Result<Report, String> {
+        let mut client = self.connect().await?;
+        let mut stream = client
+            .watch_updates(InstrumentRequest {})
+            .await
+            .map_err(|e| format!("watch_updates failed: {}", e.message()))?
+            .into_inner();
+
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let mut state = WatchState::default();
+
+        loop {
+            let update = match tokio::time::timeout_at(deadline, stream.message()).await {
+                Err(_) => break,
+                Ok(Ok(Some(update))) => update,
+                // The process exited: report what was collected so far
+                Ok(Ok(None)) => break,
+                Ok(Err(status)) if state.updates > 0 => {
+                    tracing::warn!("console stream ended: {}", status.message());
+                    break;
+                }
+                Ok(Err(status)) => {
+                    return Err(format!("console stream failed: {}", status.message()))
+                }
+            };
+            state.apply(update);
+            self.evaluate(&mut state);
+        }
+
+        let warnings = state
+            .warnings
+            .into_iter()
+            .map(|((lint, id), message)| {
+                let task = &state.tasks[&id];
+                Warning {
+                    lint,
+                    task_id: task.task_id.unwrap_or(task.id),
+                    task_name: task.name.clone(),
+                    location: task.location.clone(),
+                    message,
+                }
+            })
+            .collect();
+
+        Ok(Report {
+            target: self.target,
+            duration_secs: started.elapsed().as_secs_f64(),
+            tasks_observed: state.tasks.len(),
+            resources_observed: state.resources.len(),
+            warnings,
+        })
+    }
+
+    async fn connect(&self) -> Result<InstrumentClient<tonic::transport::Channel>, String> {
+        let give_up = Instant::now() + CONNECT_TIMEOUT;
+        loop {
+            match InstrumentClient::connect(self.target.clone()).await {
+                Ok(client) => return Ok(client),
+                Err(e) if Instant::now() >= give_up => {
+                    return Err(format!("failed to connect to {}: {}", self.target, e))
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(200)).await,
+            }
+        }
+    }
+
+    /// Check every known task, so tasks stuck in a poll (which send no
+    /// stats updates) are still caught by `never-yielded`
+    fn evaluate(&self, state: &mut WatchState) {
+        for task in state.tasks.values() {
+            for &lint in &self.lints {
+                if let Some(message) = lints::check(lint, task, &self.thresholds, state.now) {
+                    state.warnings.insert((lint, task.id), message);
+                }
+            }
+        }
+    }
+}
+
+/// Everything collected from the update stream so far
+struct WatchState {
+    updates: u64,
+    /// Clock of the process under test, from the latest update
+    now: SystemTime,
+    /// Field names by metadata id, to resolve indexed task fields
+    field_names: HashMap<u64, Vec<String>>,
+    tasks: HashMap<u64, TaskState>,
+    resources: HashSet<u64>,
+    /// Latest message per lint and task; ordered for a stable report
+    warnings: BTreeMap<(Lint, u64), String>,
+}
+
+impl Default for WatchState {
+    fn default() -> Self {
+        Self {
+            updates: 0,
+            now: SystemTime::now(),
+            field_names: HashMap::new(),
+            tasks: HashMap::new(),
+            resources: HashSet::new(),
+            warnings: BTreeMap::new(),
+        }
+    }
+}
+
+impl WatchState {
+    fn apply(&mut self, update: Update) {
+        self.updates += 1;
+        self.now = update
+            .now
+            .and_then(|now| SystemTime::try_from(now).ok())
+            .unwrap_or_else(SystemTime::now);
+
+        if let Some(metadata) = update.new_metadata {
+            for new in metadata.metadata {
+                if let (Some(id), Some(meta)) = (new.id, new.metadata) {
+                    self.field_names.insert(id.id, meta.field_names);
+                }
+            }
+        }
+
+        if let Some(task_update) = update.task_update {
+            for task in &task_update.new_tasks {
+                if let Some(state) = TaskState::new(task, &self.field_names) {
+                    self.tasks.insert(state.id, state);
+                }
+            }
+            for (id, stats) in task_update.stats_update {
+                if let Some(task) = self.tasks.get_mut(&id) {
+                    task.stats = Some(stats);
+                }
+            }
+        }
+
+        if let Some(resource_update) = update.resource_update {
+            self.resources.extend(
+                resource_update
+                    .new_resources
+                    .iter()
+                    .filter_map(|r| r.id.as_ref().map(|id| id.id)),
+            );
+        }
+    }
+}
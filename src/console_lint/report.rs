@@ -0,0 +1,134 @@
+//! Result of a console check, as JSON or text
+
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use super::lints::Lint;
+
+/// Exit status of `console-lint` when the endpoint could not be watched
+pub const ERROR_EXIT_CODE: u8 = 2;
+
+/// Lint warnings collected from one process
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// console-subscriber endpoint that was watched
+    pub target: String,
+    /// How long updates were collected
+    pub duration_secs: f64,
+    /// Distinct tasks seen, including completed ones
+    pub tasks_observed: usize,
+    /// Distinct resources seen (mutexes, semaphores, timers...)
+    pub resources_observed: usize,
+    /// Lints that fired, by lint then task id
+    pub warnings: Vec<Warning>,
+}
+
+/// One lint firing for one task
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub lint: Lint,
+    /// tokio task id
+    pub task_id: u64,
+    pub task_name: Option<String>,
+    /// Spawn location, `file:line:column`
+    pub location: Option<String>,
+    /// Latest message of the lint for this task
+    pub message: String,
+}
+
+impl Report {
+    /// No lint fired
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Exit status of `console-lint`: 0 when no lint fired, 1 otherwise
+    ///
+    /// A check that could not run exits with [`ERROR_EXIT_CODE`] instead.
+    pub fn exit_code(&self) -> u8 {
+        u8::from(!self.is_clean())
+    }
+
+    /// Warnings of one lint
+    pub fn warnings_for(&self, lint: Lint) -> impl Iterator<Item = &Warning> {
+        self.warnings.iter().filter(move |w| w.lint == lint)
+    }
+
+    /// Human-readable report, one block per warning
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "Watched {} for {:.1}s: {} tasks, {} resources, {} warnings\n",
+            self.target,
+            self.duration_secs,
+            self.tasks_observed,
+            self.resources_observed,
+            self.warnings.len()
+        );
+        for warning in &self.warnings {
+            let _ = writeln!(
+                out,
+                "\n[{}] task {} {}",
+                warning.lint,
+                warning.task_id,
+                warning.task_name.as_deref().unwrap_or("<unnamed>")
+            );
+            if let Some(location) = &warning.location {
+                let _ = writeln!(out, "  spawned at {}", location);
+            }
+            let _ = writeln!(out, "  {}", warning.message);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(warnings: Vec<Warning>) -> Report {
+        Report {
+            target: "http://127.0.0.1:6669".to_string(),
+            duration_secs: 1.0,
+            tasks_observed: 3,
+            resources_observed: 0,
+            warnings,
+        }
+    }
+
+    fn warning(lint: Lint) -> Warning {
+        Warning {
+            lint,
+            task_id: 7,
+            task_name: None,
+            location: Some("src/main.rs:10:5".to_string()),
+            message: "message".to_string(),
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(report(vec![]).exit_code(), 0);
+        assert_eq!(report(vec![warning(Lint::LostWaker)]).exit_code(), 1);
+        assert_eq!(
+            report(vec![warning(Lint::SelfWakes), warning(Lint::LargeFuture)]).exit_code(),
+            1
+        );
+        assert_eq!(ERROR_EXIT_CODE, 2);
+    }
+
+    #[test]
+    fn text_report() {
+        let report = report(vec![warning(Lint::LostWaker)]);
+        assert_eq!(
+            report.to_text(),
+            "Watched http://127.0.0.1:6669 for 1.0s: 3 tasks, 0 resources, 1 warnings\n\
+             \n\
+             [lost-waker] task 7 <unnamed>\n  \
+             spawned at src/main.rs:10:5\n  \
+             message\n"
+        );
+        assert_eq!(report.warnings_for(Lint::LostWaker).count(), 1);
+        assert_eq!(report.warnings_for(Lint::SelfWakes).count(), 0);
+    }
+}
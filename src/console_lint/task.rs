@@ -0,0 +1,167 @@
+//! Task state rebuilt from console-subscriber updates
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use console_api::field::{Name, Value};
+use console_api::tasks::{Stats, Task};
+use console_api::Field;
+
+/// Everything the lints need to know about one task
+#[derive(Debug, Default)]
+pub(super) struct TaskState {
+    /// console-subscriber id (the task span)
+    pub(super) id: u64,
+    /// tokio task id, as shown by tokio-console
+    pub(super) task_id: Option<u64>,
+    pub(super) name: Option<String>,
+    pub(super) kind: Option<String>,
+    pub(super) location: Option<String>,
+    pub(super) size_bytes: Option<u64>,
+    pub(super) original_size_bytes: Option<u64>,
+    pub(super) stats: Option<Stats>,
+}
+
+impl TaskState {
+    /// Build from a `new_tasks` entry; `field_names` resolves indexed field names
+    pub(super) fn new(task: &Task, field_names: &HashMap<u64, Vec<String>>) -> Option<Self> {
+        let id = task.id.as_ref()?.id;
+        let mut state = Self {
+            id,
+            location: task.location.as_ref().and_then(|loc| {
+                let file = loc.file.as_deref()?;
+                Some(match (loc.line, loc.column) {
+                    (Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+                    (Some(line), None) => format!("{}:{}", file, line),
+                    _ => file.to_string(),
+                })
+            }),
+            ..Self::default()
+        };
+
+        for field in &task.fields {
+            let Some(name) = field_name(field, field_names) else {
+                continue;
+            };
+            match (name, &field.value) {
+                // Unnamed tasks carry an empty name
+                ("task.name", Some(value)) => {
+                    state.name = Some(value_string(value)).filter(|name| !name.is_empty())
+                }
+                ("task.id", Some(Value::U64Val(id))) => state.task_id = Some(*id),
+                ("kind", Some(value)) => state.kind = Some(value_string(value)),
+                ("size.bytes", Some(Value::U64Val(bytes))) => state.size_bytes = Some(*bytes),
+                ("original_size.bytes", Some(Value::U64Val(bytes))) => {
+                    state.original_size_bytes = Some(*bytes)
+                }
+                _ => {}
+            }
+        }
+
+        Some(state)
+    }
+
+    pub(super) fn is_blocking(&self) -> bool {
+        self.kind.as_deref() == Some("blocking")
+    }
+
+    /// The future passed to `block_on`, whose waker tokio does not instrument
+    pub(super) fn is_block_on(&self) -> bool {
+        self.kind.as_deref() == Some("block_on")
+    }
+
+    pub(super) fn is_completed(&self) -> bool {
+        self.stats.as_ref().is_some_and(|s| s.dropped_at.is_some())
+    }
+
+    pub(super) fn polls(&self) -> u64 {
+        self.poll_stat(|p| Some(p.polls)).unwrap_or(0)
+    }
+
+    pub(super) fn wakes(&self) -> u64 {
+        self.stats.as_ref().map_or(0, |s| s.wakes)
+    }
+
+    pub(super) fn self_wakes(&self) -> u64 {
+        self.stats.as_ref().map_or(0, |s| s.self_wakes)
+    }
+
+    /// Live wakers: clones not dropped yet
+    pub(super) fn waker_count(&self) -> u64 {
+        self.stats
+            .as_ref()
+            .map_or(0, |s| s.waker_clones.saturating_sub(s.waker_drops))
+    }
+
+    /// Inside a poll right now
+    pub(super) fn is_running(&self) -> bool {
+        match (self.last_poll_started(), self.last_poll_ended()) {
+            (Some(started), Some(ended)) => started > ended,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+
+    /// Woken since the last poll started, so it is waiting to be polled
+    pub(super) fn is_awakened(&self) -> bool {
+        let last_wake = self
+            .stats
+            .as_ref()
+            .and_then(|s| s.last_wake)
+            .and_then(|ts| SystemTime::try_from(ts).ok());
+        match (last_wake, self.last_poll_started()) {
+            (Some(wake), Some(started)) => wake > started,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+
+    /// Time spent inside polls, including the current one at `now`
+    pub(super) fn busy(&self, now: SystemTime) -> Duration {
+        let finished = self
+            .poll_stat(|p| p.busy_time)
+            .and_then(|d| Duration::try_from(d).ok())
+            .unwrap_or_default();
+        let current = if self.is_running() {
+            self.last_poll_started()
+                .and_then(|started| now.duration_since(started).ok())
+                .unwrap_or_default()
+        } else {
+            Duration::ZERO
+        };
+        finished + current
+    }
+
+    fn last_poll_started(&self) -> Option<SystemTime> {
+        self.poll_stat(|p| p.last_poll_started)
+            .and_then(|ts| SystemTime::try_from(ts).ok())
+    }
+
+    fn last_poll_ended(&self) -> Option<SystemTime> {
+        self.poll_stat(|p| p.last_poll_ended)
+            .and_then(|ts| SystemTime::try_from(ts).ok())
+    }
+
+    fn poll_stat<T>(&self, read: impl FnOnce(&console_api::PollStats) -> Option<T>) -> Option<T> {
+        self.stats.as_ref()?.poll_stats.as_ref().and_then(read)
+    }
+}
+
+fn field_name<'a>(field: &'a Field, field_names: &'a HashMap<u64, Vec<String>>) -> Option<&'a str> {
+    match field.name.as_ref()? {
+        Name::StrName(name) => Some(name),
+        Name::NameIdx(idx) => field_names
+            .get(&field.metadata_id.as_ref()?.id)?
+            .get(usize::try_from(*idx).ok()?)
+            .map(String::as_str),
+    }
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::DebugVal(s) | Value::StrVal(s) => s.clone(),
+        Value::U64Val(n) => n.to_string(),
+        Value::I64Val(n) => n.to_string(),
+        Value::BoolVal(b) => b.to_string(),
+    }
+}
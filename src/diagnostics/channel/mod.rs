@@ -0,0 +1,451 @@
+//! Instrumented channels
+//!
+//! The [`mpsc`] and [`oneshot`] wrappers record which instrumented task
+//! owns each sender and receiver, and which tasks are blocked on them. A
+//! receiver blocked on a channel whose senders all belong to one task waits
+//! for that task; a sender blocked on a full channel waits for the
+//! receiver's owner. Those edges join the [wait-for graph](super::wait_for)
+//! to find deadlocks, and receivers whose senders all sit in idle tasks are
+//! reported on their own.
+//!
+//! Handles cannot see themselves being moved into another task: they take
+//! the task using them as their owner. A task keeping a handle without
+//! using it until later, e.g. the sender of a reply, should `claim()` it.
+
+pub mod mpsc;
+pub mod oneshot;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::Response;
+use serde::Serialize;
+
+use super::task::{describe, TASKS};
+use super::wait_for::WaitEdge;
+use super::{config, idle, monitor, wakes};
+use crate::http::{json_response, Body};
+
+/// Blocked receivers are judged after waiting this long
+const MIN_WAIT: Duration = Duration::from_millis(100);
+
+/// Every instrumented channel with a live handle
+static CHANNELS: ChannelTable = ChannelTable::new();
+
+struct ChannelTable {
+    next_id: AtomicU64,
+    channels: Mutex<BTreeMap<u64, Arc<ChannelState>>>,
+}
+
+impl ChannelTable {
+    const fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            channels: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn register(
+        &self,
+        kind: &'static str,
+        name: Option<String>,
+        location: &'static Location<'static>,
+    ) -> Arc<ChannelState> {
+        let channel = Arc::new(ChannelState {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            kind,
+            name,
+            location,
+            handles: Mutex::new(BTreeMap::new()),
+            waits: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(0),
+        });
+        self.channels
+            .lock()
+            .unwrap()
+            .insert(channel.id, channel.clone());
+        monitor::ensure_started();
+        channel
+    }
+
+    fn snapshot(&self) -> Vec<Arc<ChannelState>> {
+        self.channels.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Which end of a channel a handle is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Sender,
+    Receiver,
+}
+
+impl Side {
+    fn other(self) -> Self {
+        match self {
+            Side::Sender => Side::Receiver,
+            Side::Receiver => Side::Sender,
+        }
+    }
+}
+
+/// A task blocked on one end of a channel
+#[derive(Debug, Clone, Copy)]
+struct Wait {
+    side: Side,
+    task: Option<u64>,
+    location: &'static Location<'static>,
+    since: Instant,
+    /// Reported as stalled already
+    reported: bool,
+}
+
+struct ChannelState {
+    id: u64,
+    kind: &'static str,
+    name: Option<String>,
+    /// Where the channel was created
+    location: &'static Location<'static>,
+    /// Live handles and their owner task, if known
+    handles: Mutex<BTreeMap<u64, (Side, Option<u64>)>>,
+    waits: Mutex<BTreeMap<u64, Wait>>,
+    /// Ids of the handles and waits
+    next_id: AtomicU64,
+}
+
+impl ChannelState {
+    /// `mpsc channel <name> (<location>)`, for reports
+    fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} channel {} ({})", self.kind, name, self.location),
+            None => format!("{} channel {}", self.kind, self.location),
+        }
+    }
+
+    /// Owners of the live handles on `side`, `None` for unknown owners
+    fn owners(&self, side: Side) -> BTreeSet<Option<u64>> {
+        self.handles
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(s, _)| *s == side)
+            .map(|(_, owner)| *owner)
+            .collect()
+    }
+}
+
+/// Create a channel and the handles of both ends
+fn open(
+    kind: &'static str,
+    name: Option<String>,
+    location: &'static Location<'static>,
+) -> (Handle, Handle) {
+    let channel = CHANNELS.register(kind, name, location);
+    let sender = Handle::new(channel.clone(), Side::Sender, wakes::current());
+    let receiver = Handle::new(channel, Side::Receiver, wakes::current());
+    (sender, receiver)
+}
+
+/// One sender or receiver, registered in its channel while alive
+struct Handle {
+    channel: Arc<ChannelState>,
+    id: u64,
+    side: Side,
+}
+
+impl Handle {
+    fn new(channel: Arc<ChannelState>, side: Side, owner: Option<u64>) -> Self {
+        let id = channel.next_id.fetch_add(1, Ordering::Relaxed);
+        channel.handles.lock().unwrap().insert(id, (side, owner));
+        Self { channel, id, side }
+    }
+
+    /// Take the instrumented task running this as the owner
+    fn claim(&self) {
+        if let Some(task) = wakes::current() {
+            if let Some(handle) = self.channel.handles.lock().unwrap().get_mut(&self.id) {
+                handle.1 = Some(task);
+            }
+        }
+    }
+
+    /// A new handle on the same end, owned by the task cloning it
+    fn duplicate(&self) -> Self {
+        let owner = self.channel.handles.lock().unwrap()[&self.id].1;
+        Self::new(self.channel.clone(), self.side, wakes::current().or(owner))
+    }
+
+    /// Record the current task as blocked on this end until the token drops
+    fn wait(&self, location: &'static Location<'static>) -> WaitToken {
+        self.claim();
+        let id = self.channel.next_id.fetch_add(1, Ordering::Relaxed);
+        let wait = Wait {
+            side: self.side,
+            task: wakes::current(),
+            location,
+            since: Instant::now(),
+            reported: false,
+        };
+        self.channel.waits.lock().unwrap().insert(id, wait);
+        WaitToken {
+            channel: self.channel.clone(),
+            id,
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let mut handles = self.channel.handles.lock().unwrap();
+        handles.remove(&self.id);
+        if handles.is_empty() {
+            CHANNELS.channels.lock().unwrap().remove(&self.channel.id);
+        }
+    }
+}
+
+/// A blocked send or receive; leaves the channel's waits when dropped
+struct WaitToken {
+    channel: Arc<ChannelState>,
+    id: u64,
+}
+
+impl Drop for WaitToken {
+    fn drop(&mut self) {
+        self.channel.waits.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// An edge from each blocked task to the single owner of the other end
+///
+/// With handles of the other end spread over several tasks, any of them
+/// could unblock the waiter, so no edge is certain and none is added.
+pub(crate) fn wait_edges() -> Vec<WaitEdge> {
+    let now = Instant::now();
+    let mut edges = Vec::new();
+    for channel in CHANNELS.snapshot() {
+        let waits: Vec<Wait> = channel.waits.lock().unwrap().values().copied().collect();
+        for wait in waits {
+            let Some(waiter) = wait.task else {
+                continue;
+            };
+            let owners = channel.owners(wait.side.other());
+            let [Some(holder)] = owners.into_iter().collect::<Vec<_>>()[..] else {
+                continue;
+            };
+            edges.push(WaitEdge {
+                waiter,
+                holder,
+                resource: channel.describe(),
+                location: wait.location,
+                waiting_for: now - wait.since,
+            });
+        }
+    }
+    edges
+}
+
+/// Report receivers blocked on channels whose senders all belong to tasks
+/// idle past their threshold, once per blocked receive
+pub(crate) fn check_stalled_receivers(now: Instant) {
+    for channel in CHANNELS.snapshot() {
+        let senders = channel.owners(Side::Sender);
+        // Closed channels wake their receivers; unknown owners prove nothing
+        if senders.is_empty() || senders.contains(&None) {
+            continue;
+        }
+        let idle_senders: Option<Vec<String>> = senders
+            .into_iter()
+            .flatten()
+            .map(|owner| {
+                let task = TASKS.get(owner)?;
+                let (idle, _) = idle::idle_for(&task, now)?;
+                let threshold = config::read(|c| c.idle_threshold_of(&task.name));
+                (idle >= threshold)
+                    .then(|| format!("{} (idle {:.1?})", describe(Some(owner)), idle))
+            })
+            .collect();
+        let Some(idle_senders) = idle_senders else {
+            continue;
+        };
+
+        let mut waits = channel.waits.lock().unwrap();
+        for wait in waits.values_mut() {
+            if wait.side != Side::Receiver || wait.reported || now - wait.since < MIN_WAIT {
+                continue;
+            }
+            wait.reported = true;
+            tracing::warn!(
+                channel.name = %channel.describe(),
+                task.id = wait.task,
+                "stalled channel: {} waits at {} on {}, whose senders are all held by idle \
+                 tasks: {}",
+                describe(wait.task),
+                wait.location,
+                channel.describe(),
+                idle_senders.join(", ")
+            );
+        }
+    }
+}
+
+/// An instrumented channel, its handles and blocked tasks
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelStats {
+    pub id: u64,
+    /// `mpsc` or `oneshot`
+    pub kind: &'static str,
+    pub name: Option<String>,
+    /// Creation site, `file:line:column`
+    pub location: String,
+    /// Owner task of each live sender, `None` if unknown
+    pub senders: Vec<Option<u64>>,
+    /// Owner task of the receiver, `None` if unknown or dropped
+    pub receiver: Option<u64>,
+    /// Tasks blocked on the channel, oldest first
+    pub waits: Vec<ChannelWait>,
+}
+
+/// A task blocked sending to or receiving from an instrumented channel
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelWait {
+    /// `send` or `recv`
+    pub op: &'static str,
+    /// Diagnostics task id, if blocked in an instrumented task
+    pub task: Option<u64>,
+    /// Call site, `file:line:column`
+    pub location: String,
+    /// How long it has been blocked, in milliseconds
+    pub waiting_ms: f64,
+}
+
+impl ChannelStats {
+    /// Every instrumented channel with a live handle, oldest first
+    pub fn current() -> Vec<Self> {
+        let now = Instant::now();
+        CHANNELS
+            .snapshot()
+            .iter()
+            .map(|channel| {
+                let handles = channel.handles.lock().unwrap();
+                let owners = |side| {
+                    handles
+                        .values()
+                        .filter(move |(s, _)| *s == side)
+                        .map(|(_, owner)| *owner)
+                };
+                Self {
+                    id: channel.id,
+                    kind: channel.kind,
+                    name: channel.name.clone(),
+                    location: channel.location.to_string(),
+                    senders: owners(Side::Sender).collect(),
+                    receiver: owners(Side::Receiver).next().flatten(),
+                    waits: channel
+                        .waits
+                        .lock()
+                        .unwrap()
+                        .values()
+                        .map(|wait| ChannelWait {
+                            op: match wait.side {
+                                Side::Sender => "send",
+                                Side::Receiver => "recv",
+                            },
+                            task: wait.task,
+                            location: wait.location.to_string(),
+                            waiting_ms: (now - wait.since).as_secs_f64() * 1000.0,
+                        })
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct ChannelsReport {
+    channels: Vec<ChannelStats>,
+}
+
+/// `GET /debug/async/channels`
+pub(crate) fn handle_channels() -> Response<Body> {
+    json_response(&ChannelsReport {
+        channels: ChannelStats::current(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::pin::pin;
+
+    use super::*;
+    use crate::diagnostics::instrument;
+    use crate::diagnostics::task::tests::poll_once;
+
+    fn stats(name: &str) -> ChannelStats {
+        ChannelStats::current()
+            .into_iter()
+            .find(|stats| stats.name.as_deref() == Some(name))
+            .unwrap()
+    }
+
+    fn edges_of(waiter: u64) -> Vec<u64> {
+        wait_edges()
+            .into_iter()
+            .filter(|edge| edge.waiter == waiter)
+            .map(|edge| edge.holder)
+            .collect()
+    }
+
+    #[test]
+    fn claimed_sender_is_waited_for_by_the_receiver() {
+        let (sender, receiver) = oneshot::named::<()>("channel-test/reply");
+        assert_eq!(stats("channel-test/reply").senders, [None]);
+
+        let mut owner = pin!(instrument("channel-test/owner", async {
+            sender.claim();
+            pending::<()>().await;
+        }));
+        let mut waiter = pin!(instrument("channel-test/waiter", receiver));
+        assert!(poll_once(owner.as_mut()).is_pending());
+        assert!(poll_once(waiter.as_mut()).is_pending());
+        let (owner, waiter) = (owner.task().id, waiter.task().id);
+
+        let stats = stats("channel-test/reply");
+        assert_eq!(stats.senders, [Some(owner)]);
+        assert_eq!(stats.receiver, Some(waiter));
+        assert_eq!(stats.waits.len(), 1);
+        assert_eq!(stats.waits[0].op, "recv");
+        assert_eq!(edges_of(waiter), [owner]);
+    }
+
+    #[test]
+    fn senders_spread_over_tasks_are_not_waited_for() {
+        let (sender, mut receiver) = mpsc::named::<()>("channel-test/spread", 1);
+        let mut first = pin!(instrument("channel-test/first", async {
+            sender.claim();
+            let _clone = sender.clone();
+            pending::<()>().await;
+        }));
+        let mut second = pin!(instrument("channel-test/second", async {
+            let _clone = sender.clone();
+            pending::<()>().await;
+        }));
+        let mut waiter = pin!(instrument("channel-test/waiter", async {
+            receiver.recv().await;
+        }));
+        assert!(poll_once(first.as_mut()).is_pending());
+        assert!(poll_once(second.as_mut()).is_pending());
+        assert!(poll_once(waiter.as_mut()).is_pending());
+        let (first, second) = (first.task().id, second.task().id);
+
+        let mut senders = stats("channel-test/spread").senders;
+        senders.sort();
+        assert_eq!(senders, [Some(first), Some(first), Some(second)]);
+        assert!(edges_of(waiter.task().id).is_empty());
+    }
+}
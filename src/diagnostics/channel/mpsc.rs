@@ -0,0 +1,128 @@
+//! Instrumented bounded `tokio::sync::mpsc` channel
+
+use std::future::Future;
+use std::panic::Location;
+
+use tokio::sync::mpsc::error::{SendError, TryRecvError, TrySendError};
+
+use super::{open, Handle};
+
+/// A bounded channel reported under its creation site
+#[track_caller]
+pub fn channel<T>(buffer: usize) -> (Sender<T>, Receiver<T>) {
+    wrap(buffer, None)
+}
+
+/// A bounded channel reported as `name`
+#[track_caller]
+pub fn named<T>(name: impl Into<String>, buffer: usize) -> (Sender<T>, Receiver<T>) {
+    wrap(buffer, Some(name.into()))
+}
+
+#[track_caller]
+fn wrap<T>(buffer: usize, name: Option<String>) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::mpsc::channel(buffer);
+    let (sender_handle, receiver_handle) = open("mpsc", name, Location::caller());
+    (
+        Sender {
+            inner: sender,
+            handle: sender_handle,
+        },
+        Receiver {
+            inner: receiver,
+            handle: receiver_handle,
+        },
+    )
+}
+
+/// Sending half of an instrumented [`channel`]
+pub struct Sender<T> {
+    inner: tokio::sync::mpsc::Sender<T>,
+    handle: Handle,
+}
+
+impl<T> Sender<T> {
+    /// Send `value`, waiting for capacity if the channel is full
+    #[track_caller]
+    pub fn send(&self, value: T) -> impl Future<Output = Result<(), SendError<T>>> + '_ {
+        let location = Location::caller();
+        async move {
+            self.handle.claim();
+            match self.inner.try_reserve() {
+                Ok(permit) => {
+                    permit.send(value);
+                    return Ok(());
+                }
+                Err(TrySendError::Closed(())) => return Err(SendError(value)),
+                Err(TrySendError::Full(())) => {}
+            }
+            let _waiting = self.handle.wait(location);
+            self.inner.send(value).await
+        }
+    }
+
+    /// Send `value` if the channel has capacity
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.handle.claim();
+        self.inner.try_send(value)
+    }
+
+    /// Whether the receiver was dropped or closed
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    /// Make the instrumented task running this the owner of the sender
+    pub fn claim(&self) {
+        self.handle.claim();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            handle: self.handle.duplicate(),
+        }
+    }
+}
+
+/// Receiving half of an instrumented [`channel`]
+pub struct Receiver<T> {
+    inner: tokio::sync::mpsc::Receiver<T>,
+    handle: Handle,
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next value, `None` once every sender is gone
+    #[track_caller]
+    pub fn recv(&mut self) -> impl Future<Output = Option<T>> + '_ {
+        let location = Location::caller();
+        async move {
+            self.handle.claim();
+            match self.inner.try_recv() {
+                Ok(value) => return Some(value),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            let _waiting = self.handle.wait(location);
+            self.inner.recv().await
+        }
+    }
+
+    /// Receive a value if one is ready
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.handle.claim();
+        self.inner.try_recv()
+    }
+
+    /// Stop accepting values; those already sent can still be received
+    pub fn close(&mut self) {
+        self.inner.close();
+    }
+
+    /// Make the instrumented task running this the owner of the receiver
+    pub fn claim(&self) {
+        self.handle.claim();
+    }
+}
@@ -0,0 +1,107 @@
+//! Instrumented `tokio::sync::oneshot` channel
+
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::sync::oneshot::error::{RecvError, TryRecvError};
+
+use super::{open, Handle, WaitToken};
+
+/// A oneshot channel reported under its creation site
+#[track_caller]
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    wrap(None)
+}
+
+/// A oneshot channel reported as `name`
+#[track_caller]
+pub fn named<T>(name: impl Into<String>) -> (Sender<T>, Receiver<T>) {
+    wrap(Some(name.into()))
+}
+
+#[track_caller]
+fn wrap<T>(name: Option<String>) -> (Sender<T>, Receiver<T>) {
+    let location = Location::caller();
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    let (sender_handle, receiver_handle) = open("oneshot", name, location);
+    (
+        Sender {
+            inner: sender,
+            handle: sender_handle,
+        },
+        Receiver {
+            inner: receiver,
+            handle: receiver_handle,
+            location,
+            waiting: None,
+        },
+    )
+}
+
+/// Sending half of an instrumented oneshot [`channel`]
+pub struct Sender<T> {
+    inner: tokio::sync::oneshot::Sender<T>,
+    handle: Handle,
+}
+
+impl<T> Sender<T> {
+    /// Send `value`, giving it back if the receiver is gone
+    pub fn send(self, value: T) -> Result<(), T> {
+        self.inner.send(value)
+    }
+
+    /// Whether the receiver was dropped or closed
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    /// Make the instrumented task running this the owner of the sender
+    pub fn claim(&self) {
+        self.handle.claim();
+    }
+}
+
+/// Receiving half of an instrumented oneshot [`channel`], awaited for the
+/// value
+///
+/// The wait is reported at the channel's creation site: unlike a method
+/// call, `.await` has no caller location.
+pub struct Receiver<T> {
+    inner: tokio::sync::oneshot::Receiver<T>,
+    handle: Handle,
+    location: &'static Location<'static>,
+    /// Set while the receiver is pending
+    waiting: Option<WaitToken>,
+}
+
+impl<T> Receiver<T> {
+    /// Take the value if it was sent
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.handle.claim();
+        self.inner.try_recv()
+    }
+
+    /// Make the instrumented task running this the owner of the receiver
+    pub fn claim(&self) {
+        self.handle.claim();
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll(cx);
+        match result {
+            Poll::Pending if this.waiting.is_none() => {
+                this.waiting = Some(this.handle.wait(this.location));
+            }
+            Poll::Pending => {}
+            Poll::Ready(_) => this.waiting = None,
+        }
+        result
+    }
+}
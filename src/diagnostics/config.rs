@@ -0,0 +1,202 @@
+//! Process-wide diagnostics settings
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use super::OversizedFuture;
+
+/// Settings read by every instrumented task
+static CONFIG: RwLock<DiagnosticsConfig> = RwLock::new(DiagnosticsConfig::new());
+
+/// Thresholds of the async diagnostics
+///
+/// Takes effect for every instrumented task once [`install`](Self::install)ed;
+/// until then the defaults apply.
+#[derive(Debug, Clone)]
+pub struct DiagnosticsConfig {
+    pub(crate) long_poll_threshold: Duration,
+    pub(crate) long_poll_stacks: bool,
+    pub(crate) spawn_backtraces: bool,
+    pub(crate) self_wake_threshold: f64,
+    pub(crate) lost_waker_checks: bool,
+    pub(crate) idle_threshold: Duration,
+    pub(crate) idle_thresholds: BTreeMap<String, Duration>,
+    pub(crate) lock_hold_threshold: Duration,
+    pub(crate) future_size_threshold: usize,
+    pub(crate) oversized_futures: OversizedFuture,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiagnosticsConfig {
+    const fn new() -> Self {
+        Self {
+            long_poll_threshold: Duration::from_millis(100),
+            long_poll_stacks: true,
+            spawn_backtraces: false,
+            self_wake_threshold: 50.0,
+            lost_waker_checks: cfg!(debug_assertions),
+            idle_threshold: Duration::from_secs(30),
+            idle_thresholds: BTreeMap::new(),
+            lock_hold_threshold: Duration::from_secs(1),
+            future_size_threshold: 1024,
+            oversized_futures: OversizedFuture::Warn,
+        }
+    }
+
+    /// The settings currently in effect
+    pub fn current() -> Self {
+        CONFIG.read().unwrap().clone()
+    }
+
+    /// Report polls that keep their worker busy longer than this (default: 100ms)
+    pub fn long_poll_threshold(mut self, threshold: Duration) -> Self {
+        self.long_poll_threshold = threshold;
+        self
+    }
+
+    /// Sample the stack of the worker stuck in a long poll, to attach to
+    /// its report (default: true)
+    ///
+    /// The monitor interrupts the worker with `SIGURG` once the poll has
+    /// been running past the threshold, so polls ending before the monitor
+    /// looks, at most half a threshold later, are reported without one.
+    /// Unix only; applications handling `SIGURG` themselves are left alone.
+    pub fn long_poll_stacks(mut self, enabled: bool) -> Self {
+        self.long_poll_stacks = enabled;
+        self
+    }
+
+    /// Report tasks with more than this percentage of their wakes coming
+    /// from their own poll (default: 50)
+    ///
+    /// Same default as tokio-console's self-wake lint.
+    pub fn self_wake_threshold(mut self, percent: f64) -> Self {
+        self.self_wake_threshold = percent;
+        self
+    }
+
+    /// Report tasks left `Pending` with no reference to their waker
+    /// (default: in debug builds only)
+    ///
+    /// Costs a few atomic operations per poll.
+    pub fn lost_waker_checks(mut self, enabled: bool) -> Self {
+        self.lost_waker_checks = enabled;
+        self
+    }
+
+    /// Report tasks not polled for this long (default: 30s)
+    pub fn idle_threshold(mut self, threshold: Duration) -> Self {
+        self.idle_threshold = threshold;
+        self
+    }
+
+    /// Idle threshold of the tasks named `name`, overriding
+    /// [`idle_threshold`](Self::idle_threshold)
+    ///
+    /// Tasks meant to wait indefinitely, such as accept loops, can be
+    /// exempted with `Duration::MAX`.
+    pub fn idle_threshold_for(mut self, name: impl Into<String>, threshold: Duration) -> Self {
+        self.idle_thresholds.insert(name.into(), threshold);
+        self
+    }
+
+    /// Report [`Mutex`](super::Mutex) guards held across an await for
+    /// longer than this (default: 1s)
+    pub fn lock_hold_threshold(mut self, threshold: Duration) -> Self {
+        self.lock_hold_threshold = threshold;
+        self
+    }
+
+    /// Futures larger than this many bytes are oversized (default: 1024,
+    /// as tokio-console's large-future lint)
+    pub fn future_size_threshold(mut self, bytes: usize) -> Self {
+        self.future_size_threshold = bytes;
+        self
+    }
+
+    /// What [`spawn`](super::spawn) does with an oversized future
+    /// (default: [`OversizedFuture::Warn`])
+    pub fn oversized_futures(mut self, policy: OversizedFuture) -> Self {
+        self.oversized_futures = policy;
+        self
+    }
+
+    /// Capture a backtrace where each task is spawned, to attach to its
+    /// reports as `spawned from:` (default: false)
+    ///
+    /// This is the spawn site, not the blocking code of a long poll, which
+    /// [`long_poll_stacks`](Self::long_poll_stacks) samples. Costs a full
+    /// stack walk per spawn, so enable it while hunting a problem; symbols
+    /// are only resolved when a report is emitted.
+    pub fn spawn_backtraces(mut self, enabled: bool) -> Self {
+        self.spawn_backtraces = enabled;
+        self
+    }
+
+    /// Apply to all instrumented tasks, including running ones
+    pub fn install(self) {
+        *CONFIG.write().unwrap() = self;
+    }
+}
+
+impl DiagnosticsConfig {
+    pub(crate) fn idle_threshold_of(&self, name: &str) -> Duration {
+        self.idle_thresholds
+            .get(name)
+            .copied()
+            .unwrap_or(self.idle_threshold)
+    }
+}
+
+/// Read one setting without cloning the whole config
+pub(crate) fn read<T>(f: impl FnOnce(&DiagnosticsConfig) -> T) -> T {
+    f(&CONFIG.read().unwrap())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    use super::*;
+
+    /// Tests installing settings, which are process-wide, run one at a time
+    static INSTALLED: Mutex<()> = Mutex::new(());
+
+    /// Settings of one test, back to the defaults when dropped
+    pub(crate) struct TestConfig {
+        _serial: MutexGuard<'static, ()>,
+    }
+
+    impl Drop for TestConfig {
+        fn drop(&mut self) {
+            DiagnosticsConfig::default().install();
+        }
+    }
+
+    /// Install `config` for the rest of the calling test
+    pub(crate) fn install(config: DiagnosticsConfig) -> TestConfig {
+        // A test panicking on purpose poisons the lock, harmlessly
+        let serial = INSTALLED.lock().unwrap_or_else(PoisonError::into_inner);
+        config.install();
+        TestConfig { _serial: serial }
+    }
+
+    #[test]
+    fn idle_thresholds_by_name() {
+        let config = DiagnosticsConfig::default()
+            .idle_threshold(Duration::from_secs(5))
+            .idle_threshold_for("accept-loop", Duration::MAX)
+            .idle_threshold_for("worker", Duration::from_secs(1))
+            .idle_threshold_for("worker", Duration::from_secs(2));
+        assert_eq!(config.idle_threshold_of("accept-loop"), Duration::MAX);
+        assert_eq!(config.idle_threshold_of("worker"), Duration::from_secs(2));
+        assert_eq!(config.idle_threshold_of("worker/1"), Duration::from_secs(5));
+        assert_eq!(config.idle_threshold_of(""), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,96 @@
+//! Poll duration histogram with power-of-two buckets
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Bucket `i` counts durations under 2^i µs; the last one everything longer
+const BUCKETS: usize = 24;
+
+/// Poll durations of one task
+///
+/// Buckets double in width from 1µs up to ~4s, so one histogram covers
+/// both healthy microsecond polls and polls stuck in blocking code.
+#[derive(Debug, Clone, Default)]
+pub struct PollHistogram {
+    counts: [u64; BUCKETS],
+}
+
+/// One non-empty histogram bucket
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Bucket {
+    /// Exclusive upper bound in microseconds; `None` for the overflow bucket
+    pub lt_us: Option<u64>,
+    pub count: u64,
+}
+
+impl PollHistogram {
+    pub fn record(&mut self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        // Smallest i with micros < 2^i
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.counts[bucket.min(BUCKETS - 1)] += 1;
+    }
+
+    /// Polls recorded so far
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Non-empty buckets, shortest first
+    pub fn buckets(&self) -> Vec<Bucket> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| Bucket {
+                lt_us: (i < BUCKETS - 1).then(|| 1 << i),
+                count,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket_of(duration: Duration) -> Option<u64> {
+        let mut histogram = PollHistogram::default();
+        histogram.record(duration);
+        let buckets = histogram.buckets();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].count, 1);
+        buckets[0].lt_us
+    }
+
+    #[test]
+    fn record_bucket_edges() {
+        assert_eq!(bucket_of(Duration::ZERO), Some(1));
+        assert_eq!(bucket_of(Duration::from_nanos(999)), Some(1));
+        assert_eq!(bucket_of(Duration::from_micros(1)), Some(2));
+        assert_eq!(bucket_of(Duration::from_micros(1023)), Some(1024));
+        assert_eq!(bucket_of(Duration::from_micros(1024)), Some(2048));
+        assert_eq!(
+            bucket_of(Duration::from_micros((1 << 22) - 1)),
+            Some(1 << 22)
+        );
+        assert_eq!(bucket_of(Duration::from_micros(1 << 22)), None);
+        assert_eq!(bucket_of(Duration::MAX), None);
+    }
+
+    #[test]
+    fn buckets_skip_empty_ones() {
+        let mut histogram = PollHistogram::default();
+        for micros in [3, 2, 100, 5000] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let buckets: Vec<_> = histogram
+            .buckets()
+            .iter()
+            .map(|b| (b.lt_us, b.count))
+            .collect();
+        assert_eq!(buckets, [(Some(4), 2), (Some(128), 1), (Some(8192), 1)]);
+        assert_eq!(histogram.count(), 4);
+    }
+}
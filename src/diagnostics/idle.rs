@@ -0,0 +1,254 @@
+//! Hanging-task watchdog
+//!
+//! A task waiting on something that never happens uses no CPU and raises
+//! no error: it just stays idle. The monitor reports instrumented tasks
+//! idle, i.e. not polled, for longer than the threshold of their name.
+//!
+//! The task's own future cannot tell what it is waiting on, so awaits of
+//! interest are labelled with [`awaiting`](super::awaiting). When a poll
+//! returns `Pending`, the innermost labelled future that is still pending
+//! becomes what the task was last awaiting.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::Response;
+use serde::Serialize;
+
+use super::config;
+use super::task::{TaskEntry, TASKS};
+use crate::http::{json_response, Body};
+
+thread_local! {
+    /// Innermost pending labelled future of the poll in progress
+    static AWAITING: Cell<Option<AwaitPoint>> = const { Cell::new(None) };
+}
+
+/// A labelled await: what it waits for and where
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AwaitPoint {
+    what: &'static str,
+    location: &'static Location<'static>,
+}
+
+/// Idle tracking of one task
+#[derive(Default)]
+pub(crate) struct IdleWatch {
+    /// End of the last poll; the spawn time counts until the first one
+    last_ended: Option<Instant>,
+    /// What the last poll was left waiting on, if labelled
+    awaiting: Option<AwaitPoint>,
+    /// The current idle stretch was reported already
+    reported: bool,
+}
+
+/// Start collecting the labels of this poll
+///
+/// Returns the labels of the enclosing poll, for instrumented futures
+/// nested in instrumented tasks, to give back to [`poll_ended`].
+pub(crate) fn poll_started() -> Option<AwaitPoint> {
+    AWAITING.take()
+}
+
+/// Record when the poll ended and, if pending, what it waits on
+pub(crate) fn poll_ended(task: &TaskEntry, pending: bool, outer: Option<AwaitPoint>) {
+    let awaiting = AWAITING.replace(outer);
+    let mut idle = task.idle.lock().unwrap();
+    idle.last_ended = Some(Instant::now());
+    idle.awaiting = if pending { awaiting } else { None };
+    idle.reported = false;
+}
+
+/// A future labelled with what it waits for
+///
+/// Created by [`awaiting`](super::awaiting).
+pub struct Awaiting<F> {
+    future: F,
+    point: AwaitPoint,
+}
+
+impl<F> Awaiting<F> {
+    pub(crate) fn new(future: F, what: &'static str, location: &'static Location<'static>) -> Self {
+        Self {
+            future,
+            point: AwaitPoint { what, location },
+        }
+    }
+}
+
+impl<F: Future> Future for Awaiting<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // SAFETY: `future` is structurally pinned, never moved out of `self`
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        let result = future.poll(cx);
+        // Labelled futures nested in this one returned first and win
+        if result.is_pending() && AWAITING.get().is_none() {
+            AWAITING.set(Some(this.point));
+        }
+        result
+    }
+}
+
+/// An instrumented task idle past its threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskIdleStats {
+    /// Diagnostics task id (not the tokio task id)
+    pub id: u64,
+    pub name: String,
+    /// Spawn location, `file:line:column`
+    pub location: String,
+    /// Time since the task was last polled, or spawned, in milliseconds
+    pub idle_ms: f64,
+    /// Idle threshold of the task's name, in milliseconds
+    pub threshold_ms: f64,
+    /// Label of what the task was last awaiting, if known
+    pub awaiting: Option<String>,
+    /// Where that await is, `file:line:column`
+    pub awaiting_location: Option<String>,
+}
+
+impl TaskIdleStats {
+    /// Every live instrumented task idle past its threshold, longest idle first
+    pub fn current() -> Vec<Self> {
+        let now = Instant::now();
+        let mut tasks: Vec<_> = TASKS
+            .snapshot()
+            .iter()
+            .filter_map(|task| {
+                let (idle, awaiting) = idle_for(task, now)?;
+                let threshold = config::read(|c| c.idle_threshold_of(&task.name));
+                (idle >= threshold).then(|| Self {
+                    id: task.id,
+                    name: task.name.clone(),
+                    location: task.location.to_string(),
+                    idle_ms: millis(idle),
+                    threshold_ms: millis(threshold),
+                    awaiting: awaiting.map(|point| point.what.to_string()),
+                    awaiting_location: awaiting.map(|point| point.location.to_string()),
+                })
+            })
+            .collect();
+        tasks.sort_by(|a, b| b.idle_ms.total_cmp(&a.idle_ms));
+        tasks
+    }
+}
+
+/// How long `task` has been idle and what it awaits, `None` while polled
+pub(crate) fn idle_for(task: &TaskEntry, now: Instant) -> Option<(Duration, Option<AwaitPoint>)> {
+    if task.in_poll() {
+        return None;
+    }
+    let idle = task.idle.lock().unwrap();
+    let since = idle.last_ended.unwrap_or(task.spawned);
+    Some((now.saturating_duration_since(since), idle.awaiting))
+}
+
+/// Report each idle stretch past the threshold once
+pub(crate) fn check_idle_tasks(now: Instant) {
+    for task in TASKS.snapshot() {
+        let Some((idle, awaiting)) = idle_for(&task, now) else {
+            continue;
+        };
+        if idle < config::read(|c| c.idle_threshold_of(&task.name)) {
+            continue;
+        }
+        if std::mem::replace(&mut task.idle.lock().unwrap().reported, true) {
+            continue;
+        }
+        let what = match awaiting {
+            Some(point) => format!("awaiting {} at {}", point.what, point.location),
+            None => "awaiting an unlabelled future".to_string(),
+        };
+        tracing::warn!(
+            task.id = task.id,
+            task.name = %task.name,
+            task.location = %task.location,
+            task.idle_ms = millis(idle),
+            task.awaiting = awaiting.map(|point| point.what),
+            "hanging task: task {} ({}) has been idle for {:.1?}, {}{}",
+            task.name,
+            task.location,
+            idle,
+            what,
+            task.spawn_backtrace_text()
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct IdleReport {
+    tasks: Vec<TaskIdleStats>,
+}
+
+/// `GET /debug/async/idle`
+pub(crate) fn handle_idle() -> Response<Body> {
+    json_response(&IdleReport {
+        tasks: TaskIdleStats::current(),
+    })
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::pin::pin;
+
+    use super::*;
+    use crate::diagnostics::config::{self, DiagnosticsConfig};
+    use crate::diagnostics::task::tests::poll_once;
+    use crate::diagnostics::{awaiting, instrument};
+
+    fn idle_stats(name: &str) -> Option<TaskIdleStats> {
+        TaskIdleStats::current()
+            .into_iter()
+            .find(|stats| stats.name == name)
+    }
+
+    #[test]
+    fn idle_tasks_are_judged_by_the_threshold_of_their_name() {
+        let _config = config::tests::install(
+            DiagnosticsConfig::default()
+                .idle_threshold(Duration::MAX)
+                .idle_threshold_for("idle-test/stuck", Duration::ZERO),
+        );
+        let mut stuck = pin!(instrument(
+            "idle-test/stuck",
+            awaiting("the reply", pending::<()>())
+        ));
+        let mut waiting = pin!(instrument("idle-test/waiting", pending::<()>()));
+        assert!(poll_once(stuck.as_mut()).is_pending());
+        assert!(poll_once(waiting.as_mut()).is_pending());
+
+        let stats = idle_stats("idle-test/stuck").unwrap();
+        assert_eq!(stats.threshold_ms, 0.0);
+        assert_eq!(stats.awaiting.as_deref(), Some("the reply"));
+        assert!(stats.awaiting_location.unwrap().contains("idle.rs"));
+        assert!(idle_stats("idle-test/waiting").is_none());
+
+        check_idle_tasks(Instant::now());
+        assert!(stuck.task().idle.lock().unwrap().reported);
+        assert!(!waiting.task().idle.lock().unwrap().reported);
+    }
+
+    #[test]
+    fn innermost_pending_label_wins() {
+        let mut task = pin!(instrument(
+            "idle-test/nested",
+            awaiting("the batch", awaiting("one item", pending::<()>()))
+        ));
+        assert!(poll_once(task.as_mut()).is_pending());
+        let (_, point) = idle_for(task.task(), Instant::now()).unwrap();
+        assert_eq!(point.map(|point| point.what), Some("one item"));
+    }
+}
@@ -0,0 +1,216 @@
+//! Lost-waker detection
+//!
+//! A future returning `Pending` must keep the waker of its context, or a
+//! clone of it, somewhere that will call it: otherwise nothing ever polls
+//! the task again and it hangs without using any CPU. The waker handed to
+//! an instrumented future is an `Arc` kept by the wrapper too, so its
+//! strong count tells how many references the rest of the program holds.
+//!
+//! With no reference left and no wake since the last poll, the task cannot
+//! be woken anymore. That is checked when a poll returns `Pending`, which
+//! catches futures dropping the waker right away, and by the monitor, which
+//! catches the last clone being dropped later, e.g. with the object that
+//! stored it.
+
+use std::sync::atomic::{fence, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use super::config;
+use super::task::{TaskEntry, TASKS};
+use super::wakes::CountingWaker;
+
+/// Waker references of one task between polls
+#[derive(Default)]
+pub(crate) struct WakerWatch {
+    /// The waker last handed to the future, owned by its wrapper
+    current: Weak<CountingWaker>,
+    /// End of the last poll, if it returned `Pending`
+    pending_since: Option<Instant>,
+    /// Wakes counted when that poll returned
+    wakes_at_pending: u64,
+    /// Reported already; only once per task, it is hung for good
+    reported: bool,
+}
+
+/// Forget the previous pending state, a poll is starting
+pub(crate) fn poll_started(task: &TaskEntry, waker: &Arc<CountingWaker>) {
+    let mut watch = task.waker.lock().unwrap();
+    watch.current = Arc::downgrade(waker);
+    watch.pending_since = None;
+}
+
+/// Check a poll that just returned `Pending`
+///
+/// `wakes_before` is the wake count when the poll started: a wake during
+/// the poll, such as a self-wake, means the task is polled again anyway.
+/// The waker passed to the future must already be dropped, leaving only
+/// the wrapper's reference.
+pub(crate) fn poll_pending(task: &TaskEntry, waker: &Arc<CountingWaker>, wakes_before: u64) {
+    if !config::read(|c| c.lost_waker_checks) {
+        return;
+    }
+    let refs = outstanding(Arc::strong_count(waker));
+    let wakes = wakes_after(task);
+    let dropped = refs == 0 && wakes == wakes_before;
+    let report = {
+        let mut watch = task.waker.lock().unwrap();
+        watch.pending_since = Some(Instant::now());
+        watch.wakes_at_pending = wakes;
+        dropped && !std::mem::replace(&mut watch.reported, true)
+    };
+    if report {
+        report_lost_waker(task, None);
+    }
+}
+
+/// Report the pending tasks whose last waker reference is gone
+pub(crate) fn check_lost_wakers(now: Instant) {
+    if !config::read(|c| c.lost_waker_checks) {
+        return;
+    }
+    for task in TASKS.snapshot() {
+        let pending_for = {
+            let mut watch = task.waker.lock().unwrap();
+            let Some(since) = watch.pending_since else {
+                continue;
+            };
+            if watch.reported {
+                continue;
+            }
+            let refs = outstanding(watch.current.strong_count());
+            if refs > 0 || wakes_after(&task) != watch.wakes_at_pending {
+                continue;
+            }
+            watch.reported = true;
+            now - since
+        };
+        report_lost_waker(&task, Some(pending_for));
+    }
+}
+
+/// References held outside the wrapper
+fn outstanding(strong_count: usize) -> usize {
+    strong_count.saturating_sub(1)
+}
+
+/// Wake count of `task`, read after its waker references were counted
+///
+/// A wake consuming the last reference counts itself before dropping it:
+/// the fence makes that count visible once the drop is, so such a task is
+/// not mistaken for a lost one.
+fn wakes_after(task: &TaskEntry) -> u64 {
+    fence(Ordering::Acquire);
+    task.wakes.total()
+}
+
+fn report_lost_waker(task: &TaskEntry, pending_for: Option<Duration>) {
+    let what = match pending_for {
+        None => "returned Pending without keeping or calling its waker".to_string(),
+        Some(duration) => format!(
+            "has been Pending for {:.1?} and the last reference to its waker was dropped",
+            duration
+        ),
+    };
+    tracing::warn!(
+        task.id = task.id,
+        task.name = %task.name,
+        task.location = %task.location,
+        "lost waker: task {} ({}) {}, nothing can wake it anymore{}",
+        task.name,
+        task.location,
+        what,
+        task.spawn_backtrace_text()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::{pin, Pin};
+    use std::sync::Mutex;
+    use std::task::{Context, Poll, Waker};
+
+    use super::*;
+    use crate::diagnostics::config::{self, DiagnosticsConfig};
+    use crate::diagnostics::instrument;
+    use crate::diagnostics::task::tests::poll_once;
+
+    /// Pending forever without keeping its waker
+    struct NeverWakes;
+
+    impl Future for NeverWakes {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+            Poll::Pending
+        }
+    }
+
+    /// Pending while a waker is stored in `slot`
+    struct Stores<'a> {
+        slot: &'a Mutex<Option<Waker>>,
+    }
+
+    impl Future for Stores<'_> {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            *self.slot.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn reported(task: &TaskEntry) -> bool {
+        task.waker.lock().unwrap().reported
+    }
+
+    #[test]
+    fn future_dropping_its_waker_is_reported() {
+        let _config = config::tests::install(DiagnosticsConfig::default().lost_waker_checks(true));
+        let mut task = pin!(instrument("lost-waker-test/never-wakes", NeverWakes));
+        assert!(poll_once(task.as_mut()).is_pending());
+        assert!(reported(task.task()));
+    }
+
+    #[test]
+    fn future_keeping_its_waker_is_not_reported() {
+        let _config = config::tests::install(DiagnosticsConfig::default().lost_waker_checks(true));
+        let slot = Mutex::new(None);
+        let mut task = pin!(instrument("lost-waker-test/stores", Stores { slot: &slot }));
+        assert!(poll_once(task.as_mut()).is_pending());
+        check_lost_wakers(Instant::now());
+        assert!(!reported(task.task()));
+
+        // Woken, then polled again: still fine
+        slot.lock().unwrap().take().unwrap().wake();
+        assert!(poll_once(task.as_mut()).is_pending());
+        check_lost_wakers(Instant::now());
+        assert!(!reported(task.task()));
+    }
+
+    #[test]
+    fn waker_dropped_after_the_poll_is_reported_by_the_monitor() {
+        let _config = config::tests::install(DiagnosticsConfig::default().lost_waker_checks(true));
+        let slot = Mutex::new(None);
+        let mut task = pin!(instrument(
+            "lost-waker-test/dropped-later",
+            Stores { slot: &slot }
+        ));
+        assert!(poll_once(task.as_mut()).is_pending());
+        assert!(!reported(task.task()));
+
+        drop(slot.lock().unwrap().take());
+        check_lost_wakers(Instant::now());
+        assert!(reported(task.task()));
+    }
+
+    #[test]
+    fn checks_can_be_disabled() {
+        let _config = config::tests::install(DiagnosticsConfig::default().lost_waker_checks(false));
+        let mut task = pin!(instrument("lost-waker-test/unchecked", NeverWakes));
+        assert!(poll_once(task.as_mut()).is_pending());
+        check_lost_wakers(Instant::now());
+        assert!(!reported(task.task()));
+    }
+}
@@ -0,0 +1,166 @@
+//! In-process async diagnostics, without tokio-console
+//!
+//! tokio-console needs a second process and someone watching it. These
+//! helpers instrument the futures themselves and report problems through
+//! `tracing` warnings, so they show up in the logs of an ordinary run, and
+//! through [`ProfilingService`] routes.
+//!
+//! Spawn tasks with [`spawn`] instead of `tokio::spawn`, or wrap a future
+//! with [`instrument`], to get:
+//!
+//! - long polls: a `poll` call keeping its worker busy past a threshold,
+//!   reported when it returns and, if it does not, while it is still stuck,
+//!   with the stack of the worker showing where it blocks (Unix)
+//! - poll duration histograms per task
+//! - large futures: the size of every spawned future, by spawn location,
+//!   with a warning, a panic or automatic boxing above a threshold
+//! - self-wakes: tasks whose wakes mostly come from their own poll, as
+//!   with a future calling `cx.waker().wake_by_ref()` before returning
+//!   `Pending`
+//! - lost wakers: tasks left `Pending` with no reference to their waker,
+//!   which nothing can wake anymore (debug builds by default)
+//! - hanging tasks: tasks not polled for longer than the idle threshold of
+//!   their name, with what they were last [`awaiting`]
+//!
+//! Locking a [`Mutex`] from this module instead of tokio's also reports:
+//!
+//! - guards held across an await for longer than a threshold, with the
+//!   holder task and where it locked the mutex
+//! - deadlocks: instrumented tasks waiting for each other's locks in a cycle
+//!
+//! The [`mpsc`] and [`oneshot`] channels of this module add theirs to the
+//! same deadlock detection, e.g. two tasks each receiving from a channel
+//! whose sender the other holds, and report receivers waiting on channels
+//! whose senders are all held by hanging tasks.
+//!
+//! Future sizes can also be bounded before anything runs:
+//! `#[`[`max_future_size`]`(4096)]` on an async fn generates a test failing
+//! with the actual size once the function's future grows past 4 KB.
+//!
+//! Routes:
+//! - `GET /debug/async/polls` - poll statistics of every live instrumented
+//!   task (JSON), see [`TaskPollStats`]
+//! - `GET /debug/async/idle` - instrumented tasks idle past their threshold
+//!   (JSON), see [`TaskIdleStats`]
+//! - `GET /debug/async/locks` - holders and waiters of every instrumented
+//!   mutex (JSON), see [`LockStats`]
+//! - `GET /debug/async/channels` - handle owners and blocked tasks of every
+//!   instrumented channel (JSON), see [`ChannelStats`]
+//! - `GET /debug/async/sizes` - spawned future sizes by spawn location
+//!   (JSON), see [`FutureSizeStats`]
+//! - `GET /metrics` - includes the wake totals by spawn location, see
+//!   [`TaskWakeStats`]
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use tokio_console_demo::diagnostics::{self, DiagnosticsConfig};
+//!
+//! # async fn run() {
+//! DiagnosticsConfig::default()
+//!     .long_poll_threshold(Duration::from_millis(50))
+//!     .install();
+//!
+//! diagnostics::spawn("worker", async {
+//!     // Reported: blocks the worker for a second
+//!     std::thread::sleep(Duration::from_secs(1));
+//! });
+//! # }
+//! ```
+//!
+//! [`ProfilingService`]: crate::profiling::ProfilingService
+
+mod channel;
+mod config;
+mod histogram;
+mod idle;
+mod lost_waker;
+mod monitor;
+mod mutex;
+mod polls;
+mod size;
+mod stack;
+mod task;
+mod wait_for;
+mod wakes;
+
+pub use channel::{mpsc, oneshot, ChannelStats, ChannelWait};
+pub use config::DiagnosticsConfig;
+pub use histogram::{Bucket, PollHistogram};
+pub use idle::{Awaiting, TaskIdleStats};
+pub use mutex::{LockStats, LockUse, Mutex, MutexGuard};
+pub use polls::TaskPollStats;
+pub use size::{FutureSizeStats, OversizedFuture};
+pub use task::Instrumented;
+pub use wakes::TaskWakeStats;
+
+pub use tokio_console_demo_macros::max_future_size;
+
+pub(crate) use channel::handle_channels;
+pub(crate) use idle::handle_idle;
+pub(crate) use mutex::handle_locks;
+pub(crate) use polls::handle_polls;
+pub(crate) use size::handle_sizes;
+
+use std::future::Future;
+use std::panic::Location;
+
+use tokio::task::JoinHandle;
+
+/// Wrap `future` so its polls are measured and reported under `name`
+///
+/// The caller's location is recorded as the task's spawn location.
+#[track_caller]
+pub fn instrument<F: Future>(name: impl Into<String>, future: F) -> Instrumented<F> {
+    Instrumented::new(future, name.into(), Location::caller())
+}
+
+/// Label `future` as what the task awaits while it is pending
+///
+/// Shown by the hanging-task reports when the task stays idle on it. The
+/// caller's location is recorded with the label.
+#[track_caller]
+pub fn awaiting<F: Future>(what: &'static str, future: F) -> Awaiting<F> {
+    Awaiting::new(future, what, Location::caller())
+}
+
+/// `tokio::spawn` an instrumented task
+///
+/// The size of `future` is recorded under the caller's location, and a
+/// future above the size threshold is handled as configured with
+/// [`DiagnosticsConfig::oversized_futures`]. With `--cfg tokio_unstable`
+/// the tokio task gets the same name, so tokio-console shows it too.
+#[track_caller]
+pub fn spawn<F>(name: impl Into<String>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let location = Location::caller();
+    let name = name.into();
+    if size::record(&name, location, std::mem::size_of_val(&future)) {
+        spawn_instrumented(name, location, Box::pin(future))
+    } else {
+        spawn_instrumented(name, location, future)
+    }
+}
+
+#[track_caller]
+fn spawn_instrumented<F>(
+    name: String,
+    location: &'static Location<'static>,
+    future: F,
+) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(tokio_unstable)]
+    {
+        tokio::task::Builder::new()
+            .name(&name)
+            .spawn(Instrumented::new(future, name.clone(), location))
+            .expect("failed to spawn task")
+    }
+    #[cfg(not(tokio_unstable))]
+    tokio::spawn(Instrumented::new(future, name, location))
+}
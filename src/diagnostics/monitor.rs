@@ -0,0 +1,81 @@
+//! Background thread watching the instrumented tasks
+//!
+//! A poll that never returns is never reported by the task itself, so a
+//! plain thread (not a tokio task, which a blocked runtime would starve)
+//! looks at the polls in progress, and samples the stack of the workers
+//! stuck in one. It also judges the wake counts, which are only meaningful
+//! once a task has been woken a few times, the waker references of the
+//! pending tasks and how long they have been idle, and the instrumented
+//! resources they hold or wait for.
+
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use super::task::TASKS;
+use super::{channel, config, idle, lost_waker, mutex, stack, wait_for, wakes};
+
+/// Check at least this often, however high the thresholds
+const MAX_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Check at most this often, however low the thresholds
+const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Start the monitor thread if it is not running yet
+pub(crate) fn ensure_started() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        std::thread::Builder::new()
+            .name("async-diagnostics".to_string())
+            .spawn(run)
+            .expect("failed to spawn the diagnostics monitor thread");
+    });
+}
+
+fn run() {
+    loop {
+        let threshold = config::read(|c| c.long_poll_threshold);
+        std::thread::sleep((threshold / 2).clamp(MIN_INTERVAL, MAX_INTERVAL));
+        let now = Instant::now();
+        check_long_polls(threshold, now);
+        wakes::check_self_wakes();
+        lost_waker::check_lost_wakers(now);
+        idle::check_idle_tasks(now);
+        mutex::check_long_holds(now);
+        channel::check_stalled_receivers(now);
+        wait_for::check_cycles();
+    }
+}
+
+/// Report each poll running past the threshold once, while it is stuck,
+/// with the stack of its worker
+fn check_long_polls(threshold: Duration, now: Instant) {
+    let sample_stacks = config::read(|c| c.long_poll_stacks);
+    for task in TASKS.snapshot() {
+        let (started, thread) = {
+            let mut polls = task.polls.lock().unwrap();
+            match (polls.current, polls.thread) {
+                (Some(started), Some(thread))
+                    if !polls.current_reported && now - started >= threshold =>
+                {
+                    polls.current_reported = true;
+                    (started, thread)
+                }
+                _ => continue,
+            }
+        };
+
+        let stack = sample_stacks.then(|| stack::sample(thread)).flatten();
+        // Only keep it if it is still the same poll, not whatever the
+        // worker went on to run after it
+        let stack = {
+            let mut polls = task.polls.lock().unwrap();
+            if polls.current == Some(started) {
+                polls.current_stack.clone_from(&stack);
+                stack
+            } else {
+                None
+            }
+        };
+        task.report_long_poll(now - started, true, stack.as_deref());
+    }
+}
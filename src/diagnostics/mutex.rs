@@ -0,0 +1,429 @@
+//! Instrumented async mutex
+//!
+//! [`Mutex`] wraps `tokio::sync::Mutex` and records who holds it, where it
+//! was locked and since when, and who is waiting for it. The monitor uses
+//! that to report guards kept across an `.await` for too long, and feeds
+//! the waiters into the [wait-for graph](super::wait_for) to find deadlocks.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::Response;
+use serde::Serialize;
+use tokio::sync::TryLockError;
+
+use super::task::{describe, TASKS};
+use super::wait_for::WaitEdge;
+use super::{config, monitor, wakes};
+use crate::http::{json_response, Body};
+
+/// Every instrumented mutex that has not been dropped
+static LOCKS: LockTable = LockTable::new();
+
+struct LockTable {
+    next_id: AtomicU64,
+    locks: std::sync::Mutex<BTreeMap<u64, Arc<LockState>>>,
+}
+
+impl LockTable {
+    const fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            locks: std::sync::Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn register(
+        &self,
+        name: Option<String>,
+        location: &'static Location<'static>,
+    ) -> Arc<LockState> {
+        let lock = Arc::new(LockState {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            name,
+            location,
+            holder: std::sync::Mutex::new(None),
+            waiters: std::sync::Mutex::new(BTreeMap::new()),
+            next_wait: AtomicU64::new(0),
+        });
+        self.locks.lock().unwrap().insert(lock.id, lock.clone());
+        monitor::ensure_started();
+        lock
+    }
+
+    fn snapshot(&self) -> Vec<Arc<LockState>> {
+        self.locks.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Who holds or waits for a mutex, from where and since when
+#[derive(Debug, Clone, Copy)]
+struct Use {
+    /// Instrumented task, `None` outside of one
+    task: Option<u64>,
+    location: &'static Location<'static>,
+    since: Instant,
+}
+
+impl Use {
+    fn new(location: &'static Location<'static>) -> Self {
+        Self {
+            task: wakes::current(),
+            location,
+            since: Instant::now(),
+        }
+    }
+}
+
+struct LockState {
+    id: u64,
+    name: Option<String>,
+    /// Where the mutex was created
+    location: &'static Location<'static>,
+    /// The current guard, and whether its hold was reported already
+    holder: std::sync::Mutex<Option<(Use, bool)>>,
+    waiters: std::sync::Mutex<BTreeMap<u64, Use>>,
+    next_wait: AtomicU64,
+}
+
+impl LockState {
+    /// `mutex <name> (<location>)`, for reports
+    fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("mutex {} ({})", name, self.location),
+            None => format!("mutex {}", self.location),
+        }
+    }
+
+    fn wait(&self, location: &'static Location<'static>) -> Waiting<'_> {
+        let id = self.next_wait.fetch_add(1, Ordering::Relaxed);
+        self.waiters.lock().unwrap().insert(id, Use::new(location));
+        Waiting { lock: self, id }
+    }
+}
+
+/// A pending `lock()` call; leaves the waiters when acquired or cancelled
+struct Waiting<'a> {
+    lock: &'a LockState,
+    id: u64,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.lock.waiters.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// A `tokio::sync::Mutex` reporting long holds and deadlocks
+///
+/// Holders and waiters are attributed to the instrumented task locking it,
+/// see [`spawn`](super::spawn); only those take part in deadlock detection.
+pub struct Mutex<T> {
+    lock: Arc<LockState>,
+    inner: tokio::sync::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    /// A mutex reported under its creation site
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        Self {
+            lock: LOCKS.register(None, Location::caller()),
+            inner: tokio::sync::Mutex::new(value),
+        }
+    }
+
+    /// A mutex reported as `name`
+    #[track_caller]
+    pub fn named(name: impl Into<String>, value: T) -> Self {
+        Self {
+            lock: LOCKS.register(Some(name.into()), Location::caller()),
+            inner: tokio::sync::Mutex::new(value),
+        }
+    }
+
+    /// Lock the mutex, recording the caller as the acquisition site
+    #[track_caller]
+    pub fn lock(&self) -> impl Future<Output = MutexGuard<'_, T>> + '_ {
+        let location = Location::caller();
+        async move {
+            let guard = match self.inner.try_lock() {
+                Ok(guard) => guard,
+                Err(_) => {
+                    let _waiting = self.lock.wait(location);
+                    self.inner.lock().await
+                }
+            };
+            MutexGuard::new(guard, &self.lock, location)
+        }
+    }
+
+    /// Lock the mutex if it is free
+    #[track_caller]
+    pub fn try_lock(&self) -> Result<MutexGuard<'_, T>, TryLockError> {
+        let guard = self.inner.try_lock()?;
+        Ok(MutexGuard::new(guard, &self.lock, Location::caller()))
+    }
+}
+
+impl<T> Drop for Mutex<T> {
+    fn drop(&mut self) {
+        LOCKS.locks.lock().unwrap().remove(&self.lock.id);
+    }
+}
+
+/// Guard of an instrumented [`Mutex`]
+pub struct MutexGuard<'a, T> {
+    guard: tokio::sync::MutexGuard<'a, T>,
+    lock: &'a LockState,
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    fn new(
+        guard: tokio::sync::MutexGuard<'a, T>,
+        lock: &'a LockState,
+        location: &'static Location<'static>,
+    ) -> Self {
+        *lock.holder.lock().unwrap() = Some((Use::new(location), false));
+        Self { guard, lock }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Runs before `guard` unlocks, so no new holder is overwritten
+        *self.lock.holder.lock().unwrap() = None;
+    }
+}
+
+/// Report each guard held past the threshold while its task is suspended
+///
+/// A task inside a poll is blocking its worker rather than awaiting; the
+/// long poll report covers that, and the hold is checked again later.
+pub(crate) fn check_long_holds(now: Instant) {
+    let threshold = config::read(|c| c.lock_hold_threshold);
+    for lock in LOCKS.snapshot() {
+        let holder = {
+            let mut holder = lock.holder.lock().unwrap();
+            match holder.as_mut() {
+                Some((hold, reported)) if !*reported && now - hold.since >= threshold => {
+                    let in_poll = hold
+                        .task
+                        .and_then(|id| TASKS.get(id))
+                        .is_some_and(|task| task.in_poll());
+                    if in_poll {
+                        continue;
+                    }
+                    *reported = true;
+                    *hold
+                }
+                _ => continue,
+            }
+        };
+        let waiting = lock.waiters.lock().unwrap().len();
+        let held = now - holder.since;
+        tracing::warn!(
+            lock.name = %lock.describe(),
+            lock.held_ms = held.as_secs_f64() * 1000.0,
+            lock.waiting = waiting,
+            task.id = holder.task,
+            "long lock hold: {} locked at {} by {} has been held across an await for {:.1?}, \
+             {} task(s) waiting for it",
+            lock.describe(),
+            holder.location,
+            describe(holder.task),
+            held,
+            waiting
+        );
+    }
+}
+
+/// An edge from each instrumented waiter to the instrumented holder
+pub(crate) fn wait_edges() -> Vec<WaitEdge> {
+    let now = Instant::now();
+    let mut edges = Vec::new();
+    for lock in LOCKS.snapshot() {
+        let holder = match *lock.holder.lock().unwrap() {
+            Some((
+                Use {
+                    task: Some(task), ..
+                },
+                _,
+            )) => task,
+            _ => continue,
+        };
+        for wait in lock.waiters.lock().unwrap().values() {
+            if let Some(waiter) = wait.task {
+                edges.push(WaitEdge {
+                    waiter,
+                    holder,
+                    resource: lock.describe(),
+                    location: wait.location,
+                    waiting_for: now - wait.since,
+                });
+            }
+        }
+    }
+    edges
+}
+
+/// An instrumented mutex and who uses it
+#[derive(Debug, Clone, Serialize)]
+pub struct LockStats {
+    pub id: u64,
+    pub name: Option<String>,
+    /// Creation site, `file:line:column`
+    pub location: String,
+    pub holder: Option<LockUse>,
+    /// Oldest first
+    pub waiters: Vec<LockUse>,
+}
+
+/// A holder or waiter of an instrumented mutex
+#[derive(Debug, Clone, Serialize)]
+pub struct LockUse {
+    /// Diagnostics task id, if locked from an instrumented task
+    pub task: Option<u64>,
+    /// `lock()` call site, `file:line:column`
+    pub location: String,
+    /// How long it has been held or waited for, in milliseconds
+    pub for_ms: f64,
+}
+
+impl LockStats {
+    /// Every live instrumented mutex, oldest first
+    pub fn current() -> Vec<Self> {
+        let now = Instant::now();
+        let to_use = |u: &Use| LockUse {
+            task: u.task,
+            location: u.location.to_string(),
+            for_ms: millis(now - u.since),
+        };
+        LOCKS
+            .snapshot()
+            .iter()
+            .map(|lock| Self {
+                id: lock.id,
+                name: lock.name.clone(),
+                location: lock.location.to_string(),
+                holder: lock.holder.lock().unwrap().map(|(hold, _)| to_use(&hold)),
+                waiters: lock.waiters.lock().unwrap().values().map(to_use).collect(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct LocksReport {
+    lock_hold_threshold_ms: f64,
+    locks: Vec<LockStats>,
+}
+
+/// `GET /debug/async/locks`
+pub(crate) fn handle_locks() -> Response<Body> {
+    json_response(&LocksReport {
+        lock_hold_threshold_ms: millis(config::read(|c| c.lock_hold_threshold)),
+        locks: LockStats::current(),
+    })
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::pin::pin;
+
+    use super::*;
+    use crate::diagnostics::instrument;
+    use crate::diagnostics::task::tests::poll_once;
+
+    fn holder(mutex: &Mutex<()>) -> Option<(Option<u64>, bool)> {
+        mutex
+            .lock
+            .holder
+            .lock()
+            .unwrap()
+            .map(|(hold, reported)| (hold.task, reported))
+    }
+
+    #[test]
+    fn holds_across_awaits_are_reported_past_the_threshold() {
+        let mutex = Mutex::named("mutex-test/held", ());
+        let mut task = pin!(instrument("mutex-test/holder", async {
+            let _guard = mutex.lock().await;
+            pending::<()>().await;
+        }));
+        assert!(poll_once(task.as_mut()).is_pending());
+        let id = task.task().id;
+
+        check_long_holds(Instant::now());
+        assert_eq!(holder(&mutex), Some((Some(id), false)));
+
+        let threshold = config::read(|c| c.lock_hold_threshold);
+        check_long_holds(Instant::now() + threshold);
+        assert_eq!(holder(&mutex), Some((Some(id), true)));
+    }
+
+    #[test]
+    fn waiters_wait_for_the_holder() {
+        let mutex = Mutex::named("mutex-test/contended", ());
+        let mut holding = pin!(instrument("mutex-test/holding", async {
+            let _guard = mutex.lock().await;
+            pending::<()>().await;
+        }));
+        let mut waiting = pin!(instrument("mutex-test/waiting", async {
+            drop(mutex.lock().await);
+        }));
+        assert!(poll_once(holding.as_mut()).is_pending());
+        assert!(poll_once(waiting.as_mut()).is_pending());
+        let (holder, waiter) = (holding.task().id, waiting.task().id);
+
+        let edges: Vec<_> = wait_edges()
+            .into_iter()
+            .filter(|edge| edge.waiter == waiter)
+            .map(|edge| (edge.holder, edge.resource))
+            .collect();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].0, holder);
+        assert!(edges[0].1.starts_with("mutex mutex-test/contended ("));
+
+        let stats = LockStats::current()
+            .into_iter()
+            .find(|stats| stats.id == mutex.lock.id)
+            .unwrap();
+        assert_eq!(stats.holder.unwrap().task, Some(holder));
+        assert_eq!(stats.waiters.len(), 1);
+        assert_eq!(stats.waiters[0].task, Some(waiter));
+    }
+
+    #[test]
+    fn dropped_guards_release_the_holder() {
+        let mutex = Mutex::new(());
+        let guard = mutex.try_lock().unwrap();
+        assert_eq!(holder(&mutex), Some((None, false)));
+        drop(guard);
+        assert_eq!(holder(&mutex), None);
+    }
+}
@@ -0,0 +1,74 @@
+//! Poll statistics of the live instrumented tasks, as JSON
+
+use std::time::Instant;
+
+use hyper::Response;
+use serde::Serialize;
+
+use super::config;
+use super::histogram::Bucket;
+use super::task::TASKS;
+use crate::http::{json_response, Body};
+
+/// Poll timings of one instrumented task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskPollStats {
+    /// Diagnostics task id (not the tokio task id)
+    pub id: u64,
+    pub name: String,
+    /// Spawn location, `file:line:column`
+    pub location: String,
+    /// Time since the task was spawned, in milliseconds
+    pub age_ms: f64,
+    pub polls: u64,
+    /// Polls that reached the long poll threshold
+    pub long_polls: u64,
+    pub longest_poll_ms: f64,
+    /// How long the poll in progress has been running, if any
+    pub current_poll_ms: Option<f64>,
+    /// Poll durations, non-empty buckets only
+    pub histogram: Vec<Bucket>,
+}
+
+impl TaskPollStats {
+    /// Statistics of every live instrumented task, oldest first
+    pub fn current() -> Vec<Self> {
+        let now = Instant::now();
+        TASKS
+            .snapshot()
+            .iter()
+            .map(|task| {
+                let polls = task.polls.lock().unwrap();
+                Self {
+                    id: task.id,
+                    name: task.name.clone(),
+                    location: task.location.to_string(),
+                    age_ms: millis(now - task.spawned),
+                    polls: polls.histogram.count(),
+                    long_polls: polls.long_polls,
+                    longest_poll_ms: millis(polls.longest),
+                    current_poll_ms: polls.current.map(|started| millis(now - started)),
+                    histogram: polls.histogram.buckets(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct PollsReport {
+    long_poll_threshold_ms: f64,
+    tasks: Vec<TaskPollStats>,
+}
+
+/// `GET /debug/async/polls`
+pub(crate) fn handle_polls() -> Response<Body> {
+    json_response(&PollsReport {
+        long_poll_threshold_ms: millis(config::read(|c| c.long_poll_threshold)),
+        tasks: TaskPollStats::current(),
+    })
+}
+
+fn millis(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
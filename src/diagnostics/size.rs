@@ -0,0 +1,205 @@
+//! Spawned future sizes, by spawn location
+//!
+//! A future is as large as the state it keeps across its `.await`s, nested
+//! futures included, and tokio moves it around by value until it reaches
+//! the heap. [`spawn`](super::spawn) measures every future it is given and
+//! applies [`OversizedFuture`] to those above the threshold.
+
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::sync::Mutex;
+
+use hyper::Response;
+use serde::Serialize;
+
+use super::config;
+use crate::http::{json_response, Body};
+
+/// Sizes seen so far, by spawn location
+static SIZES: Mutex<BTreeMap<&'static Location<'static>, Sizes>> = Mutex::new(BTreeMap::new());
+
+/// What [`spawn`](super::spawn) does with a future above
+/// [`future_size_threshold`](super::DiagnosticsConfig::future_size_threshold)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizedFuture {
+    /// Log a warning, the first time and whenever the call site's largest
+    /// future grows
+    #[default]
+    Warn,
+    /// Warn, and spawn the future boxed so only a pointer is moved
+    Box,
+    /// Panic at the call site, e.g. to catch regressions in tests
+    Panic,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Sizes {
+    name: String,
+    spawns: u64,
+    last: usize,
+    min: usize,
+    max: usize,
+    boxed: u64,
+}
+
+/// Record the size of a future spawned at `location`; returns whether to
+/// box it
+///
+/// Must be called from the `#[track_caller]` spawn so a panic points at the
+/// caller.
+#[track_caller]
+pub(crate) fn record(name: &str, location: &'static Location<'static>, size: usize) -> bool {
+    let (threshold, policy) = config::read(|c| (c.future_size_threshold, c.oversized_futures));
+    let oversized = size > threshold;
+    let boxed = oversized && policy == OversizedFuture::Box;
+
+    let grew = {
+        let mut sizes = SIZES.lock().unwrap();
+        let entry = sizes.entry(location).or_insert_with(|| Sizes {
+            min: size,
+            ..Sizes::default()
+        });
+        let grew = entry.spawns == 0 || size > entry.max;
+        entry.name = name.to_string();
+        entry.spawns += 1;
+        entry.last = size;
+        entry.min = entry.min.min(size);
+        entry.max = entry.max.max(size);
+        entry.boxed += u64::from(boxed);
+        grew
+    };
+
+    if !oversized {
+        return false;
+    }
+    match policy {
+        OversizedFuture::Panic => panic!(
+            "future of task {} spawned at {} is {} bytes, over the {} bytes limit; \
+             box the large state it keeps across awaits, or the future itself",
+            name, location, size, threshold
+        ),
+        OversizedFuture::Warn | OversizedFuture::Box if grew => tracing::warn!(
+            task.name = %name,
+            task.location = %location,
+            future.size = size,
+            future.boxed = boxed,
+            "large future: task {} spawned at {} is {} bytes, over the {} bytes threshold{}",
+            name,
+            location,
+            size,
+            threshold,
+            if boxed { "; spawned boxed" } else { "" }
+        ),
+        _ => {}
+    }
+    boxed
+}
+
+/// Sizes of the futures spawned at one location
+#[derive(Debug, Clone, Serialize)]
+pub struct FutureSizeStats {
+    /// Spawn location, `file:line:column`
+    pub location: String,
+    /// Task name of the last spawn
+    pub name: String,
+    pub spawns: u64,
+    pub last_bytes: usize,
+    pub min_bytes: usize,
+    pub max_bytes: usize,
+    /// Spawns boxed for being over the threshold
+    pub boxed: u64,
+}
+
+impl FutureSizeStats {
+    /// Every spawn location seen so far, largest future first
+    pub fn current() -> Vec<Self> {
+        let mut sizes: Vec<_> = SIZES
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(location, sizes)| Self {
+                location: location.to_string(),
+                name: sizes.name.clone(),
+                spawns: sizes.spawns,
+                last_bytes: sizes.last,
+                min_bytes: sizes.min,
+                max_bytes: sizes.max,
+                boxed: sizes.boxed,
+            })
+            .collect();
+        sizes.sort_by_key(|stats| std::cmp::Reverse(stats.max_bytes));
+        sizes
+    }
+}
+
+#[derive(Serialize)]
+struct SizesReport {
+    future_size_threshold: usize,
+    callsites: Vec<FutureSizeStats>,
+}
+
+/// `GET /debug/async/sizes`
+pub(crate) fn handle_sizes() -> Response<Body> {
+    json_response(&SizesReport {
+        future_size_threshold: config::read(|c| c.future_size_threshold),
+        callsites: FutureSizeStats::current(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::config::{self, DiagnosticsConfig};
+
+    fn policy(policy: OversizedFuture) -> config::tests::TestConfig {
+        config::tests::install(
+            DiagnosticsConfig::default()
+                .future_size_threshold(64)
+                .oversized_futures(policy),
+        )
+    }
+
+    fn stats(location: &Location<'_>) -> FutureSizeStats {
+        FutureSizeStats::current()
+            .into_iter()
+            .find(|stats| stats.location == location.to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn warn_records_and_spawns_as_is() {
+        let _config = policy(OversizedFuture::Warn);
+        let location = Location::caller();
+        assert!(!record("size-test/warn", location, 32));
+        assert!(!record("size-test/warn", location, 128));
+        assert!(!record("size-test/warn", location, 96));
+
+        let stats = stats(location);
+        assert_eq!(stats.spawns, 3);
+        assert_eq!(
+            (stats.last_bytes, stats.min_bytes, stats.max_bytes),
+            (96, 32, 128)
+        );
+        assert_eq!(stats.boxed, 0);
+    }
+
+    #[test]
+    fn box_boxes_oversized_futures_only() {
+        let _config = policy(OversizedFuture::Box);
+        let location = Location::caller();
+        assert!(!record("size-test/box", location, 64));
+        assert!(record("size-test/box", location, 65));
+
+        let stats = stats(location);
+        assert_eq!((stats.spawns, stats.boxed), (2, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "is 65 bytes, over the 64 bytes limit")]
+    fn panic_panics_on_oversized_futures() {
+        let _config = policy(OversizedFuture::Panic);
+        let location = Location::caller();
+        assert!(!record("size-test/panic", location, 64));
+        record("size-test/panic", location, 65);
+    }
+}
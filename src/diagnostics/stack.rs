@@ -0,0 +1,231 @@
+//! Stacks of threads stuck inside a poll
+//!
+//! The frames of a blocking poll are gone once it returns, and std cannot
+//! walk the stack of another thread. So the monitor interrupts the worker
+//! still inside the poll with a signal: the handler, running on that
+//! thread on top of the blocking code, records the return addresses of the
+//! stack without locking or allocating, and the monitor resolves them into
+//! symbols once it is done.
+//!
+//! Unix only, with `SIGURG`, whose default action is to do nothing; on
+//! other targets, or when the application has its own `SIGURG` handler, no
+//! stack is sampled.
+
+use std::sync::Mutex;
+
+/// One sample at a time: the signal handler writes to the statics below
+static SAMPLING: Mutex<()> = Mutex::new(());
+
+/// A thread whose stack can be sampled
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Thread {
+    #[cfg(unix)]
+    id: libc::pthread_t,
+}
+
+impl Thread {
+    pub(crate) fn current() -> Self {
+        Self {
+            // SAFETY: no preconditions
+            #[cfg(unix)]
+            id: unsafe { libc::pthread_self() },
+        }
+    }
+}
+
+/// The stack of `thread`, resolved and formatted like a std backtrace,
+/// from the blocking code down to the instrumented poll
+///
+/// `None` if it cannot be sampled. Must not be called from `thread` itself.
+pub(crate) fn sample(thread: Thread) -> Option<String> {
+    let _sampling = SAMPLING.lock().unwrap();
+    let frames = signal::sample(thread)?;
+    Some(format_frames(&frames))
+}
+
+#[cfg(unix)]
+mod signal {
+    use std::ffi::{c_int, c_void};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::OnceLock;
+    use std::time::{Duration, Instant};
+
+    use super::Thread;
+
+    const SIGNAL: c_int = libc::SIGURG;
+
+    /// Frames kept from the interrupted stack, innermost first
+    const MAX_FRAMES: usize = 64;
+
+    /// How long the monitor waits for the handler to run
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    /// Set by the monitor, taken by the handler: a signal nobody asked
+    /// for, e.g. arriving after a timeout, is ignored
+    static REQUESTED: AtomicBool = AtomicBool::new(false);
+    static DONE: AtomicBool = AtomicBool::new(false);
+    static DEPTH: AtomicUsize = AtomicUsize::new(0);
+    static FRAMES: [AtomicUsize; MAX_FRAMES] = [const { AtomicUsize::new(0) }; MAX_FRAMES];
+
+    /// Install the handler unless the signal is taken already
+    fn installed() -> bool {
+        static INSTALLED: OnceLock<bool> = OnceLock::new();
+        *INSTALLED.get_or_init(|| unsafe {
+            // SAFETY: plain sigaction calls on zeroed, then filled, structs
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(SIGNAL, std::ptr::null(), &mut previous) != 0
+                || previous.sa_sigaction != libc::SIG_DFL
+            {
+                return false;
+            }
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(SIGNAL, &action, std::ptr::null_mut()) == 0
+        })
+    }
+
+    extern "C" fn on_signal(_: c_int, _: *mut libc::siginfo_t, _: *mut c_void) {
+        if !REQUESTED.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let mut depth = 0;
+        // SAFETY: the unsynchronized walk takes no lock and does not
+        // allocate, so it cannot deadlock with the interrupted code
+        unsafe {
+            backtrace::trace_unsynchronized(|frame| {
+                FRAMES[depth].store(frame.ip() as usize, Ordering::Relaxed);
+                depth += 1;
+                depth < MAX_FRAMES
+            });
+        }
+        DEPTH.store(depth, Ordering::Relaxed);
+        DONE.store(true, Ordering::Release);
+    }
+
+    pub(super) fn sample(thread: Thread) -> Option<Vec<usize>> {
+        if !installed() {
+            return None;
+        }
+        DONE.store(false, Ordering::SeqCst);
+        REQUESTED.store(true, Ordering::SeqCst);
+        // SAFETY: `thread` is a live worker: it was inside a poll just now,
+        // and runtime workers outlive the polls they run
+        if unsafe { libc::pthread_kill(thread.id, SIGNAL) } != 0 {
+            REQUESTED.store(false, Ordering::SeqCst);
+            return None;
+        }
+
+        let deadline = Instant::now() + TIMEOUT;
+        while !DONE.load(Ordering::Acquire) {
+            // A handler that started finishes quickly; one that did not is
+            // told to return right away when the signal comes
+            if Instant::now() >= deadline && REQUESTED.swap(false, Ordering::SeqCst) {
+                return None;
+            }
+            if Instant::now() >= deadline + TIMEOUT {
+                return None;
+            }
+            std::thread::sleep(Duration::from_micros(100));
+        }
+        let depth = DEPTH.load(Ordering::Relaxed);
+        Some(
+            FRAMES[..depth]
+                .iter()
+                .map(|frame| frame.load(Ordering::Relaxed))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(not(unix))]
+mod signal {
+    use super::Thread;
+
+    pub(super) fn sample(_: Thread) -> Option<Vec<usize>> {
+        None
+    }
+}
+
+/// Frames the kernel puts between a signal handler and the code it
+/// interrupted (Linux, macOS), when they resolve at all
+const SIGNAL_TRAMPOLINES: [&str; 2] = ["__restore_rt", "_sigtramp"];
+
+/// Resolve `frames`, leaving out the signal handler above the interrupted
+/// code and the runtime below the instrumented poll
+fn format_frames(frames: &[usize]) -> String {
+    let resolved: Vec<(String, Option<String>)> = frames
+        .iter()
+        .map(|&ip| {
+            let mut name = None;
+            let mut file = None;
+            backtrace::resolve(ip as *mut std::ffi::c_void, |symbol| {
+                if name.is_none() {
+                    name = symbol.name().map(|name| format!("{:#}", name));
+                    file = symbol
+                        .filename()
+                        .zip(symbol.lineno())
+                        .map(|(file, line)| format!("{}:{}", file.display(), line));
+                }
+            });
+            (name.unwrap_or_else(|| format!("{:#x}", ip)), file)
+        })
+        .collect();
+
+    let handler = resolved
+        .iter()
+        .position(|(name, _)| name.contains("stack::signal::on_signal"));
+    let start = handler.map_or(0, |handler| {
+        handler
+            + 1
+            + resolved[handler + 1..]
+                .iter()
+                .take_while(|(name, file)| {
+                    file.is_none()
+                        && (name.starts_with("0x") || SIGNAL_TRAMPOLINES.contains(&name.as_str()))
+                })
+                .count()
+    });
+    let end = resolved
+        .iter()
+        .position(|(name, _)| name.contains("diagnostics::task::Instrumented"))
+        .map_or(resolved.len(), |poll| poll + 1);
+
+    resolved[start..end.max(start)]
+        .iter()
+        .enumerate()
+        .map(|(i, (name, file))| match file {
+            Some(file) => format!("{:>4}: {}\n             at {}\n", i, name, file),
+            None => format!("{:>4}: {}\n", i, name),
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[inline(never)]
+    fn blocked_in_here(started: mpsc::Sender<Thread>) {
+        started.send(Thread::current()).unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+    }
+
+    #[test]
+    fn samples_the_stack_of_a_blocked_thread() {
+        let (started, thread) = mpsc::channel();
+        let blocked = std::thread::spawn(move || blocked_in_here(started));
+        let thread = thread.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let stack = sample(thread).unwrap();
+        assert!(stack.contains("blocked_in_here"), "{}", stack);
+        assert!(!stack.contains("on_signal"), "{}", stack);
+        assert!(!stack.starts_with("   0: 0x"), "{}", stack);
+        blocked.join().unwrap();
+    }
+}
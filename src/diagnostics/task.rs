@@ -0,0 +1,369 @@
+//! Instrumented tasks: the future wrapper and the process-wide task table
+
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use super::histogram::PollHistogram;
+use super::idle::{self, IdleWatch};
+use super::lost_waker::{self, WakerWatch};
+use super::stack::Thread;
+use super::wakes::{self, CountingWaker, WakeCounts};
+use super::{config, monitor};
+
+/// Every instrumented task that has not finished yet
+pub(crate) static TASKS: TaskTable = TaskTable::new();
+
+pub(crate) struct TaskTable {
+    next_id: AtomicU64,
+    tasks: Mutex<BTreeMap<u64, Arc<TaskEntry>>>,
+}
+
+impl TaskTable {
+    const fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            tasks: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn register(&self, name: String, location: &'static Location<'static>) -> Arc<TaskEntry> {
+        let spawn_backtrace = config::read(|c| c.spawn_backtraces).then(Backtrace::force_capture);
+        let task = Arc::new(TaskEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            name,
+            location,
+            spawned: Instant::now(),
+            spawn_backtrace,
+            polls: Mutex::default(),
+            wakes: WakeCounts::default(),
+            waker: Mutex::default(),
+            idle: Mutex::default(),
+        });
+        self.tasks.lock().unwrap().insert(task.id, task.clone());
+        monitor::ensure_started();
+        task
+    }
+
+    fn remove(&self, task: &TaskEntry) {
+        let removed = self.tasks.lock().unwrap().remove(&task.id);
+        if removed.is_some() {
+            wakes::retire(task);
+        }
+    }
+
+    /// The live task `id`, if it has not finished
+    pub(crate) fn get(&self, id: u64) -> Option<Arc<TaskEntry>> {
+        self.tasks.lock().unwrap().get(&id).cloned()
+    }
+
+    /// The live tasks, by id
+    pub(crate) fn snapshot(&self) -> Vec<Arc<TaskEntry>> {
+        self.tasks.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// `task <name> (<spawn location>)`, for reports naming other tasks
+pub(crate) fn describe(task: Option<u64>) -> String {
+    match (task, task.and_then(|id| TASKS.get(id))) {
+        (_, Some(entry)) => format!("task {} ({})", entry.name, entry.location),
+        (Some(id), None) => format!("finished task {}", id),
+        (None, None) => "an uninstrumented task".to_string(),
+    }
+}
+
+/// What is known about one instrumented task
+pub(crate) struct TaskEntry {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+    pub(crate) location: &'static Location<'static>,
+    pub(crate) spawned: Instant,
+    spawn_backtrace: Option<Backtrace>,
+    pub(crate) polls: Mutex<PollState>,
+    pub(crate) wakes: WakeCounts,
+    pub(crate) waker: Mutex<WakerWatch>,
+    pub(crate) idle: Mutex<IdleWatch>,
+}
+
+/// Poll timings of one task
+#[derive(Default)]
+pub(crate) struct PollState {
+    /// Start of the poll in progress
+    pub(crate) current: Option<Instant>,
+    /// The worker running the poll in progress
+    pub(crate) thread: Option<Thread>,
+    /// The poll in progress was already reported by the monitor
+    pub(crate) current_reported: bool,
+    /// Stack of the poll in progress, sampled by the monitor
+    pub(crate) current_stack: Option<String>,
+    pub(crate) long_polls: u64,
+    pub(crate) longest: Duration,
+    pub(crate) histogram: PollHistogram,
+}
+
+impl TaskEntry {
+    /// Warn about a poll that took, or has been running for, `duration`
+    ///
+    /// `stack` is the one of the worker inside the poll, sampled by the
+    /// monitor while it was running past the threshold: where the task
+    /// blocks. Polls ending before the monitor looks have none.
+    pub(crate) fn report_long_poll(
+        &self,
+        duration: Duration,
+        in_progress: bool,
+        stack: Option<&str>,
+    ) {
+        let what = if in_progress {
+            "is still inside a poll after"
+        } else {
+            "blocked its worker in a single poll for"
+        };
+        let stack = stack
+            .map(|stack| format!("\nblocked at:\n{}", stack))
+            .unwrap_or_default();
+        tracing::warn!(
+            task.id = self.id,
+            task.name = %self.name,
+            task.location = %self.location,
+            poll.duration_ms = duration.as_secs_f64() * 1000.0,
+            "long poll: task {} ({}) {} {:.1?}{}{}",
+            self.name,
+            self.location,
+            what,
+            duration,
+            stack,
+            self.spawn_backtrace_text()
+        );
+    }
+
+    /// Whether the task is inside a poll right now, rather than suspended
+    /// at an await
+    pub(crate) fn in_poll(&self) -> bool {
+        self.polls.lock().unwrap().current.is_some()
+    }
+
+    /// The spawn backtrace on its own lines, to end a report with
+    pub(crate) fn spawn_backtrace_text(&self) -> String {
+        self.spawn_backtrace
+            .as_ref()
+            .map(|bt| format!("\nspawned from:\n{}", bt))
+            .unwrap_or_default()
+    }
+
+    fn poll_started(&self) -> Instant {
+        let started = Instant::now();
+        let mut polls = self.polls.lock().unwrap();
+        polls.current = Some(started);
+        polls.thread = Some(Thread::current());
+        polls.current_reported = false;
+        polls.current_stack = None;
+        started
+    }
+
+    fn poll_ended(&self, started: Instant) {
+        let ended = Instant::now();
+        let duration = ended - started;
+        let long = duration >= config::read(|c| c.long_poll_threshold);
+        let stack = {
+            let mut polls = self.polls.lock().unwrap();
+            polls.current = None;
+            polls.thread = None;
+            polls.histogram.record(duration);
+            polls.longest = polls.longest.max(duration);
+            if long {
+                polls.long_polls += 1;
+            }
+            polls.current_stack.take()
+        };
+        if long {
+            self.report_long_poll(duration, false, stack.as_deref());
+        }
+    }
+}
+
+/// A future reporting its polls to the diagnostics
+///
+/// Created by [`instrument`](super::instrument) or [`spawn`](super::spawn).
+/// The task leaves the diagnostics when the future completes or is dropped.
+pub struct Instrumented<F> {
+    future: F,
+    task: Arc<TaskEntry>,
+    /// Reused while the runtime keeps passing the same waker, dropped when
+    /// the future keeps no clone of it
+    waker: Option<Arc<CountingWaker>>,
+}
+
+impl<F> Instrumented<F> {
+    pub(crate) fn new(future: F, name: String, location: &'static Location<'static>) -> Self {
+        Self {
+            future,
+            task: TASKS.register(name, location),
+            waker: None,
+        }
+    }
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // SAFETY: `future` is structurally pinned: it is never moved out of
+        // `self`, and `Drop` below does not touch it
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        let waker = match &this.waker {
+            Some(waker) if waker.wraps(cx.waker()) => waker.clone(),
+            _ => {
+                let waker = CountingWaker::new(this.task.clone(), cx.waker().clone());
+                this.waker = Some(waker.clone());
+                waker
+            }
+        };
+        lost_waker::poll_started(&this.task, &waker);
+        let wakes_before = this.task.wakes.total();
+
+        let result = {
+            let waker = Waker::from(waker.clone());
+            let mut cx = Context::from_waker(&waker);
+            let _current = wakes::enter(this.task.id);
+            let outer = idle::poll_started();
+            let started = this.task.poll_started();
+            let result = future.poll(&mut cx);
+            this.task.poll_ended(started);
+            idle::poll_ended(&this.task, result.is_pending(), outer);
+            result
+        };
+
+        if result.is_ready() {
+            TASKS.remove(&this.task);
+        } else {
+            // The context's waker is gone, `waker` and the cache remain
+            drop(waker);
+            let cached = this.waker.as_ref().unwrap();
+            lost_waker::poll_pending(&this.task, cached, wakes_before);
+            // Nothing kept the waker: let go of the runtime's too, so the
+            // task shows no waker to tokio-console either, as it would
+            // uninstrumented
+            if Arc::strong_count(cached) == 1 {
+                this.waker = None;
+            }
+        }
+        result
+    }
+}
+
+impl<F> Drop for Instrumented<F> {
+    fn drop(&mut self) {
+        TASKS.remove(&self.task);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::future::poll_fn;
+    use std::pin::pin;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::diagnostics::config::{self, DiagnosticsConfig};
+    use crate::diagnostics::instrument;
+
+    impl<F> Instrumented<F> {
+        /// The task's entry, to look at its counters
+        pub(crate) fn task(&self) -> &TaskEntry {
+            &self.task
+        }
+    }
+
+    /// Poll `future` once, as a runtime would with a waker of its own
+    pub(crate) fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn long_polls_are_counted() {
+        let _config = config::tests::install(
+            DiagnosticsConfig::default().long_poll_threshold(Duration::from_millis(20)),
+        );
+        let mut polls = 0;
+        let mut task = pin!(instrument(
+            "task-test/blocking",
+            poll_fn(|cx| {
+                polls += 1;
+                if polls == 1 {
+                    std::thread::sleep(Duration::from_millis(30));
+                }
+                cx.waker().wake_by_ref();
+                Poll::<()>::Pending
+            })
+        ));
+        assert!(poll_once(task.as_mut()).is_pending());
+        assert!(poll_once(task.as_mut()).is_pending());
+
+        let polls = task.task().polls.lock().unwrap();
+        assert_eq!(polls.long_polls, 1);
+        assert!(polls.longest >= Duration::from_millis(30));
+        assert_eq!(polls.histogram.count(), 2);
+        assert!(polls.current.is_none());
+    }
+
+    #[inline(never)]
+    fn block_the_worker() {
+        std::thread::sleep(Duration::from_millis(500));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn monitor_samples_the_stack_of_a_stuck_poll() {
+        let _config = config::tests::install(
+            DiagnosticsConfig::default().long_poll_threshold(Duration::from_millis(20)),
+        );
+        let mut task = Box::pin(instrument(
+            "task-test/stuck",
+            poll_fn(|_| {
+                block_the_worker();
+                Poll::Ready(())
+            }),
+        ));
+        let entry = TASKS.get(task.task().id).unwrap();
+
+        let stack = std::thread::scope(|scope| {
+            scope.spawn(|| poll_once(task.as_mut()));
+            let deadline = Instant::now() + Duration::from_millis(400);
+            loop {
+                let stack = entry.polls.lock().unwrap().current_stack.clone();
+                if stack.is_some() || Instant::now() > deadline {
+                    break stack;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+        let stack = stack.expect("no stack sampled while the poll was stuck");
+        assert!(stack.contains("block_the_worker"), "{}", stack);
+        assert!(entry.polls.lock().unwrap().current_stack.is_none());
+    }
+
+    #[test]
+    fn finished_tasks_leave_the_table() {
+        let mut task = pin!(instrument("task-test/ready", async { 7 }));
+        let id = task.task().id;
+        assert!(TASKS.get(id).is_some());
+        assert_eq!(poll_once(task.as_mut()), Poll::Ready(7));
+        assert!(TASKS.get(id).is_none());
+    }
+
+    #[test]
+    fn dropped_tasks_leave_the_table() {
+        let task = instrument("task-test/dropped", std::future::pending::<()>());
+        let id = task.task().id;
+        drop(task);
+        assert!(TASKS.get(id).is_none());
+    }
+}
@@ -0,0 +1,202 @@
+//! Wait-for graph between instrumented tasks
+//!
+//! An edge goes from a task blocked on a resource to the task that has to
+//! act for it to proceed, e.g. the holder of the mutex it is locking or the
+//! owner of the sender of the channel it receives from. A cycle in that
+//! graph is a deadlock: every task in it waits for the next one and none of
+//! them will ever run again.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::Location;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::task::describe;
+use super::{channel, mutex};
+
+/// Waits shorter than this are left out: the snapshot of the resources is
+/// not atomic, and a wait about to end could complete a phantom cycle
+const MIN_WAIT: Duration = Duration::from_millis(100);
+
+/// Cycles reported already, as their task ids starting from the lowest
+static REPORTED: Mutex<BTreeSet<Vec<u64>>> = Mutex::new(BTreeSet::new());
+
+/// `waiter` cannot proceed until `holder` acts on `resource`
+#[derive(Debug, Clone)]
+pub(crate) struct WaitEdge {
+    pub(crate) waiter: u64,
+    pub(crate) holder: u64,
+    /// What is waited for, e.g. `mutex config (src/main.rs:10:5)`
+    pub(crate) resource: String,
+    /// Where `waiter` waits
+    pub(crate) location: &'static Location<'static>,
+    pub(crate) waiting_for: Duration,
+}
+
+/// The current edges of every kind of instrumented resource
+fn edges() -> Vec<WaitEdge> {
+    mutex::wait_edges()
+        .into_iter()
+        .chain(channel::wait_edges())
+        .filter(|edge| edge.waiting_for >= MIN_WAIT)
+        .collect()
+}
+
+/// Report each new cycle of the wait-for graph once
+pub(crate) fn check_cycles() {
+    let edges = edges();
+    for cycle in find_cycles(&edges) {
+        let mut key: Vec<u64> = cycle.iter().map(|edge| edge.waiter).collect();
+        let lowest = key.iter().enumerate().min_by_key(|(_, id)| **id).unwrap().0;
+        key.rotate_left(lowest);
+        if !REPORTED.lock().unwrap().insert(key) {
+            continue;
+        }
+
+        let steps: String = cycle
+            .iter()
+            .map(|edge| {
+                format!(
+                    "\n  {} waits at {} for {}, held by {}",
+                    describe(Some(edge.waiter)),
+                    edge.location,
+                    edge.resource,
+                    describe(Some(edge.holder))
+                )
+            })
+            .collect();
+        tracing::warn!(
+            deadlock.tasks = ?cycle.iter().map(|edge| edge.waiter).collect::<Vec<_>>(),
+            "deadlock: {} tasks wait for each other in a cycle:{}",
+            cycle.len(),
+            steps
+        );
+    }
+}
+
+/// Cycles of the graph, each as the edges followed around it
+///
+/// A depth-first search from every task; a cycle is found when the path
+/// reaches a task already on it. Graphs are tiny, only tasks blocked on
+/// instrumented resources take part.
+fn find_cycles(edges: &[WaitEdge]) -> Vec<Vec<&WaitEdge>> {
+    let mut graph: BTreeMap<u64, Vec<&WaitEdge>> = BTreeMap::new();
+    for edge in edges {
+        graph.entry(edge.waiter).or_default().push(edge);
+    }
+
+    let mut cycles = Vec::new();
+    let mut done = BTreeSet::new();
+    for &start in graph.keys() {
+        let mut path = Vec::new();
+        visit(start, &graph, &mut path, &mut done, &mut cycles);
+    }
+    cycles
+}
+
+fn visit<'a>(
+    task: u64,
+    graph: &BTreeMap<u64, Vec<&'a WaitEdge>>,
+    path: &mut Vec<&'a WaitEdge>,
+    done: &mut BTreeSet<u64>,
+    cycles: &mut Vec<Vec<&'a WaitEdge>>,
+) {
+    if done.contains(&task) {
+        return;
+    }
+    if let Some(start) = path.iter().position(|edge| edge.waiter == task) {
+        cycles.push(path[start..].to_vec());
+        return;
+    }
+    for edge in graph.get(&task).into_iter().flatten() {
+        path.push(edge);
+        visit(edge.holder, graph, path, done, cycles);
+        path.pop();
+    }
+    done.insert(task);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::pin::pin;
+    use std::task::Poll;
+
+    use super::*;
+    use crate::diagnostics::task::tests::poll_once;
+    use crate::diagnostics::{instrument, Mutex};
+
+    fn edge(waiter: u64, holder: u64) -> WaitEdge {
+        WaitEdge {
+            waiter,
+            holder,
+            resource: format!("resource of {}", holder),
+            location: Location::caller(),
+            waiting_for: MIN_WAIT,
+        }
+    }
+
+    fn waiters(cycles: Vec<Vec<&WaitEdge>>) -> Vec<Vec<u64>> {
+        cycles
+            .into_iter()
+            .map(|cycle| cycle.iter().map(|edge| edge.waiter).collect())
+            .collect()
+    }
+
+    #[test]
+    fn chains_are_not_cycles() {
+        let edges = [edge(1, 2), edge(2, 3), edge(4, 3)];
+        assert!(find_cycles(&edges).is_empty());
+    }
+
+    #[test]
+    fn each_cycle_is_found_once() {
+        let edges = [edge(1, 2), edge(2, 1), edge(3, 4), edge(4, 5), edge(5, 3)];
+        assert_eq!(waiters(find_cycles(&edges)), [vec![1, 2], vec![3, 4, 5]]);
+    }
+
+    #[test]
+    fn tasks_leading_into_a_cycle_are_not_part_of_it() {
+        let edges = [edge(1, 2), edge(2, 3), edge(3, 2)];
+        assert_eq!(waiters(find_cycles(&edges)), [vec![2, 3]]);
+    }
+
+    /// Pending once, waking itself
+    async fn yield_now() {
+        let mut yielded = false;
+        poll_fn(|cx| {
+            if std::mem::replace(&mut yielded, true) {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    #[test]
+    fn tasks_locking_mutexes_in_opposite_orders_are_reported() {
+        let first = Mutex::named("wait-for-test/first", ());
+        let second = Mutex::named("wait-for-test/second", ());
+        let mut a = pin!(instrument("wait-for-test/a", async {
+            let _first = first.lock().await;
+            yield_now().await;
+            let _second = second.lock().await;
+        }));
+        let mut b = pin!(instrument("wait-for-test/b", async {
+            let _second = second.lock().await;
+            yield_now().await;
+            let _first = first.lock().await;
+        }));
+        for _ in 0..2 {
+            assert!(poll_once(a.as_mut()).is_pending());
+            assert!(poll_once(b.as_mut()).is_pending());
+        }
+        let (a, b) = (a.task().id, b.task().id);
+
+        std::thread::sleep(MIN_WAIT);
+        check_cycles();
+        assert!(REPORTED.lock().unwrap().contains(&vec![a, b]));
+    }
+}
@@ -0,0 +1,322 @@
+//! Self-wake accounting
+//!
+//! Each instrumented task polls its future with a waker of its own wrapping
+//! the runtime's. A wake through it while the task is being polled on the
+//! same thread is a self-wake: the future asked to be polled again right
+//! away, usually to fake a yield or to busy-wait on a condition. Any other
+//! wake comes from a timer, an I/O driver or another task.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Wake, Waker};
+
+use serde::Serialize;
+
+use super::config;
+use super::task::{TaskEntry, TASKS};
+
+/// Tasks with fewer wakes than this are not judged yet
+const MIN_WAKES: u64 = 10;
+
+thread_local! {
+    /// Id of the instrumented task being polled on this thread
+    static CURRENT: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Mark `task` as the one being polled on this thread until the guard drops
+pub(crate) fn enter(task: u64) -> RestoreCurrent {
+    RestoreCurrent(CURRENT.replace(Some(task)))
+}
+
+/// Id of the instrumented task being polled on this thread, if any
+pub(crate) fn current() -> Option<u64> {
+    CURRENT.get()
+}
+
+/// Puts back the task polled before, for instrumented futures nested in
+/// instrumented tasks
+pub(crate) struct RestoreCurrent(Option<u64>);
+
+impl Drop for RestoreCurrent {
+    fn drop(&mut self) {
+        CURRENT.set(self.0);
+    }
+}
+
+/// Wake counters of one task
+#[derive(Default)]
+pub(crate) struct WakeCounts {
+    self_wakes: AtomicU64,
+    external: AtomicU64,
+    reported: AtomicBool,
+}
+
+impl WakeCounts {
+    /// Wakes of any kind
+    pub(crate) fn total(&self) -> u64 {
+        let (self_wakes, external) = self.load();
+        self_wakes + external
+    }
+
+    fn load(&self) -> (u64, u64) {
+        (
+            self.self_wakes.load(Ordering::Relaxed),
+            self.external.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// The waker handed to an instrumented future
+pub(crate) struct CountingWaker {
+    task: Arc<TaskEntry>,
+    inner: Waker,
+}
+
+impl CountingWaker {
+    pub(crate) fn new(task: Arc<TaskEntry>, inner: Waker) -> Arc<Self> {
+        Arc::new(Self { task, inner })
+    }
+
+    /// Whether this still wraps the runtime's current waker for the task
+    pub(crate) fn wraps(&self, waker: &Waker) -> bool {
+        self.inner.will_wake(waker)
+    }
+
+    fn count(&self) {
+        let wakes = &self.task.wakes;
+        if CURRENT.get() == Some(self.task.id) {
+            wakes.self_wakes.fetch_add(1, Ordering::Relaxed);
+        } else {
+            wakes.external.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.count();
+        self.inner.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.count();
+        self.inner.wake_by_ref();
+    }
+}
+
+/// Wakes of the finished tasks, by spawn location, so the metrics counters
+/// never go down
+///
+/// Keyed by location only: task names may be built at runtime, e.g. per
+/// connection, and would grow this and the metrics series without bound.
+/// The name kept is the one of the last task retired there.
+static FINISHED: Mutex<BTreeMap<&'static Location<'static>, Totals>> = Mutex::new(BTreeMap::new());
+
+/// Wakes added up over the tasks spawned at one location
+#[derive(Debug, Clone, Default)]
+struct Totals {
+    name: String,
+    self_wakes: u64,
+    external: u64,
+}
+
+/// Fold the wakes of a task leaving the diagnostics into the totals
+///
+/// Short-lived tasks may finish between two checks of the monitor, so they
+/// are judged here one last time.
+pub(crate) fn retire(task: &TaskEntry) {
+    check(task);
+    let (self_wakes, external) = task.wakes.load();
+    if self_wakes + external == 0 {
+        return;
+    }
+    let mut finished = FINISHED.lock().unwrap();
+    let totals = finished.entry(task.location).or_default();
+    totals.name.clone_from(&task.name);
+    totals.self_wakes += self_wakes;
+    totals.external += external;
+}
+
+/// Wake counts of one instrumented task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskWakeStats {
+    /// Diagnostics task id (not the tokio task id)
+    pub id: u64,
+    pub name: String,
+    /// Spawn location, `file:line:column`
+    pub location: String,
+    /// Wakes from inside the task's own poll
+    pub self_wakes: u64,
+    /// Wakes from anywhere else
+    pub external_wakes: u64,
+    /// Share of self-wakes in all wakes, 0 without wakes
+    pub self_wake_percent: f64,
+}
+
+impl TaskWakeStats {
+    /// Wake counts of every live instrumented task, oldest first
+    pub fn current() -> Vec<Self> {
+        TASKS
+            .snapshot()
+            .iter()
+            .map(|task| {
+                let location = task.location.to_string();
+                Self::new(task.id, task.name.clone(), location, task.wakes.load())
+            })
+            .collect()
+    }
+
+    /// Wake totals by spawn location, finished tasks included
+    ///
+    /// `id` is 0 and `name` is the name of the latest task spawned there:
+    /// several tasks add up in each entry.
+    pub fn totals() -> Vec<Self> {
+        let mut totals = FINISHED.lock().unwrap().clone();
+        for task in TASKS.snapshot() {
+            let (self_wakes, external) = task.wakes.load();
+            let entry = totals.entry(task.location).or_default();
+            entry.name.clone_from(&task.name);
+            entry.self_wakes += self_wakes;
+            entry.external += external;
+        }
+        totals
+            .into_iter()
+            .map(|(location, totals)| {
+                let counts = (totals.self_wakes, totals.external);
+                Self::new(0, totals.name, location.to_string(), counts)
+            })
+            .collect()
+    }
+
+    fn new(id: u64, name: String, location: String, (self_wakes, external): (u64, u64)) -> Self {
+        Self {
+            id,
+            name,
+            location,
+            self_wakes,
+            external_wakes: external,
+            self_wake_percent: percent(self_wakes, external),
+        }
+    }
+}
+
+fn percent(self_wakes: u64, external: u64) -> f64 {
+    let total = self_wakes + external;
+    if total == 0 {
+        0.0
+    } else {
+        self_wakes as f64 * 100.0 / total as f64
+    }
+}
+
+/// Warn once about each task waking itself more than the threshold allows
+pub(crate) fn check_self_wakes() {
+    for task in TASKS.snapshot() {
+        check(&task);
+    }
+}
+
+fn check(task: &TaskEntry) {
+    let (self_wakes, external) = task.wakes.load();
+    let ratio = percent(self_wakes, external);
+    if self_wakes + external < MIN_WAKES
+        || ratio <= config::read(|c| c.self_wake_threshold)
+        || task.wakes.reported.swap(true, Ordering::Relaxed)
+    {
+        return;
+    }
+    tracing::warn!(
+        task.id = task.id,
+        task.name = %task.name,
+        task.location = %task.location,
+        wakes.self_wakes = self_wakes,
+        wakes.external = external,
+        wakes.self_percent = ratio,
+        "self-wakes: task {} ({}) woke itself for {:.0}% of its {} wakes, \
+         it is likely busy-waiting instead of waiting on a real event",
+        task.name,
+        task.location,
+        ratio,
+        self_wakes + external
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::pin::pin;
+    use std::task::Poll;
+
+    use super::*;
+    use crate::diagnostics::instrument;
+    use crate::diagnostics::task::tests::poll_once;
+
+    #[test]
+    fn wakes_from_the_own_poll_are_self_wakes() {
+        let mut task = pin!(instrument(
+            "wakes-test/self",
+            poll_fn(|cx| {
+                cx.waker().wake_by_ref();
+                Poll::<()>::Pending
+            })
+        ));
+        for _ in 0..MIN_WAKES {
+            assert!(poll_once(task.as_mut()).is_pending());
+        }
+
+        let wakes = &task.task().wakes;
+        assert_eq!(wakes.load(), (MIN_WAKES, 0));
+        check(task.task());
+        assert!(wakes.reported.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn wakes_from_elsewhere_are_external() {
+        let stored = Mutex::new(None);
+        let mut task = pin!(instrument(
+            "wakes-test/external",
+            poll_fn(|cx| {
+                *stored.lock().unwrap() = Some(cx.waker().clone());
+                Poll::<()>::Pending
+            })
+        ));
+        for _ in 0..MIN_WAKES {
+            assert!(poll_once(task.as_mut()).is_pending());
+            stored.lock().unwrap().take().unwrap().wake();
+        }
+
+        let wakes = &task.task().wakes;
+        assert_eq!(wakes.load(), (0, MIN_WAKES));
+        check(task.task());
+        assert!(!wakes.reported.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn totals_add_up_tasks_by_spawn_location() {
+        let mut locations = BTreeMap::new();
+        for i in 0..3 {
+            let mut task = pin!(instrument(
+                format!("wakes-test/totals/{}", i),
+                poll_fn(|cx| {
+                    cx.waker().wake_by_ref();
+                    Poll::Ready(())
+                })
+            ));
+            *locations.entry(task.task().location).or_insert(0) += 1;
+            assert!(poll_once(task.as_mut()).is_ready());
+        }
+        let (&location, &tasks) = locations.iter().next().unwrap();
+        assert_eq!((locations.len(), tasks), (1, 3));
+
+        let totals: Vec<_> = TaskWakeStats::totals()
+            .into_iter()
+            .filter(|stats| stats.location == location.to_string())
+            .collect();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].self_wakes, 3);
+        assert_eq!(totals[0].name, "wakes-test/totals/2");
+    }
+}
@@ -0,0 +1,172 @@
+//! Small HTTP helpers shared by the profiling endpoints
+//!
+//! Responses are always fully buffered (`Full<Bytes>`): profiles are produced
+//! in one piece, so there is nothing to stream.
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+
+/// Body type used by every response produced by this crate
+pub type Body = Full<Bytes>;
+
+/// Find the raw value of a query string parameter
+pub fn query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query.and_then(|q| {
+        q.split('&').find_map(|param| {
+            param
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+    })
+}
+
+/// Find a query string parameter and percent-decode it (`+` is a space)
+pub fn query_param_decoded(query: Option<&str>, name: &str) -> Option<String> {
+    query_param(query, name).map(percent_decode)
+}
+
+/// Parse a boolean query parameter (`1`/`true`/`yes`/`on`, or present without a value)
+pub fn parse_bool_param(query: Option<&str>, name: &str) -> bool {
+    let bare_flag = query.is_some_and(|q| q.split('&').any(|param| param == name));
+    bare_flag || matches!(query_param(query, name), Some("1" | "true" | "yes" | "on"))
+}
+
+/// Decode `%XX` escapes and `+` in a query string value
+///
+/// Malformed escapes are kept verbatim rather than rejected.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match value
+                .get(i + 1..i + 3)
+                .map(|hex| u8::from_str_radix(hex, 16))
+            {
+                Some(Ok(byte)) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Parse seconds parameter from query string
+///
+/// Values of zero or above `max_seconds` are rejected.
+pub fn parse_seconds_param(query: Option<&str>, max_seconds: u64) -> Option<u64> {
+    query_param(query, "seconds")
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|&seconds| seconds > 0 && seconds <= max_seconds)
+}
+
+/// Binary attachment response (protobuf profiles)
+pub fn attachment_response(body: Vec<u8>, filename: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/octet-stream")
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+/// SVG image response (flamegraphs), viewable directly in a browser
+pub fn svg_response(body: Vec<u8>) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "image/svg+xml")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+/// JSON response
+pub fn json_response<T: serde::Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_vec_pretty(value) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap(),
+        Err(e) => error_response(format!("Failed to encode JSON: {}", e)),
+    }
+}
+
+/// Plain text response
+pub fn text_response(status: StatusCode, body: impl Into<Bytes>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Full::new(body.into()))
+        .unwrap()
+}
+
+/// 404 Not Found response
+pub fn not_found() -> Response<Body> {
+    text_response(StatusCode::NOT_FOUND, "404 Not Found\n")
+}
+
+/// 400 Bad Request response for invalid query parameters
+pub fn bad_request(message: String) -> Response<Body> {
+    text_response(StatusCode::BAD_REQUEST, format!("Error: {}\n", message))
+}
+
+/// Error response
+pub fn error_response(message: String) -> Response<Body> {
+    text_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Error: {}\n", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_param_matches_whole_names() {
+        let query = Some("seconds=30&title=cpu+profile&reverse&xseconds=5");
+        assert_eq!(query_param(query, "seconds"), Some("30"));
+        assert_eq!(query_param(query, "title"), Some("cpu+profile"));
+        assert_eq!(query_param(query, "second"), None);
+        assert_eq!(query_param(query, "reverse"), None);
+        assert_eq!(query_param(Some("seconds="), "seconds"), Some(""));
+        assert_eq!(query_param(None, "seconds"), None);
+    }
+
+    #[test]
+    fn percent_decode_escapes_and_plus() {
+        assert_eq!(percent_decode("cpu+profile"), "cpu profile");
+        assert_eq!(percent_decode("a%2Bb%20c"), "a+b c");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("%e2%9c%93"), "✓");
+    }
+
+    #[test]
+    fn percent_decode_keeps_malformed_escapes() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%%41"), "%A");
+        assert_eq!(percent_decode("%é"), "%é");
+        assert_eq!(percent_decode("%FF"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn bool_params() {
+        assert!(parse_bool_param(Some("reverse"), "reverse"));
+        assert!(parse_bool_param(Some("a=1&reverse=yes"), "reverse"));
+        assert!(!parse_bool_param(Some("reverse=0"), "reverse"));
+        assert!(!parse_bool_param(Some("reversed"), "reverse"));
+        assert!(!parse_bool_param(None, "reverse"));
+    }
+}
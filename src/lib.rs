@@ -0,0 +1,21 @@
+//! Profiling and async diagnostics helpers for tokio + hyper services
+//!
+//! The examples in this repository demonstrate common async pitfalls; this
+//! library holds the reusable pieces so services can attach them without
+//! copying example code.
+//!
+//! - [`console_lint`]: tokio-console's warnings evaluated headlessly, for CI
+//! - [`diagnostics`]: instrumented tasks reporting async pitfalls via tracing
+//! - [`profiling`]: mountable HTTP service serving CPU and heap profiles
+//! - [`scenario`]: named demo scenarios and the examples' command line
+//! - [`stats`]: live allocator and tokio scheduler statistics as JSON
+//! - [`http`]: response and query string helpers used by the endpoints
+//! - [`workload`]: synthetic CPU and memory load for demos
+
+pub mod console_lint;
+pub mod diagnostics;
+pub mod http;
+pub mod profiling;
+pub mod scenario;
+pub mod stats;
+pub mod workload;
@@ -0,0 +1,450 @@
+//! Continuous CPU profiling into a rolling in-memory ring buffer
+//!
+//! A background task profiles the process in back-to-back fixed windows and
+//! keeps the most recent ones, so the profile of an incident that is already
+//! over can still be pulled afterwards.
+//!
+//! Routes (enabled with [`ProfilingServiceBuilder::continuous_profiling`]):
+//! - `GET /profile/cpu/continuous/windows` - JSON list of retained windows
+//! - `GET /profile/cpu/continuous?window=<id>` - pprof protobuf of one window
+//! - `GET /profile/cpu/continuous?minutes=<n>` - windows of the last n minutes merged
+//!   (all retained windows when neither parameter is given)
+//! - `GET /profile/cpu/continuous/flamegraph` - same selection, rendered as SVG
+//!
+//! [`ProfilingServiceBuilder::continuous_profiling`]: super::ProfilingServiceBuilder::continuous_profiling
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::{Method, Response, StatusCode};
+use serde::Serialize;
+
+use super::cpu::{flamegraph_response, pprof_response};
+use super::flamegraph::FlamegraphParams;
+use super::session::{SessionSource, CPU_SESSIONS};
+use super::ContinuousConfig;
+use crate::http::{bad_request, json_response, query_param, text_response, Body};
+
+/// One captured profiling window
+///
+/// The report is behind a mutex because `pprof::Symbol` keeps a raw address
+/// and is not `Sync`; what the window list shows is copied out of it.
+struct Window {
+    id: u64,
+    started: SystemTime,
+    duration: Duration,
+    samples: isize,
+    report: Mutex<pprof::Report>,
+}
+
+impl Window {
+    fn new(id: u64, started: SystemTime, report: pprof::Report) -> Self {
+        Self {
+            id,
+            started,
+            duration: report.timing.duration,
+            samples: report.data.values().sum(),
+            report: Mutex::new(report),
+        }
+    }
+
+    fn ended(&self) -> SystemTime {
+        self.started + self.duration
+    }
+}
+
+/// Background profiler and the windows it has retained
+pub(crate) struct ContinuousProfiler {
+    config: ContinuousConfig,
+    windows: Mutex<VecDeque<Arc<Window>>>,
+}
+
+/// Which retained windows a request asks for
+#[derive(Debug, PartialEq, Eq)]
+enum Selection {
+    Window(u64),
+    LastMinutes(u64),
+    All,
+}
+
+#[derive(Serialize)]
+struct WindowList {
+    window_seconds: u64,
+    capacity: usize,
+    windows: Vec<WindowInfo>,
+}
+
+#[derive(Serialize)]
+struct WindowInfo {
+    id: u64,
+    start_unix_ms: u128,
+    duration_ms: u128,
+    samples: isize,
+}
+
+impl ContinuousProfiler {
+    /// Spawn the capture loop; it stops once the returned profiler is dropped
+    ///
+    /// Must be called from within a tokio runtime.
+    pub(crate) fn start(config: ContinuousConfig, frequency: i32) -> Arc<Self> {
+        let profiler = Arc::new(Self {
+            config,
+            windows: Mutex::new(VecDeque::with_capacity(config.capacity)),
+        });
+
+        tracing::info!(
+            "Starting continuous CPU profiling ({}s windows, keeping {})",
+            config.window.as_secs(),
+            config.capacity
+        );
+        tokio::spawn(capture_loop(Arc::downgrade(&profiler), config, frequency));
+
+        profiler
+    }
+
+    fn push(&self, window: Window) {
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() == self.config.capacity {
+            windows.pop_front();
+        }
+        windows.push_back(Arc::new(window));
+    }
+
+    fn select(&self, selection: &Selection) -> Vec<Arc<Window>> {
+        let windows = self.windows.lock().unwrap();
+        match *selection {
+            Selection::Window(id) => windows.iter().filter(|w| w.id == id).cloned().collect(),
+            Selection::LastMinutes(minutes) => {
+                // Beyond what the clock can go back, every window is recent
+                let since = minutes
+                    .checked_mul(60)
+                    .and_then(|secs| SystemTime::now().checked_sub(Duration::from_secs(secs)));
+                windows
+                    .iter()
+                    .filter(|w| since.is_none_or(|since| w.ended() > since))
+                    .cloned()
+                    .collect()
+            }
+            Selection::All => windows.iter().cloned().collect(),
+        }
+    }
+
+    /// Answer the `/profile/cpu/continuous` routes, `None` for any other request
+    pub(crate) fn route(
+        &self,
+        method: &Method,
+        path: &str,
+        query: Option<&str>,
+    ) -> Option<Response<Body>> {
+        if method != Method::GET {
+            return None;
+        }
+        match path {
+            "/profile/cpu/continuous" => Some(self.handle_profile(query)),
+            "/profile/cpu/continuous/windows" => Some(self.handle_windows()),
+            "/profile/cpu/continuous/flamegraph" => Some(self.handle_flamegraph(query)),
+            _ => None,
+        }
+    }
+
+    /// List retained windows as JSON
+    fn handle_windows(&self) -> Response<Body> {
+        let windows = self.select(&Selection::All);
+        json_response(&WindowList {
+            window_seconds: self.config.window.as_secs(),
+            capacity: self.config.capacity,
+            windows: windows
+                .iter()
+                .map(|w| WindowInfo {
+                    id: w.id,
+                    start_unix_ms: unix_millis(w.started),
+                    duration_ms: w.duration.as_millis(),
+                    samples: w.samples,
+                })
+                .collect(),
+        })
+    }
+
+    /// pprof protobuf of the selected windows
+    fn handle_profile(&self, query: Option<&str>) -> Response<Body> {
+        let selection = match parse_selection(query) {
+            Ok(selection) => selection,
+            Err(message) => return bad_request(message),
+        };
+        match self.merged_report(&selection) {
+            Some(report) => pprof_response(&report, "cpu_profile_continuous.pb"),
+            None => no_windows(&selection),
+        }
+    }
+
+    /// SVG flamegraph of the selected windows
+    fn handle_flamegraph(&self, query: Option<&str>) -> Response<Body> {
+        let selection = match parse_selection(query) {
+            Ok(selection) => selection,
+            Err(message) => return bad_request(message),
+        };
+        let params = match FlamegraphParams::from_query(query) {
+            Ok(params) => params,
+            Err(message) => return bad_request(message),
+        };
+        match self.merged_report(&selection) {
+            Some(report) => flamegraph_response(&report, &params, "Continuous CPU Flame Graph"),
+            None => no_windows(&selection),
+        }
+    }
+
+    /// Merge the selected windows into one report, `None` if nothing matches
+    fn merged_report(&self, selection: &Selection) -> Option<pprof::Report> {
+        let windows = self.select(selection);
+        let (first, last) = (windows.first()?, windows.last()?);
+
+        // Frames carry their sample timestamp, so identical stacks from
+        // different windows stay separate keys; pprof and inferno sum them.
+        let mut timing = first.report.lock().unwrap().timing.clone();
+        timing.start_time = first.started;
+        timing.duration = last
+            .ended()
+            .duration_since(first.started)
+            .unwrap_or_default();
+
+        let mut data = std::collections::HashMap::new();
+        for window in &windows {
+            for (frames, count) in &window.report.lock().unwrap().data {
+                *data.entry(frames.clone()).or_insert(0) += count;
+            }
+        }
+
+        Some(pprof::Report { data, timing })
+    }
+}
+
+/// Profile back-to-back windows until the profiler is dropped
+async fn capture_loop(
+    profiler: Weak<ContinuousProfiler>,
+    config: ContinuousConfig,
+    frequency: i32,
+) {
+    let mut next_id = 1;
+
+    while profiler.strong_count() > 0 {
+        // Queued on-demand requests get the slot first (the lock is FIFO)
+        let session = CPU_SESSIONS
+            .begin(config.window, SessionSource::Continuous)
+            .await;
+        let started = SystemTime::now();
+
+        let guard = match pprof::ProfilerGuard::new(frequency) {
+            Ok(guard) => guard,
+            Err(e) => {
+                tracing::warn!("Continuous profiling: failed to start profiler: {}", e);
+                drop(session);
+                tokio::time::sleep(config.window).await;
+                continue;
+            }
+        };
+
+        tokio::time::sleep(config.window).await;
+
+        let report = guard.report().build();
+        drop(guard);
+        drop(session);
+
+        let Some(profiler) = profiler.upgrade() else {
+            break;
+        };
+        match report {
+            Ok(report) => {
+                profiler.push(Window::new(next_id, started, report));
+                next_id += 1;
+            }
+            Err(e) => tracing::warn!("Continuous profiling: failed to build report: {}", e),
+        }
+    }
+
+    tracing::info!("Continuous CPU profiling stopped");
+}
+
+/// 404 explaining why the selection matched no window
+fn no_windows(selection: &Selection) -> Response<Body> {
+    let message = match selection {
+        Selection::Window(id) => format!("Window {} is not retained.\n", id),
+        _ => {
+            "No profiling windows captured yet; the first one completes after one window length.\n"
+                .to_string()
+        }
+    };
+    text_response(StatusCode::NOT_FOUND, message)
+}
+
+fn parse_selection(query: Option<&str>) -> Result<Selection, String> {
+    if let Some(value) = query_param(query, "window") {
+        return value
+            .parse()
+            .map(Selection::Window)
+            .map_err(|_| format!("invalid window id: {}", value));
+    }
+    if let Some(value) = query_param(query, "minutes") {
+        return match value.parse() {
+            Ok(minutes) if minutes > 0 => Ok(Selection::LastMinutes(minutes)),
+            _ => Err(format!("invalid minutes: {}", value)),
+        };
+    }
+    Ok(Selection::All)
+}
+
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn profiler(capacity: usize) -> ContinuousProfiler {
+        ContinuousProfiler {
+            config: ContinuousConfig {
+                window: Duration::from_secs(10),
+                capacity,
+            },
+            windows: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn frames(function: &str, sample_timestamp: SystemTime) -> pprof::Frames {
+        pprof::Frames {
+            frames: vec![vec![pprof::Symbol {
+                name: Some(function.as_bytes().to_vec()),
+                addr: None,
+                lineno: None,
+                filename: None,
+            }]],
+            thread_name: "worker".to_string(),
+            thread_id: 1,
+            sample_timestamp,
+        }
+    }
+
+    /// A 10s window starting `ago` before now with `samples` per function
+    fn window(id: u64, ago: Duration, samples: &[(&str, isize)]) -> Window {
+        let started = SystemTime::now() - ago;
+        let mut report = pprof::Report {
+            data: HashMap::new(),
+            timing: Default::default(),
+        };
+        report.timing.frequency = 100;
+        report.timing.start_time = started;
+        report.timing.duration = Duration::from_secs(10);
+        for (function, count) in samples {
+            report.data.insert(frames(function, started), *count);
+        }
+        Window::new(id, started, report)
+    }
+
+    fn counts(report: &pprof::Report) -> HashMap<String, isize> {
+        let mut counts = HashMap::new();
+        for (frames, count) in &report.data {
+            let name = String::from_utf8(frames.frames[0][0].raw_name().to_vec()).unwrap();
+            *counts.entry(name).or_default() += count;
+        }
+        counts
+    }
+
+    #[test]
+    fn parse_selection_params() {
+        assert_eq!(parse_selection(None), Ok(Selection::All));
+        assert_eq!(parse_selection(Some("seconds=5")), Ok(Selection::All));
+        assert_eq!(parse_selection(Some("window=3")), Ok(Selection::Window(3)));
+        assert_eq!(
+            parse_selection(Some("minutes=15")),
+            Ok(Selection::LastMinutes(15))
+        );
+        // `window` wins when both are given
+        assert_eq!(
+            parse_selection(Some("minutes=15&window=3")),
+            Ok(Selection::Window(3))
+        );
+    }
+
+    #[test]
+    fn parse_selection_rejects_bad_and_out_of_range_values() {
+        for query in [
+            "window=",
+            "window=abc",
+            "window=-1",
+            "window=18446744073709551616",
+            "minutes=0",
+            "minutes=-5",
+            "minutes=1.5",
+            "minutes=18446744073709551616",
+        ] {
+            assert!(parse_selection(Some(query)).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn capacity_drops_the_oldest_window() {
+        let profiler = profiler(2);
+        for id in 1..=3 {
+            profiler.push(window(id, Duration::from_secs(60), &[("f", 1)]));
+        }
+        let ids: Vec<_> = profiler
+            .select(&Selection::All)
+            .iter()
+            .map(|w| w.id)
+            .collect();
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[test]
+    fn merged_report_sums_the_selected_windows() {
+        let profiler = profiler(8);
+        assert!(profiler.merged_report(&Selection::All).is_none());
+
+        profiler.push(window(1, Duration::from_secs(3600), &[("old", 7)]));
+        profiler.push(window(2, Duration::from_secs(120), &[("a", 2), ("b", 1)]));
+        profiler.push(window(3, Duration::from_secs(60), &[("a", 3)]));
+
+        let all = profiler.merged_report(&Selection::All).unwrap();
+        assert_eq!(
+            counts(&all),
+            HashMap::from([
+                ("old".to_string(), 7),
+                ("a".to_string(), 5),
+                ("b".to_string(), 1)
+            ])
+        );
+        // Spans from the first window's start to the last one's end
+        let windows = profiler.select(&Selection::All);
+        assert_eq!(all.timing.start_time, windows[0].started);
+        assert_eq!(
+            all.timing.duration,
+            windows[2]
+                .ended()
+                .duration_since(windows[0].started)
+                .unwrap()
+        );
+        assert_eq!(all.timing.frequency, 100);
+
+        let recent = profiler.merged_report(&Selection::LastMinutes(5)).unwrap();
+        assert_eq!(
+            counts(&recent),
+            HashMap::from([("a".to_string(), 5), ("b".to_string(), 1)])
+        );
+
+        let one = profiler.merged_report(&Selection::Window(1)).unwrap();
+        assert_eq!(counts(&one), HashMap::from([("old".to_string(), 7)]));
+        assert_eq!(one.timing.duration, Duration::from_secs(10));
+
+        assert!(profiler.merged_report(&Selection::Window(9)).is_none());
+        // Further back than the clock goes: every window is recent
+        let everything = profiler
+            .merged_report(&Selection::LastMinutes(u64::MAX))
+            .unwrap();
+        assert_eq!(counts(&everything), counts(&all));
+    }
+}
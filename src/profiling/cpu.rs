@@ -0,0 +1,214 @@
+//! CPU profiling via pprof-rs
+
+use std::time::Duration;
+
+use hyper::{Response, StatusCode};
+
+use super::ProfilingConfig;
+use crate::http::{error_response, parse_seconds_param, text_response, Body};
+use crate::workload;
+
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+use {
+    super::flamegraph::FlamegraphParams,
+    super::session::{BusyPolicy, CPU_SESSIONS},
+    crate::http::{attachment_response, bad_request, svg_response},
+    pprof::protos::Message,
+};
+
+/// Profile the process for the requested duration and build a report
+///
+/// Errors are already turned into responses so handlers can return them as-is.
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+async fn collect_cpu_report(
+    config: &ProfilingConfig,
+    query: Option<&str>,
+) -> Result<pprof::Report, Response<Body>> {
+    let seconds =
+        parse_seconds_param(query, config.max_cpu_seconds).unwrap_or(config.default_cpu_seconds);
+
+    let duration = Duration::from_secs(seconds);
+
+    // Only one profiler may run per process; hold the slot until the report is built.
+    // The continuous profiler holds the slot almost permanently, so requests
+    // always queue behind its current window when it is enabled.
+    let policy = if config.continuous.is_some() {
+        BusyPolicy::Wait
+    } else {
+        config.busy_policy
+    };
+    let _session = CPU_SESSIONS
+        .acquire(policy, duration)
+        .await
+        .map_err(|busy| {
+            tracing::warn!(
+                "Rejecting CPU profile request: another session has {}s remaining",
+                busy.remaining.as_secs()
+            );
+            busy.into_response()
+        })?;
+
+    tracing::info!("Starting CPU profiling ({} seconds)...", seconds);
+
+    let guard = match pprof::ProfilerGuard::new(config.cpu_frequency) {
+        Ok(guard) => guard,
+        Err(e) => {
+            tracing::warn!("Failed to start profiler: {}", e);
+            return Err(error_response(format!("Failed to start profiler: {}", e)));
+        }
+    };
+
+    // Sample whatever the process is doing for the requested duration
+    tokio::time::sleep(duration).await;
+    tracing::info!("Profiling duration completed");
+
+    guard.report().build().map_err(|e| {
+        tracing::warn!("Failed to build report: {}", e);
+        error_response(format!("Failed to build report: {}", e))
+    })
+}
+
+/// CPU profile endpoint - returns profile in protobuf format
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(crate) async fn handle_cpu_profile(
+    config: &ProfilingConfig,
+    query: Option<&str>,
+) -> Response<Body> {
+    let report = match collect_cpu_report(config, query).await {
+        Ok(report) => report,
+        Err(response) => return response,
+    };
+
+    pprof_response(&report, "cpu_profile.pb")
+}
+
+/// Encode a report as a pprof protobuf attachment
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(super) fn pprof_response(report: &pprof::Report, filename: &str) -> Response<Body> {
+    // Generate protobuf profile
+    match report.pprof() {
+        Ok(profile) => {
+            // Convert profile to bytes using write_to_writer
+            let mut body = Vec::new();
+            if let Err(e) = profile.write_to_writer(&mut body) {
+                tracing::warn!("Failed to encode profile: {}", e);
+                return error_response(format!("Failed to encode profile: {}", e));
+            }
+
+            if body.is_empty() {
+                tracing::warn!("Generated profile is empty");
+                return error_response("Generated profile is empty. This might be due to system limitations or insufficient CPU activity (try POST /workload/cpu first when workload endpoints are enabled).".to_string());
+            }
+
+            tracing::info!("CPU profile generated successfully ({} bytes)", body.len());
+
+            attachment_response(body, filename)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to generate pprof: {}", e);
+            error_response(format!("Failed to generate pprof: {}", e))
+        }
+    }
+}
+
+/// CPU flamegraph endpoint - returns an interactive SVG
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(crate) async fn handle_cpu_flamegraph(
+    config: &ProfilingConfig,
+    query: Option<&str>,
+) -> Response<Body> {
+    // Validate options before spending `seconds` profiling
+    let params = match FlamegraphParams::from_query(query) {
+        Ok(params) => params,
+        Err(message) => return bad_request(message),
+    };
+
+    let report = match collect_cpu_report(config, query).await {
+        Ok(report) => report,
+        Err(response) => return response,
+    };
+
+    flamegraph_response(&report, &params, "CPU Flame Graph")
+}
+
+/// Render a report as an SVG flamegraph
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(super) fn flamegraph_response(
+    report: &pprof::Report,
+    params: &FlamegraphParams,
+    default_title: &str,
+) -> Response<Body> {
+    if report.data.is_empty() {
+        tracing::warn!("CPU profile has no samples");
+        return error_response(
+            "CPU profile has no samples; the process was idle during the profiled period."
+                .to_string(),
+        );
+    }
+
+    let mut opts = pprof::flamegraph::Options::default();
+    if let Err(message) = params.apply(&mut opts, default_title) {
+        return bad_request(message);
+    }
+
+    let mut svg = Vec::new();
+    if let Err(e) = report.flamegraph_with_options(&mut svg, &mut opts) {
+        tracing::warn!("Failed to render flamegraph: {}", e);
+        return error_response(format!("Failed to render flamegraph: {}", e));
+    }
+
+    tracing::info!(
+        "CPU flamegraph generated successfully ({} bytes)",
+        svg.len()
+    );
+
+    svg_response(svg)
+}
+
+/// CPU profile endpoint - Windows/MSVC fallback (pprof-rs not available)
+#[cfg(any(target_env = "msvc", target_os = "windows"))]
+pub(crate) async fn handle_cpu_profile(
+    _config: &ProfilingConfig,
+    _query: Option<&str>,
+) -> Response<Body> {
+    error_response(
+        "CPU profiling is not available on Windows/MSVC targets. \
+         Use Linux/macOS or consider alternative tools like Windows Performance Analyzer."
+            .to_string(),
+    )
+}
+
+/// CPU flamegraph endpoint - Windows/MSVC fallback (pprof-rs not available)
+#[cfg(any(target_env = "msvc", target_os = "windows"))]
+pub(crate) async fn handle_cpu_flamegraph(
+    config: &ProfilingConfig,
+    query: Option<&str>,
+) -> Response<Body> {
+    handle_cpu_profile(config, query).await
+}
+
+/// Synthetic CPU load endpoint - opt-in, for demos only
+///
+/// Returns immediately; the load keeps running in the background for the
+/// requested number of seconds so it can be captured by `/profile/cpu`.
+pub(crate) async fn handle_cpu_load(
+    config: &ProfilingConfig,
+    query: Option<&str>,
+) -> Response<Body> {
+    let seconds =
+        parse_seconds_param(query, config.max_cpu_seconds).unwrap_or(config.default_cpu_seconds);
+
+    tracing::info!("Generating synthetic CPU load for {} seconds...", seconds);
+    tokio::spawn(async move {
+        workload::run_cpu_load(Duration::from_secs(seconds)).await;
+        tracing::info!("Synthetic CPU load finished");
+    });
+
+    text_response(
+        StatusCode::ACCEPTED,
+        format!(
+            "Generating synthetic CPU load for {} seconds.\nProfile it with: POST /profile/cpu?seconds={}\n",
+            seconds, seconds
+        ),
+    )
+}
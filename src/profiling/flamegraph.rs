@@ -0,0 +1,105 @@
+//! Flamegraph rendering options taken from the query string
+//!
+//! Supported parameters:
+//! - `title=<text>`     - Title shown at the top of the graph
+//! - `min_width=<f64>`  - Omit frames narrower than this many pixels (default 0.01)
+//! - `reverse=true`     - Draw an icicle graph (root at the top)
+//! - `palette=<name>`   - Color palette: hot, mem, io, wakeup, java, js, perl,
+//!   python, rust, red, green, blue, aqua, yellow, purple, orange
+
+use crate::http::{parse_bool_param, query_param, query_param_decoded};
+
+/// Palette names understood by inferno
+const PALETTES: &[&str] = &[
+    "hot", "mem", "io", "wakeup", "java", "js", "perl", "python", "rust", "red", "green", "blue",
+    "aqua", "yellow", "purple", "orange",
+];
+
+/// Flamegraph options requested by the client
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FlamegraphParams {
+    pub(crate) title: Option<String>,
+    pub(crate) min_width: Option<f64>,
+    pub(crate) reverse: bool,
+    pub(crate) palette: Option<String>,
+}
+
+impl FlamegraphParams {
+    /// Parse options from the query string, rejecting invalid values
+    pub(crate) fn from_query(query: Option<&str>) -> Result<Self, String> {
+        let min_width = match query_param(query, "min_width") {
+            Some(value) => match value.parse::<f64>() {
+                Ok(width) if width >= 0.0 && width.is_finite() => Some(width),
+                _ => return Err(format!("invalid min_width: {}", value)),
+            },
+            None => None,
+        };
+
+        let palette = query_param_decoded(query, "palette");
+        if let Some(palette) = &palette {
+            if !PALETTES.contains(&palette.as_str()) {
+                return Err(format!(
+                    "unknown palette: {} (expected one of: {})",
+                    palette,
+                    PALETTES.join(", ")
+                ));
+            }
+        }
+
+        Ok(Self {
+            title: query_param_decoded(query, "title"),
+            min_width,
+            reverse: parse_bool_param(query, "reverse"),
+            palette,
+        })
+    }
+
+    /// Apply the options on top of pprof-rs' inferno defaults (CPU profiles)
+    #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+    pub(crate) fn apply(
+        &self,
+        opts: &mut pprof::flamegraph::Options<'_>,
+        default_title: &str,
+    ) -> Result<(), String> {
+        opts.title = self
+            .title
+            .clone()
+            .unwrap_or_else(|| default_title.to_string());
+        if let Some(min_width) = self.min_width {
+            opts.min_width = min_width;
+        }
+        if self.reverse {
+            opts.direction = pprof::flamegraph::Direction::Inverted;
+        }
+        if let Some(palette) = &self.palette {
+            opts.colors = palette.parse()?;
+        }
+        Ok(())
+    }
+
+    /// Apply the options on top of jemalloc_pprof's inferno defaults (heap profiles)
+    ///
+    /// jemalloc_pprof links a newer inferno than pprof-rs, so its option type
+    /// is distinct even though the fields are the same.
+    #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+    pub(crate) fn apply_heap(
+        &self,
+        opts: &mut jemalloc_pprof::FlamegraphOptions<'_>,
+        default_title: &str,
+    ) -> Result<(), String> {
+        opts.title = self
+            .title
+            .clone()
+            .unwrap_or_else(|| default_title.to_string());
+        if let Some(min_width) = self.min_width {
+            opts.min_width = min_width;
+        }
+        if self.reverse {
+            opts.direction = inferno::flamegraph::Direction::Inverted;
+        }
+        if let Some(palette) = &self.palette {
+            opts.colors = palette.parse()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Go `net/http/pprof` compatible routes
+//!
+//! Mirrors the URL layout and GET semantics of Go's `/debug/pprof/` so the
+//! standard tooling works unmodified:
+//!
+//! ```bash
+//! go tool pprof http://localhost:8080/debug/pprof/profile?seconds=30
+//! go tool pprof http://localhost:8080/debug/pprof/heap
+//! ```
+
+use http_body_util::{BodyExt, Limited};
+use hyper::body::Incoming;
+use hyper::{Method, Request, Response, StatusCode};
+
+use crate::http::{bad_request, Body};
+
+/// Largest symbol lookup request body accepted (addresses are ~19 bytes each)
+const MAX_SYMBOL_BODY: usize = 1024 * 1024;
+
+/// Index page listing the available profiles, like Go's `/debug/pprof/`
+pub(crate) fn handle_index() -> Response<Body> {
+    let body = r#"<!DOCTYPE html>
+<html>
+<head>
+    <title>/debug/pprof/</title>
+    <style>
+        body { font-family: Arial, sans-serif; max-width: 800px; margin: 50px auto; padding: 20px; }
+        td { padding: 4px 12px 4px 0; vertical-align: top; }
+    </style>
+</head>
+<body>
+    <h1>/debug/pprof/</h1>
+    <p>Types of profiles available:</p>
+    <table>
+        <tr>
+            <td><a href="profile?seconds=30">profile</a></td>
+            <td>CPU profile. Use the <code>seconds</code> GET parameter to set the duration.
+                After you get the profile file, use <code>go tool pprof</code> to investigate it.</td>
+        </tr>
+        <tr>
+            <td><a href="heap">heap</a></td>
+            <td>Live heap allocations sampled by jemalloc (in-use bytes by allocation stack).</td>
+        </tr>
+        <tr>
+            <td><a href="symbol">symbol</a></td>
+            <td>Resolves program counters to function names; POST <code>0xaddr+0xaddr</code>.</td>
+        </tr>
+        <tr>
+            <td><a href="cmdline">cmdline</a></td>
+            <td>The command line of the running program, NUL separated.</td>
+        </tr>
+    </table>
+    <p><a href="/profile/cpu/flamegraph?seconds=30">CPU flamegraph</a> |
+       <a href="/profile/memory/flamegraph">Heap flamegraph</a></p>
+</body>
+</html>"#;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// `301` from `/debug/pprof` to `/debug/pprof/`, as Go's mux does
+///
+/// The index links are relative, and only resolve under the trailing slash.
+pub(crate) fn redirect_to_index() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header("Location", "/debug/pprof/")
+        .body(Body::from("Moved to /debug/pprof/\n"))
+        .unwrap()
+}
+
+/// Command line of the running program, arguments separated by NUL bytes
+pub(crate) fn handle_cmdline() -> Response<Body> {
+    let cmdline = std::env::args().collect::<Vec<_>>().join("\0");
+    text_plain(cmdline)
+}
+
+/// Symbol lookup, following Go's protocol
+///
+/// `GET` without addresses reports that symbolization is supported. Addresses
+/// are read as `+`-separated hex values from the POST body or, for GET, the
+/// raw query string. Each resolved address produces a `0x<addr> <name>` line;
+/// unknown addresses are skipped.
+pub(crate) async fn handle_symbol(req: Request<Incoming>) -> Response<Body> {
+    let addresses = if req.method() == Method::POST {
+        match Limited::new(req.into_body(), MAX_SYMBOL_BODY)
+            .collect()
+            .await
+        {
+            Ok(collected) => String::from_utf8_lossy(&collected.to_bytes()).into_owned(),
+            Err(e) => return bad_request(format!("Failed to read request body: {}", e)),
+        }
+    } else {
+        req.uri().query().unwrap_or_default().to_string()
+    };
+
+    let mut body = String::from("num_symbols: 1\n");
+    for pc in parse_addresses(&addresses) {
+        if let Some(name) = resolve_symbol(pc) {
+            body.push_str(&format!("{:#x} {}\n", pc, name));
+        }
+    }
+
+    text_plain(body)
+}
+
+/// Parse a `+`-separated address list, skipping words that are not addresses
+fn parse_addresses(list: &str) -> impl Iterator<Item = u64> + '_ {
+    list.split('+').map(str::trim).filter_map(parse_address)
+}
+
+/// Parse a `0x`-prefixed hex or plain decimal address, ignoring zero
+///
+/// The same forms Go's `strconv.ParseUint(word, 0, 64)` reads, minus octal.
+fn parse_address(word: &str) -> Option<u64> {
+    let pc = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => word.parse().ok()?,
+    };
+    (pc != 0).then_some(pc)
+}
+
+/// Resolve a program counter in this process to a demangled function name
+fn resolve_symbol(pc: u64) -> Option<String> {
+    let mut name = None;
+    backtrace::resolve(pc as usize as *mut std::ffi::c_void, |symbol| {
+        if name.is_none() {
+            // Alternate formatting drops the trailing `::h<hash>`
+            name = symbol.name().map(|n| format!("{:#}", n));
+        }
+    });
+    name
+}
+
+/// `text/plain; charset=utf-8`, as Go sets it for symbol and cmdline
+fn text_plain(body: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_and_decimal_addresses() {
+        assert_eq!(parse_address("0x55d0c0ffee00"), Some(0x55d0_c0ff_ee00));
+        assert_eq!(parse_address("0X1F"), Some(0x1f));
+        assert_eq!(parse_address("0xffffffffffffffff"), Some(u64::MAX));
+        // Without the prefix the word is decimal, as in Go
+        assert_eq!(parse_address("4096"), Some(4096));
+        assert_eq!(parse_address("1000"), Some(1000));
+    }
+
+    #[test]
+    fn parse_rejects_bad_addresses() {
+        for word in [
+            "",
+            "0x",
+            "0",
+            "0x0",
+            "1f",
+            "0xzz",
+            "0x-1",
+            "-1",
+            "0x10000000000000000",
+            "18446744073709551616",
+            "x10",
+        ] {
+            assert_eq!(parse_address(word), None, "{:?}", word);
+        }
+    }
+
+    #[test]
+    fn parse_plus_separated_lists() {
+        let parse = |list| parse_addresses(list).collect::<Vec<_>>();
+        assert_eq!(parse("0x10+0x20+48"), vec![0x10, 0x20, 48]);
+        // pprof sends a trailing newline; stray separators and junk are skipped
+        assert_eq!(parse("0x10+0x20\n"), vec![0x10, 0x20]);
+        assert_eq!(parse("+0x10++bogus+0x0+ 0x20 "), vec![0x10, 0x20]);
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn resolves_a_function_of_this_binary() {
+        // Like the return addresses pprof sends, point inside the function:
+        // its first byte resolves as the end of the one before it
+        let pc = parse_plus_separated_lists as *const () as u64 + 1;
+        let name = resolve_symbol(pc).unwrap();
+        assert!(name.ends_with("parse_plus_separated_lists"), "{}", name);
+    }
+}
@@ -0,0 +1,402 @@
+//! Named heap snapshots and the diff between two of them
+//!
+//! A single heap dump shows what is live, not what is growing. Snapshots are
+//! parsed jemalloc dumps kept in memory under an id; diffing two of them
+//! subtracts the sampled bytes per allocation stack, so a leak shows up as the
+//! stacks with a positive delta.
+//!
+//! Routes:
+//! - `POST /profile/memory/snapshots?name=<id>` - take a snapshot (the id
+//!   defaults to a sequence number)
+//! - `GET /profile/memory/snapshots` - JSON list of stored snapshots
+//! - `GET /profile/memory/diff?base=<id>&target=<id>` - pprof protobuf of
+//!   `target - base` in-use bytes per allocation stack (positive = growth)
+//!
+//! ```bash
+//! curl -X POST 'http://localhost:8080/profile/memory/snapshots?name=before'
+//! # ... exercise the suspected leak ...
+//! curl -X POST 'http://localhost:8080/profile/memory/snapshots?name=after'
+//! curl 'http://localhost:8080/profile/memory/diff?base=before&target=after' > diff.pb
+//! go tool pprof -top diff.pb
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::io::BufReader;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::{Method, Response, StatusCode};
+use pprof_util::{StackProfile, WeightedStack};
+use serde::Serialize;
+
+use super::memory::lock_active_prof_ctl;
+use crate::http::{
+    attachment_response, bad_request, error_response, json_response, query_param_decoded,
+    text_response, Body,
+};
+
+/// Oldest snapshots are dropped beyond this many
+const MAX_SNAPSHOTS: usize = 16;
+
+/// Longest accepted snapshot name
+const MAX_NAME_LEN: usize = 64;
+
+/// One parsed heap dump
+struct Snapshot {
+    id: String,
+    taken: SystemTime,
+    profile: StackProfile,
+}
+
+impl Snapshot {
+    fn bytes(&self) -> f64 {
+        self.profile
+            .stacks
+            .iter()
+            .map(|(stack, _)| stack.weight)
+            .sum()
+    }
+}
+
+/// Heap snapshots taken through the HTTP routes
+#[derive(Default)]
+pub(crate) struct HeapSnapshots {
+    inner: Mutex<Store>,
+}
+
+#[derive(Default)]
+struct Store {
+    next_id: u64,
+    snapshots: VecDeque<Snapshot>,
+}
+
+#[derive(Serialize)]
+struct SnapshotInfo {
+    id: String,
+    taken_unix_ms: u128,
+    stacks: usize,
+    /// Estimated in-use bytes (jemalloc samples, scaled up)
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct SnapshotList {
+    capacity: usize,
+    snapshots: Vec<SnapshotInfo>,
+}
+
+impl HeapSnapshots {
+    /// Answer the snapshot and diff routes, `None` for any other request
+    pub(crate) async fn route(
+        &self,
+        method: &Method,
+        path: &str,
+        query: Option<&str>,
+    ) -> Option<Response<Body>> {
+        let response = match (method, path) {
+            (&Method::POST, "/profile/memory/snapshots") => self.handle_take(query).await,
+            (&Method::GET, "/profile/memory/snapshots") => self.handle_list(),
+            (&Method::GET, "/profile/memory/diff") => self.handle_diff(query),
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    /// Dump the heap and store it under the requested (or next numeric) id
+    async fn handle_take(&self, query: Option<&str>) -> Response<Body> {
+        let name = query_param_decoded(query, "name");
+        if let Some(name) = &name {
+            if let Err(message) = validate_name(name) {
+                return bad_request(message);
+            }
+            if self.inner.lock().unwrap().position(name).is_some() {
+                return text_response(
+                    StatusCode::CONFLICT,
+                    format!("Error: snapshot {} already exists\n", name),
+                );
+            }
+        }
+
+        let mut prof_ctl_guard = match lock_active_prof_ctl().await {
+            Ok(guard) => guard,
+            Err(response) => return response,
+        };
+
+        let dump = match prof_ctl_guard.dump() {
+            Ok(dump) => dump,
+            Err(e) => {
+                tracing::warn!("Failed to dump heap profile: {}", e);
+                return error_response(format!("Failed to dump heap profile: {}", e));
+            }
+        };
+        drop(prof_ctl_guard);
+
+        let profile =
+            match pprof_util::parse_jeheap(BufReader::new(dump), mappings::MAPPINGS.as_deref()) {
+                Ok(profile) => profile,
+                Err(e) => {
+                    tracing::warn!("Failed to parse heap profile: {}", e);
+                    return error_response(format!("Failed to parse heap profile: {}", e));
+                }
+            };
+
+        let mut store = self.inner.lock().unwrap();
+        let id = match name {
+            Some(name) => name,
+            None => {
+                store.next_id += 1;
+                store.next_id.to_string()
+            }
+        };
+        if store.position(&id).is_some() {
+            // Taken concurrently, or a numeric id chosen by hand earlier
+            return text_response(
+                StatusCode::CONFLICT,
+                format!("Error: snapshot {} already exists\n", id),
+            );
+        }
+
+        let snapshot = Snapshot {
+            id,
+            taken: SystemTime::now(),
+            profile,
+        };
+        let info = snapshot_info(&snapshot);
+        tracing::info!(
+            "Heap snapshot {} taken ({} stacks, {:.2} MB)",
+            info.id,
+            info.stacks,
+            info.bytes as f64 / 1024.0 / 1024.0
+        );
+
+        if let Some(evicted) = store.push(snapshot) {
+            tracing::info!("Dropping oldest heap snapshot {}", evicted.id);
+        }
+
+        json_response(&info)
+    }
+
+    /// List stored snapshots as JSON
+    fn handle_list(&self) -> Response<Body> {
+        let store = self.inner.lock().unwrap();
+        json_response(&SnapshotList {
+            capacity: MAX_SNAPSHOTS,
+            snapshots: store.snapshots.iter().map(snapshot_info).collect(),
+        })
+    }
+
+    /// pprof protobuf of `target - base` per allocation stack
+    fn handle_diff(&self, query: Option<&str>) -> Response<Body> {
+        let (Some(base), Some(target)) = (
+            query_param_decoded(query, "base"),
+            query_param_decoded(query, "target"),
+        ) else {
+            return bad_request("both base and target snapshot ids are required".to_string());
+        };
+
+        let store = self.inner.lock().unwrap();
+        let (Some(base_idx), Some(target_idx)) = (store.position(&base), store.position(&target))
+        else {
+            let missing = if store.position(&base).is_none() {
+                base
+            } else {
+                target
+            };
+            return text_response(
+                StatusCode::NOT_FOUND,
+                format!("Error: no heap snapshot {}\n", missing),
+            );
+        };
+
+        let diff = diff_profiles(
+            &store.snapshots[base_idx].profile,
+            &store.snapshots[target_idx].profile,
+        );
+        drop(store);
+
+        tracing::info!(
+            "Heap diff {} -> {}: {} stacks changed",
+            base,
+            target,
+            diff.stacks.len()
+        );
+
+        let pprof = diff.to_pprof(("inuse_space", "bytes"), ("space", "bytes"), None);
+        attachment_response(pprof, "heap_diff.pb")
+    }
+}
+
+impl Store {
+    fn position(&self, id: &str) -> Option<usize> {
+        self.snapshots.iter().position(|s| s.id == id)
+    }
+
+    /// Store `snapshot`, returning the oldest one if it had to make room
+    fn push(&mut self, snapshot: Snapshot) -> Option<Snapshot> {
+        let evicted = if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front()
+        } else {
+            None
+        };
+        self.snapshots.push_back(snapshot);
+        evicted
+    }
+}
+
+/// Subtract `base` from `target` per stack, dropping stacks that did not change
+///
+/// Both dumps come from this process, so identical address lists mean the
+/// same allocation site and the target's mappings symbolize both.
+fn diff_profiles(base: &StackProfile, target: &StackProfile) -> StackProfile {
+    let mut deltas: HashMap<&[usize], f64> = HashMap::new();
+    for (stack, _) in &target.stacks {
+        *deltas.entry(&stack.addrs).or_default() += stack.weight;
+    }
+    for (stack, _) in &base.stacks {
+        *deltas.entry(&stack.addrs).or_default() -= stack.weight;
+    }
+
+    let mut deltas: Vec<_> = deltas
+        .into_iter()
+        .filter(|(_, weight)| weight.trunc() != 0.0)
+        .collect();
+    // Largest growth first, for a stable and readable output
+    deltas.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut diff = StackProfile::default();
+    for mapping in &target.mappings {
+        diff.push_mapping(mapping.clone());
+    }
+    for (addrs, weight) in deltas {
+        diff.push_stack(
+            WeightedStack {
+                addrs: addrs.to_vec(),
+                weight,
+            },
+            None,
+        );
+    }
+    diff
+}
+
+fn snapshot_info(snapshot: &Snapshot) -> SnapshotInfo {
+    SnapshotInfo {
+        id: snapshot.id.clone(),
+        taken_unix_ms: snapshot
+            .taken
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis(),
+        stacks: snapshot.profile.stacks.len(),
+        bytes: snapshot.bytes() as u64,
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid snapshot name: {} (use up to {} of [A-Za-z0-9._-])",
+            name, MAX_NAME_LEN
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(stacks: &[(&[usize], f64)]) -> StackProfile {
+        let mut profile = StackProfile::default();
+        for (addrs, weight) in stacks {
+            profile.push_stack(
+                WeightedStack {
+                    addrs: addrs.to_vec(),
+                    weight: *weight,
+                },
+                None,
+            );
+        }
+        profile
+    }
+
+    fn weights(profile: &StackProfile) -> Vec<(Vec<usize>, f64)> {
+        profile
+            .stacks
+            .iter()
+            .map(|(stack, _)| (stack.addrs.clone(), stack.weight))
+            .collect()
+    }
+
+    #[test]
+    fn diff_keeps_growth_and_shrink_sorted_by_delta() {
+        let base = profile(&[(&[1, 2], 100.0), (&[3], 500.0), (&[4], 64.0)]);
+        let target = profile(&[(&[1, 2], 400.0), (&[3], 200.0), (&[4], 64.0), (&[5], 32.0)]);
+
+        let diff = diff_profiles(&base, &target);
+        assert_eq!(
+            weights(&diff),
+            vec![(vec![1, 2], 300.0), (vec![5], 32.0), (vec![3], -300.0),]
+        );
+    }
+
+    #[test]
+    fn diff_of_identical_profiles_is_empty() {
+        let stacks: &[(&[usize], f64)] = &[(&[1, 2], 100.0), (&[3], 0.5)];
+        let diff = diff_profiles(&profile(stacks), &profile(stacks));
+        assert!(diff.stacks.is_empty());
+
+        // Sub-byte deltas from sample scaling are not growth either
+        let diff = diff_profiles(&profile(&[(&[1], 10.0)]), &profile(&[(&[1], 10.4)]));
+        assert!(diff.stacks.is_empty());
+    }
+
+    #[test]
+    fn diff_sums_repeated_stacks_and_counts_freed_ones() {
+        let base = profile(&[(&[7], 50.0)]);
+        let target = profile(&[(&[1], 10.0), (&[1], 15.0)]);
+
+        let diff = diff_profiles(&base, &target);
+        assert_eq!(weights(&diff), vec![(vec![1], 25.0), (vec![7], -50.0)]);
+    }
+
+    #[test]
+    fn snapshot_names() {
+        for name in ["before", "after-1", "v1.2_x", &"a".repeat(MAX_NAME_LEN)] {
+            assert_eq!(validate_name(name), Ok(()), "{}", name);
+        }
+        for name in ["", "a b", "../x", "a/b", "é", &"a".repeat(MAX_NAME_LEN + 1)] {
+            assert!(validate_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn store_drops_the_oldest_snapshot_beyond_the_cap() {
+        let mut store = Store::default();
+        for i in 0..MAX_SNAPSHOTS {
+            let evicted = store.push(Snapshot {
+                id: i.to_string(),
+                taken: SystemTime::now(),
+                profile: StackProfile::default(),
+            });
+            assert!(evicted.is_none());
+        }
+
+        let evicted = store.push(Snapshot {
+            id: "latest".to_string(),
+            taken: SystemTime::now(),
+            profile: StackProfile::default(),
+        });
+        assert_eq!(evicted.map(|s| s.id).as_deref(), Some("0"));
+        assert_eq!(store.snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(store.position("0"), None);
+        assert_eq!(store.position("1"), Some(0));
+        assert_eq!(store.position("latest"), Some(MAX_SNAPSHOTS - 1));
+    }
+}
@@ -0,0 +1,186 @@
+//! Heap profiling via jemalloc_pprof
+//!
+//! Requires the application to use jemalloc as its global allocator with
+//! profiling enabled (`_RJEM_MALLOC_CONF=prof:true` or an exported
+//! `malloc_conf`).
+
+use std::time::Duration;
+
+use hyper::{Response, StatusCode};
+
+use super::ProfilingConfig;
+use crate::http::{error_response, parse_seconds_param, text_response, Body};
+use crate::workload;
+
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+use {
+    super::flamegraph::FlamegraphParams,
+    crate::http::{attachment_response, bad_request, svg_response},
+    jemalloc_pprof::JemallocProfCtl,
+    tokio::sync::MutexGuard,
+};
+
+/// Message returned when jemalloc profiling is unavailable or inactive
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+const ENABLE_PROFILING_HINT: &str = "Run with:\n\
+     _RJEM_MALLOC_CONF=prof:true,lg_prof_sample:0,prof_final:false \\\n\
+     RUSTFLAGS=\"-C force-frame-pointers=yes\" \\\n\
+     cargo run --example pprof_http --release";
+
+/// Lock the jemalloc profiling controller, active or not
+///
+/// Errors are already turned into responses so handlers can return them as-is.
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(super) async fn lock_prof_ctl() -> Result<MutexGuard<'static, JemallocProfCtl>, Response<Body>>
+{
+    // Check if jemalloc was started with profiling enabled (`prof:true`)
+    let Some(prof_ctl) = jemalloc_pprof::PROF_CTL.as_ref() else {
+        return Err(error_response(format!(
+            "Profiling controller not available. Ensure jemalloc is properly configured.\n{}",
+            ENABLE_PROFILING_HINT
+        )));
+    };
+
+    Ok(prof_ctl.lock().await)
+}
+
+/// Lock the jemalloc profiling controller, checking that profiling is active
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(super) async fn lock_active_prof_ctl(
+) -> Result<MutexGuard<'static, JemallocProfCtl>, Response<Body>> {
+    let prof_ctl_guard = lock_prof_ctl().await?;
+
+    // Check if profiling is active
+    if !prof_ctl_guard.activated() {
+        tracing::warn!("Jemalloc profiling is not active");
+        return Err(error_response(format!(
+            "Jemalloc profiling is not active.\n{}",
+            ENABLE_PROFILING_HINT
+        )));
+    }
+
+    Ok(prof_ctl_guard)
+}
+
+/// Heap profile endpoint - uses jemalloc heap profiling
+///
+/// Served at `/profile/memory` and the Go-compatible `/debug/pprof/heap`
+/// route. Shows the process as-is: synthetic allocations only come from
+/// `/workload/memory` when it is enabled.
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(crate) async fn handle_heap_profile() -> Response<Body> {
+    tracing::info!("Generating heap memory profile using jemalloc...");
+
+    let mut prof_ctl_guard = match lock_active_prof_ctl().await {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    heap_profile_response(prof_ctl_guard.dump_pprof())
+}
+
+/// Turn the result of a jemalloc pprof dump into a response
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+fn heap_profile_response<E: std::fmt::Display>(result: Result<Vec<u8>, E>) -> Response<Body> {
+    match result {
+        Ok(pprof_data) => {
+            if pprof_data.is_empty() {
+                tracing::warn!("Generated heap profile is empty");
+                return error_response(
+                    "Generated heap profile is empty.\n\
+                     This might happen if no memory is allocated or jemalloc profiling is not working."
+                        .to_string(),
+                );
+            }
+
+            tracing::info!(
+                "Heap profile generated successfully ({} bytes)",
+                pprof_data.len()
+            );
+
+            attachment_response(pprof_data, "heap_profile.pb")
+        }
+        Err(e) => {
+            tracing::warn!("Failed to dump heap profile: {}", e);
+            error_response(format!("Failed to dump heap profile: {}", e))
+        }
+    }
+}
+
+/// Heap flamegraph endpoint - SVG of live heap bytes by allocation stack
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(crate) async fn handle_memory_flamegraph(query: Option<&str>) -> Response<Body> {
+    let params = match FlamegraphParams::from_query(query) {
+        Ok(params) => params,
+        Err(message) => return bad_request(message),
+    };
+
+    let mut opts = jemalloc_pprof::FlamegraphOptions::default();
+    opts.count_name = "bytes".to_string();
+    if let Err(message) = params.apply_heap(&mut opts, "inuse_space") {
+        return bad_request(message);
+    }
+
+    tracing::info!("Generating heap flamegraph using jemalloc...");
+
+    let mut prof_ctl_guard = match lock_active_prof_ctl().await {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    match prof_ctl_guard.dump_flamegraph_with_options(&mut opts) {
+        Ok(svg) => {
+            tracing::info!(
+                "Heap flamegraph generated successfully ({} bytes)",
+                svg.len()
+            );
+            svg_response(svg)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to render heap flamegraph: {}", e);
+            error_response(format!("Failed to render heap flamegraph: {}", e))
+        }
+    }
+}
+
+/// Heap profile endpoint - Windows/MSVC fallback (jemalloc not available)
+#[cfg(any(target_env = "msvc", target_os = "windows"))]
+pub(crate) async fn handle_heap_profile() -> Response<Body> {
+    error_response(
+        "Heap profiling is not available on Windows/MSVC targets. \
+         Use Linux/macOS or consider alternative tools like heaptrack or valgrind."
+            .to_string(),
+    )
+}
+
+/// Heap flamegraph endpoint - Windows/MSVC fallback (jemalloc not available)
+#[cfg(any(target_env = "msvc", target_os = "windows"))]
+pub(crate) async fn handle_memory_flamegraph(_query: Option<&str>) -> Response<Body> {
+    handle_heap_profile().await
+}
+
+/// Synthetic allocations endpoint - holds the demo allocations of
+/// [`workload::create_demo_allocations`] for the requested duration
+///
+/// Only routed when the workload endpoints are enabled.
+pub(crate) async fn handle_memory_load(
+    config: &ProfilingConfig,
+    query: Option<&str>,
+) -> Response<Body> {
+    let seconds =
+        parse_seconds_param(query, config.max_cpu_seconds).unwrap_or(config.default_cpu_seconds);
+
+    tracing::info!("Holding synthetic allocations for {} seconds...", seconds);
+    tokio::spawn(async move {
+        workload::run_memory_load(Duration::from_secs(seconds)).await;
+        tracing::info!("Synthetic allocations released");
+    });
+
+    text_response(
+        StatusCode::ACCEPTED,
+        format!(
+            "Holding synthetic allocations for {} seconds.\nProfile them with: POST /profile/memory\n",
+            seconds
+        ),
+    )
+}
@@ -0,0 +1,505 @@
+//! Prometheus `/metrics` in the text exposition format
+//!
+//! One scrape covers the HTTP requests answered by the service, CPU profiling
+//! sessions, the usual `process_*` metrics, jemalloc statistics, tokio runtime
+//! metrics and the wakes of the [instrumented tasks](crate::diagnostics):
+//!
+//! ```bash
+//! curl http://localhost:8080/metrics
+//! ```
+//!
+//! Requests are labelled by the matched route, never by the raw path, so
+//! scanners probing random URLs all land in `route="other"` instead of
+//! growing the series count.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::sync::Mutex;
+
+use hyper::{Response, StatusCode};
+
+use super::session::{SessionStats, CPU_SESSIONS};
+use crate::diagnostics::TaskWakeStats;
+use crate::http::Body;
+use crate::stats::{MemoryStats, RuntimeStats};
+
+/// Content type of the text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// HTTP request counters by route and status code
+///
+/// [`ProfilingService`](super::ProfilingService) records the requests it
+/// answers; applications routing requests themselves can record theirs into
+/// the same counters via
+/// [`ProfilingService::http_metrics`](super::ProfilingService::http_metrics).
+#[derive(Debug, Default)]
+pub struct HttpMetrics {
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+}
+
+impl HttpMetrics {
+    /// Count one request to `route` answered with `status`
+    ///
+    /// `route` becomes a label value: pass the route pattern, not the raw
+    /// request path.
+    pub fn record(&self, route: &str, status: StatusCode) {
+        let mut requests = self.requests.lock().unwrap();
+        *requests
+            .entry((route.to_string(), status.as_u16()))
+            .or_insert(0) += 1;
+    }
+
+    /// Requests recorded so far, across all routes
+    pub fn total(&self) -> u64 {
+        self.requests.lock().unwrap().values().sum()
+    }
+}
+
+/// Render every metric family
+pub(crate) fn handle_metrics(http: &HttpMetrics) -> Response<Body> {
+    let mut out = Exposition::default();
+
+    write_http(&mut out, http);
+    write_sessions(&mut out, CPU_SESSIONS.stats());
+    write_process(&mut out);
+    if let Ok(memory) = MemoryStats::read() {
+        write_memory(&mut out, &memory);
+    }
+    write_runtime(&mut out, &RuntimeStats::current());
+    write_wakes(&mut out, &TaskWakeStats::totals());
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", CONTENT_TYPE)
+        .body(Body::from(out.text))
+        .unwrap()
+}
+
+fn write_http(out: &mut Exposition, http: &HttpMetrics) {
+    out.family(
+        "http_requests_total",
+        "counter",
+        "HTTP requests by route and status code",
+    );
+    for ((route, status), count) in http.requests.lock().unwrap().iter() {
+        out.sample(
+            "http_requests_total",
+            &[("route", route), ("status", &status.to_string())],
+            count,
+        );
+    }
+}
+
+fn write_sessions(out: &mut Exposition, stats: SessionStats) {
+    let sources = [("request", stats.request), ("continuous", stats.continuous)];
+
+    out.family(
+        "profiling_cpu_sessions_total",
+        "counter",
+        "Finished CPU profiling sessions",
+    );
+    for (source, totals) in sources {
+        out.sample(
+            "profiling_cpu_sessions_total",
+            &[("source", source)],
+            totals.count,
+        );
+    }
+
+    out.family(
+        "profiling_cpu_session_seconds_total",
+        "counter",
+        "Time spent in finished CPU profiling sessions",
+    );
+    for (source, totals) in sources {
+        out.sample(
+            "profiling_cpu_session_seconds_total",
+            &[("source", source)],
+            totals.seconds,
+        );
+    }
+
+    out.family(
+        "profiling_cpu_sessions_rejected_total",
+        "counter",
+        "CPU profile requests rejected with 429 because a session was active",
+    );
+    out.sample("profiling_cpu_sessions_rejected_total", &[], stats.rejected);
+
+    out.family(
+        "profiling_cpu_session_active",
+        "gauge",
+        "Whether a CPU profiling session is running",
+    );
+    out.sample("profiling_cpu_session_active", &[], u8::from(stats.active));
+}
+
+/// The `process_*` families of the official Prometheus clients
+///
+/// Each one is left out where it cannot be read: resident memory and open
+/// descriptors come from `/proc`, so they are Linux only.
+fn write_process(out: &mut Exposition) {
+    if let Some(seconds) = process::cpu_seconds() {
+        out.family(
+            "process_cpu_seconds_total",
+            "counter",
+            "User and system CPU time spent by the process",
+        );
+        out.sample("process_cpu_seconds_total", &[], seconds);
+    }
+    if let Some(bytes) = process::resident_memory_bytes() {
+        out.family(
+            "process_resident_memory_bytes",
+            "gauge",
+            "Resident memory size of the process",
+        );
+        out.sample("process_resident_memory_bytes", &[], bytes);
+    }
+    if let Some(fds) = process::open_fds() {
+        out.family(
+            "process_open_fds",
+            "gauge",
+            "Open file descriptors of the process",
+        );
+        out.sample("process_open_fds", &[], fds);
+    }
+}
+
+mod process {
+    /// User plus system CPU time of the whole process
+    #[cfg(unix)]
+    pub(super) fn cpu_seconds() -> Option<f64> {
+        // SAFETY: getrusage only writes to the struct it is given
+        let usage = unsafe {
+            let mut usage: libc::rusage = std::mem::zeroed();
+            if libc::getrusage(libc::RUSAGE_SELF, &mut usage) != 0 {
+                return None;
+            }
+            usage
+        };
+        let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1e6;
+        Some(seconds(usage.ru_utime) + seconds(usage.ru_stime))
+    }
+
+    #[cfg(not(unix))]
+    pub(super) fn cpu_seconds() -> Option<f64> {
+        None
+    }
+
+    /// Resident pages from `/proc/self/statm`, in bytes
+    #[cfg(target_os = "linux")]
+    pub(super) fn resident_memory_bytes() -> Option<u64> {
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        // SAFETY: no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        Some(pages * u64::try_from(page_size).ok()?)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn resident_memory_bytes() -> Option<u64> {
+        None
+    }
+
+    /// Entries of `/proc/self/fd`, not counting the one listing it
+    #[cfg(target_os = "linux")]
+    pub(super) fn open_fds() -> Option<u64> {
+        let entries = std::fs::read_dir("/proc/self/fd").ok()?.count() as u64;
+        Some(entries.saturating_sub(1))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn open_fds() -> Option<u64> {
+        None
+    }
+}
+
+fn write_memory(out: &mut Exposition, memory: &MemoryStats) {
+    let gauges = [
+        (
+            "jemalloc_allocated_bytes",
+            "Bytes allocated by the application",
+            memory.allocated,
+        ),
+        (
+            "jemalloc_active_bytes",
+            "Bytes in active pages",
+            memory.active,
+        ),
+        (
+            "jemalloc_resident_bytes",
+            "Bytes in physically resident pages",
+            memory.resident,
+        ),
+        (
+            "jemalloc_mapped_bytes",
+            "Bytes in active extents mapped by the allocator",
+            memory.mapped,
+        ),
+        (
+            "jemalloc_retained_bytes",
+            "Bytes retained for reuse, not returned to the OS",
+            memory.retained,
+        ),
+        (
+            "jemalloc_metadata_bytes",
+            "Bytes dedicated to allocator metadata",
+            memory.metadata,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        out.family(name, "gauge", help);
+        out.sample(name, &[], value);
+    }
+}
+
+fn write_runtime(out: &mut Exposition, runtime: &RuntimeStats) {
+    out.family("tokio_workers", "gauge", "Worker threads of the runtime");
+    out.sample("tokio_workers", &[], runtime.num_workers);
+    out.family(
+        "tokio_alive_tasks",
+        "gauge",
+        "Tasks spawned and not yet finished",
+    );
+    out.sample("tokio_alive_tasks", &[], runtime.num_alive_tasks);
+    out.family(
+        "tokio_global_queue_depth",
+        "gauge",
+        "Tasks waiting in the shared injection queue",
+    );
+    out.sample("tokio_global_queue_depth", &[], runtime.global_queue_depth);
+
+    let optional = [
+        (
+            "tokio_blocking_threads",
+            "gauge",
+            "Threads of the blocking pool",
+            runtime.num_blocking_threads,
+        ),
+        (
+            "tokio_idle_blocking_threads",
+            "gauge",
+            "Idle threads of the blocking pool",
+            runtime.num_idle_blocking_threads,
+        ),
+        (
+            "tokio_blocking_queue_depth",
+            "gauge",
+            "Tasks waiting for a blocking thread",
+            runtime.blocking_queue_depth,
+        ),
+    ];
+    for (name, kind, help, value) in optional {
+        if let Some(value) = value {
+            out.family(name, kind, help);
+            out.sample(name, &[], value);
+        }
+    }
+    if let Some(spawned) = runtime.spawned_tasks_count {
+        out.family(
+            "tokio_spawned_tasks_total",
+            "counter",
+            "Tasks spawned since start",
+        );
+        out.sample("tokio_spawned_tasks_total", &[], spawned);
+    }
+
+    let workers: Vec<_> = runtime
+        .workers
+        .iter()
+        .map(|w| (w.worker.to_string(), w))
+        .collect();
+
+    out.family(
+        "tokio_worker_park_total",
+        "counter",
+        "Times a worker went idle",
+    );
+    for (worker, stats) in &workers {
+        out.sample(
+            "tokio_worker_park_total",
+            &[("worker", worker)],
+            stats.park_count,
+        );
+    }
+    out.family(
+        "tokio_worker_busy_seconds_total",
+        "counter",
+        "Time a worker spent running tasks",
+    );
+    for (worker, stats) in &workers {
+        out.sample(
+            "tokio_worker_busy_seconds_total",
+            &[("worker", worker)],
+            stats.busy_duration_ms / 1000.0,
+        );
+    }
+
+    // Only available with --cfg tokio_unstable
+    if !runtime.unstable_metrics {
+        return;
+    }
+    out.family(
+        "tokio_worker_local_queue_depth",
+        "gauge",
+        "Tasks waiting in a worker's run queue",
+    );
+    for (worker, stats) in &workers {
+        if let Some(depth) = stats.local_queue_depth {
+            out.sample(
+                "tokio_worker_local_queue_depth",
+                &[("worker", worker)],
+                depth,
+            );
+        }
+    }
+    out.family(
+        "tokio_worker_steal_total",
+        "counter",
+        "Tasks a worker stole from other workers",
+    );
+    for (worker, stats) in &workers {
+        if let Some(steals) = stats.steal_count {
+            out.sample("tokio_worker_steal_total", &[("worker", worker)], steals);
+        }
+    }
+    out.family(
+        "tokio_worker_polls_total",
+        "counter",
+        "Task polls by a worker",
+    );
+    for (worker, stats) in &workers {
+        if let Some(polls) = stats.poll_count {
+            out.sample("tokio_worker_polls_total", &[("worker", worker)], polls);
+        }
+    }
+    out.family(
+        "tokio_worker_mean_poll_time_seconds",
+        "gauge",
+        "Moving average of a worker's task poll duration",
+    );
+    for (worker, stats) in &workers {
+        if let Some(mean) = stats.mean_poll_time_us {
+            out.sample(
+                "tokio_worker_mean_poll_time_seconds",
+                &[("worker", worker)],
+                mean / 1_000_000.0,
+            );
+        }
+    }
+}
+
+fn write_wakes(out: &mut Exposition, wakes: &[TaskWakeStats]) {
+    out.family(
+        "async_task_wakes_total",
+        "counter",
+        "Wakes of instrumented tasks by spawn location, from their own poll (self) or not \
+         (external)",
+    );
+    for stats in wakes {
+        for (kind, count) in [
+            ("self", stats.self_wakes),
+            ("external", stats.external_wakes),
+        ] {
+            out.sample(
+                "async_task_wakes_total",
+                &[("location", &stats.location), ("kind", kind)],
+                count,
+            );
+        }
+    }
+}
+
+/// Text exposition writer
+#[derive(Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// `# HELP` and `# TYPE` header of a metric family
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            self.text.push('{');
+            for (i, (key, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.text.push(',');
+                }
+                let _ = write!(self.text, "{}=\"{}\"", key, escape_label(value));
+            }
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+}
+
+/// Escape `\`, `"` and newlines in a label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape_label("GET /metrics"), "GET /metrics");
+        assert_eq!(escape_label(r"C:\tmp"), r"C:\\tmp");
+        assert_eq!(escape_label(r#"say "hi""#), r#"say \"hi\""#);
+        assert_eq!(escape_label("a\nb"), r"a\nb");
+        assert_eq!(escape_label("\\\"\n"), r#"\\\"\n"#);
+    }
+
+    #[test]
+    fn renders_families_and_samples() {
+        let mut out = Exposition::default();
+        out.family("requests_total", "counter", "Requests served");
+        out.sample("requests_total", &[("route", "/a"), ("status", "200")], 3);
+        out.sample("requests_total", &[("route", "say \"hi\"")], 1);
+        out.family("queue_depth", "gauge", "Queued tasks");
+        out.sample("queue_depth", &[], 0.5);
+
+        assert_eq!(
+            out.text,
+            "# HELP requests_total Requests served\n\
+             # TYPE requests_total counter\n\
+             requests_total{route=\"/a\",status=\"200\"} 3\n\
+             requests_total{route=\"say \\\"hi\\\"\"} 1\n\
+             # HELP queue_depth Queued tasks\n\
+             # TYPE queue_depth gauge\n\
+             queue_depth 0.5\n"
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn process_metrics() {
+        let cpu = process::cpu_seconds().unwrap();
+        assert!(cpu >= 0.0, "{}", cpu);
+        assert!(process::resident_memory_bytes().unwrap() > 0);
+
+        // The test harness has its standard streams open at least
+        let fds = process::open_fds().unwrap();
+        assert!(fds >= 3, "{}", fds);
+
+        let mut out = Exposition::default();
+        write_process(&mut out);
+        for line in [
+            "# TYPE process_cpu_seconds_total counter\n",
+            "# TYPE process_resident_memory_bytes gauge\n",
+            "# TYPE process_open_fds gauge\n",
+        ] {
+            assert!(out.text.contains(line), "{}", out.text);
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Mountable HTTP profiling service
+//!
+//! [`ProfilingService`] is a hyper [`Service`] that answers the profiling
+//! routes and returns 404 for everything else. Applications can either run it
+//! on a dedicated listener with [`ProfilingService::serve`], or route their
+//! own requests first and fall back to [`ProfilingService::handle`].
+//!
+//! Routes:
+//! - `POST /profile/cpu?seconds=<n>` - CPU profile (pprof protobuf)
+//! - `GET|POST /profile/cpu/flamegraph?seconds=<n>` - CPU flamegraph (SVG), see
+//!   the `flamegraph` module for `title`, `min_width`, `reverse` and `palette`
+//! - `POST /profile/memory`          - Heap profile (jemalloc, pprof protobuf)
+//! - `GET|POST /profile/memory/flamegraph` - Live heap bytes flamegraph (SVG),
+//!   same options as the CPU flamegraph
+//! - `POST|GET /profile/memory/snapshots`, `GET /profile/memory/diff?base=<id>&target=<id>` -
+//!   Named heap snapshots and the per-stack growth between two of them
+//! - `GET|POST /profile/memory/sampling[?active=<bool>]`,
+//!   `POST /profile/memory/sampling/reset?lg_prof_sample=<n>` - Toggle jemalloc
+//!   sampling and change its rate at runtime
+//! - `GET /stats/memory`             - jemalloc allocator statistics (JSON), see
+//!   [`MemoryStats`](crate::stats::MemoryStats)
+//! - `GET /stats/runtime`            - tokio scheduler metrics (JSON), see
+//!   [`RuntimeStats`](crate::stats::RuntimeStats)
+//! - `GET /metrics`                  - Prometheus text exposition: HTTP requests,
+//!   CPU profiling sessions, jemalloc, tokio runtime and task wake metrics
+//! - `POST /workload/cpu?seconds=<n>`, `POST /workload/memory?seconds=<n>` -
+//!   Synthetic CPU load and heap allocations, only when enabled with
+//!   [`ProfilingServiceBuilder::workload_endpoints`]
+//! - `GET /debug/pprof/`, `/debug/pprof/profile?seconds=<n>`, `/debug/pprof/heap`,
+//!   `/debug/pprof/symbol`, `/debug/pprof/cmdline` - Go `net/http/pprof` layout,
+//!   so `go tool pprof http://host/debug/pprof/profile` works unmodified
+//! - `GET /debug/tasks[?format=json]` - Async backtrace of every task, with the
+//!   `tokio_unstable` cargo feature (see the `task_dump` module)
+//! - `GET /debug/async/polls` - Poll duration statistics of the tasks spawned
+//!   through [`diagnostics`](crate::diagnostics)
+//! - `GET /debug/async/idle` - Instrumented tasks idle past their threshold
+//! - `GET /debug/async/locks` - Holders and waiters of the instrumented mutexes
+//! - `GET /debug/async/channels` - Handle owners and blocked tasks of the
+//!   instrumented channels
+//! - `GET /debug/async/sizes` - Spawned future sizes by spawn location
+//!
+//! - `GET /profile/cpu/continuous[/windows|/flamegraph]` - Rolling windows from
+//!   the background profiler, only when enabled with
+//!   [`ProfilingServiceBuilder::continuous_profiling`]
+//!
+//! Only one CPU profile can be collected at a time per process; concurrent
+//! requests are rejected with `429` + `Retry-After` or queued, see [`BusyPolicy`].
+//!
+//! The CPU profile only samples the process; it never generates load itself,
+//! so profiles taken in production show what the service was really doing.
+//!
+//! ```no_run
+//! use tokio_console_demo::profiling::ProfilingService;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:6060").await?;
+//! ProfilingService::builder()
+//!     .default_cpu_seconds(10)
+//!     .build()
+//!     .serve(listener)
+//!     .await
+//! # }
+//! ```
+
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+mod continuous;
+mod cpu;
+mod flamegraph;
+mod go_compat;
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+mod heap_snapshots;
+mod memory;
+mod metrics;
+mod sampling;
+mod session;
+mod task_dump;
+
+pub use metrics::HttpMetrics;
+pub use session::BusyPolicy;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::body::Incoming;
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use tokio::net::TcpListener;
+
+use crate::diagnostics;
+use crate::http::{error_response, json_response, not_found, Body};
+use crate::stats::{MemoryStats, RuntimeStats};
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+use continuous::ContinuousProfiler;
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+use heap_snapshots::HeapSnapshots;
+
+/// Settings shared by all profiling handlers
+#[derive(Debug, Clone)]
+pub(crate) struct ProfilingConfig {
+    /// CPU sampling frequency in Hz
+    pub(crate) cpu_frequency: i32,
+    /// Duration used when `seconds` is missing or invalid
+    pub(crate) default_cpu_seconds: u64,
+    /// Upper bound accepted for `seconds`
+    pub(crate) max_cpu_seconds: u64,
+    /// Serve the synthetic `/workload/*` routes
+    pub(crate) workload_endpoints: bool,
+    /// Handling of CPU profile requests while another one is running
+    pub(crate) busy_policy: BusyPolicy,
+    /// Background profiling into a ring buffer of fixed windows
+    pub(crate) continuous: Option<ContinuousConfig>,
+}
+
+impl Default for ProfilingConfig {
+    fn default() -> Self {
+        Self {
+            // 100 Hz is more reliable than higher frequencies
+            cpu_frequency: 100,
+            // Same as Go's `/debug/pprof/profile`
+            default_cpu_seconds: 30,
+            max_cpu_seconds: 300, // Max 5 minutes
+            workload_endpoints: false,
+            busy_policy: BusyPolicy::Reject,
+            continuous: None,
+        }
+    }
+}
+
+/// Window length and ring buffer capacity of the continuous profiler
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ContinuousConfig {
+    pub(crate) window: Duration,
+    pub(crate) capacity: usize,
+}
+
+/// Builder for [`ProfilingService`]
+#[derive(Debug, Default)]
+pub struct ProfilingServiceBuilder {
+    config: ProfilingConfig,
+}
+
+impl ProfilingServiceBuilder {
+    /// CPU sampling frequency in Hz (default: 100)
+    pub fn cpu_frequency(mut self, hz: i32) -> Self {
+        self.config.cpu_frequency = hz;
+        self
+    }
+
+    /// Profiling duration when the request has no valid `seconds` (default: 30,
+    /// like Go's `net/http/pprof`)
+    pub fn default_cpu_seconds(mut self, seconds: u64) -> Self {
+        self.config.default_cpu_seconds = seconds;
+        self
+    }
+
+    /// Longest profiling duration a request may ask for (default: 300)
+    pub fn max_cpu_seconds(mut self, seconds: u64) -> Self {
+        self.config.max_cpu_seconds = seconds;
+        self
+    }
+
+    /// What to do with a CPU profile request while another is running
+    /// (default: [`BusyPolicy::Reject`])
+    pub fn busy_policy(mut self, policy: BusyPolicy) -> Self {
+        self.config.busy_policy = policy;
+        self
+    }
+
+    /// Profile continuously in `window`-long slices, keeping the last `retained`
+    ///
+    /// The background task starts in [`build`](Self::build), which must then
+    /// run inside a tokio runtime, and stops when the service is dropped. As
+    /// the profiler is almost always busy, on-demand CPU profiles are queued
+    /// behind the current window regardless of [`busy_policy`](Self::busy_policy).
+    /// Ignored on targets without pprof-rs support.
+    pub fn continuous_profiling(mut self, window: Duration, retained: usize) -> Self {
+        self.config.continuous = Some(ContinuousConfig {
+            window,
+            capacity: retained.max(1),
+        });
+        self
+    }
+
+    /// Serve `POST /workload/cpu` and `POST /workload/memory` for demos
+    /// (default: false)
+    ///
+    /// Never enable this in production: anyone reaching the endpoints can burn
+    /// four cores, or hold a few hundred MB, for up to `max_cpu_seconds`.
+    pub fn workload_endpoints(mut self, enabled: bool) -> Self {
+        self.config.workload_endpoints = enabled;
+        self
+    }
+
+    pub fn build(self) -> ProfilingService {
+        #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+        let continuous = self
+            .config
+            .continuous
+            .map(|config| ContinuousProfiler::start(config, self.config.cpu_frequency));
+
+        ProfilingService {
+            config: Arc::new(self.config),
+            http_metrics: Arc::default(),
+            #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+            continuous,
+            #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+            heap_snapshots: Arc::default(),
+        }
+    }
+}
+
+/// HTTP service exposing CPU and heap profiling endpoints
+///
+/// Cheap to clone; clones share the same configuration, metrics, continuous
+/// profiler and heap snapshots.
+#[derive(Clone)]
+pub struct ProfilingService {
+    config: Arc<ProfilingConfig>,
+    http_metrics: Arc<HttpMetrics>,
+    #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+    continuous: Option<Arc<ContinuousProfiler>>,
+    #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+    heap_snapshots: Arc<HeapSnapshots>,
+}
+
+impl std::fmt::Debug for ProfilingService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProfilingService")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ProfilingService {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl ProfilingService {
+    pub fn builder() -> ProfilingServiceBuilder {
+        ProfilingServiceBuilder::default()
+    }
+
+    /// Route a request to the matching profiling handler
+    ///
+    /// Unknown routes get a 404 response.
+    pub async fn handle(&self, req: Request<Incoming>) -> Response<Body> {
+        let route = req.uri().path().to_string();
+        match self.route(req).await {
+            Some(response) => {
+                self.http_metrics.record(&route, response.status());
+                response
+            }
+            None => {
+                self.http_metrics.record("other", StatusCode::NOT_FOUND);
+                not_found()
+            }
+        }
+    }
+
+    /// Counters of the HTTP requests answered so far, exported by `/metrics`
+    ///
+    /// Applications serving their own routes next to the profiling ones can
+    /// record them here too, so one scrape covers the whole server.
+    pub fn http_metrics(&self) -> &HttpMetrics {
+        &self.http_metrics
+    }
+
+    /// Answer a profiling route, `None` if the path is not one
+    async fn route(&self, req: Request<Incoming>) -> Option<Response<Body>> {
+        let query = req.uri().query();
+
+        #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+        if let Some(continuous) = &self.continuous {
+            if let Some(response) = continuous.route(req.method(), req.uri().path(), query) {
+                return Some(response);
+            }
+        }
+
+        #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+        if let Some(response) = self
+            .heap_snapshots
+            .route(req.method(), req.uri().path(), query)
+            .await
+        {
+            return Some(response);
+        }
+
+        let response = match (req.method(), req.uri().path()) {
+            (&Method::POST, "/profile/cpu") => cpu::handle_cpu_profile(&self.config, query).await,
+            (&Method::GET | &Method::POST, "/profile/cpu/flamegraph") => {
+                cpu::handle_cpu_flamegraph(&self.config, query).await
+            }
+            (&Method::POST, "/profile/memory") => memory::handle_heap_profile().await,
+            (&Method::GET | &Method::POST, "/profile/memory/flamegraph") => {
+                memory::handle_memory_flamegraph(query).await
+            }
+            (&Method::GET, "/profile/memory/sampling") => sampling::handle_get_sampling().await,
+            (&Method::POST, "/profile/memory/sampling") => {
+                sampling::handle_set_sampling(query).await
+            }
+            (&Method::POST, "/profile/memory/sampling/reset") => {
+                sampling::handle_reset_sampling(query).await
+            }
+            (&Method::GET, "/debug/pprof") => go_compat::redirect_to_index(),
+            (&Method::GET, "/debug/pprof/") => go_compat::handle_index(),
+            (&Method::GET, "/debug/pprof/profile") => {
+                cpu::handle_cpu_profile(&self.config, query).await
+            }
+            (&Method::GET, "/debug/pprof/heap") => memory::handle_heap_profile().await,
+            (&Method::GET | &Method::POST, "/debug/pprof/symbol") => {
+                go_compat::handle_symbol(req).await
+            }
+            (&Method::GET, "/debug/pprof/cmdline") => go_compat::handle_cmdline(),
+            (&Method::GET, "/debug/tasks") => task_dump::handle_task_dump(query).await,
+            (&Method::GET, "/debug/async/polls") => diagnostics::handle_polls(),
+            (&Method::GET, "/debug/async/idle") => diagnostics::handle_idle(),
+            (&Method::GET, "/debug/async/locks") => diagnostics::handle_locks(),
+            (&Method::GET, "/debug/async/channels") => diagnostics::handle_channels(),
+            (&Method::GET, "/debug/async/sizes") => diagnostics::handle_sizes(),
+            (&Method::GET, "/stats/memory") => handle_memory_stats(),
+            (&Method::GET, "/stats/runtime") => json_response(&RuntimeStats::current()),
+            (&Method::GET, "/metrics") => metrics::handle_metrics(&self.http_metrics),
+            (&Method::POST, "/workload/cpu") if self.config.workload_endpoints => {
+                cpu::handle_cpu_load(&self.config, query).await
+            }
+            (&Method::POST, "/workload/memory") if self.config.workload_endpoints => {
+                memory::handle_memory_load(&self.config, query).await
+            }
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    /// Accept connections on `listener` and serve the profiling routes
+    ///
+    /// Runs until accepting a connection fails.
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let service = self.clone();
+            let io = TokioIo::new(stream);
+
+            tokio::spawn(async move {
+                if let Err(err) = Builder::new(TokioExecutor::new())
+                    .serve_connection(io, service)
+                    .await
+                {
+                    tracing::warn!("Error serving connection from {}: {:?}", addr, err);
+                }
+            });
+        }
+    }
+}
+
+/// Current jemalloc statistics as JSON
+fn handle_memory_stats() -> Response<Body> {
+    match MemoryStats::read() {
+        Ok(stats) => json_response(&stats),
+        Err(message) => error_response(message),
+    }
+}
+
+impl Service<Request<Incoming>> for ProfilingService {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move { Ok(service.handle(req).await) })
+    }
+}
@@ -0,0 +1,151 @@
+//! Runtime control of jemalloc heap sampling
+//!
+//! `lg_prof_sample` and `prof_active` are normally fixed at startup through
+//! `malloc_conf` or `_RJEM_MALLOC_CONF`. These routes change them on a running
+//! process, so sampling can be cranked up for an investigation and turned back
+//! down afterwards without a restart:
+//!
+//! - `GET /profile/memory/sampling` - current state (JSON)
+//! - `POST /profile/memory/sampling?active=<bool>` - start or stop sampling
+//! - `POST /profile/memory/sampling/reset?lg_prof_sample=<n>` - discard all
+//!   samples and continue at one sample every 2^n bytes on average
+//!
+//! jemalloc must still be started with `prof:true`; only sampling can be
+//! toggled at runtime, not the profiling machinery itself.
+//!
+//! Both deactivating and resetting discard the collected samples, so heap
+//! snapshots taken before and after are not comparable.
+
+use hyper::Response;
+
+use crate::http::{error_response, Body};
+
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+use {
+    super::memory::lock_prof_ctl,
+    crate::http::{bad_request, json_response, query_param},
+    serde::Serialize,
+};
+
+/// Largest accepted `lg_prof_sample`; jemalloc clamps anything above to 63
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+const MAX_LG_PROF_SAMPLE: usize = 63;
+
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+#[derive(Serialize)]
+struct SamplingState {
+    active: bool,
+    lg_prof_sample: usize,
+    /// Average bytes allocated between two samples (2^lg_prof_sample)
+    sample_interval_bytes: u64,
+}
+
+/// Current sampling state
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(crate) async fn handle_get_sampling() -> Response<Body> {
+    match lock_prof_ctl().await {
+        Ok(prof_ctl_guard) => sampling_response(&prof_ctl_guard),
+        Err(response) => response,
+    }
+}
+
+/// Start or stop heap sampling (`prof.active`)
+///
+/// Stopping also resets the collected samples, as jemalloc_pprof does.
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(crate) async fn handle_set_sampling(query: Option<&str>) -> Response<Body> {
+    let active = match query_param(query, "active") {
+        Some("1" | "true" | "yes" | "on") => true,
+        Some("0" | "false" | "no" | "off") => false,
+        Some(value) => return bad_request(format!("invalid active value: {}", value)),
+        None => return bad_request("missing active=true|false".to_string()),
+    };
+
+    let mut prof_ctl_guard = match lock_prof_ctl().await {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    let result = if active {
+        prof_ctl_guard.activate()
+    } else {
+        prof_ctl_guard.deactivate()
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to set jemalloc prof.active: {}", e);
+        return error_response(format!("Failed to set jemalloc prof.active: {}", e));
+    }
+
+    tracing::info!(
+        "Jemalloc heap sampling {}",
+        if active { "activated" } else { "deactivated" }
+    );
+    sampling_response(&prof_ctl_guard)
+}
+
+/// Discard all samples and continue with a new sampling rate (`prof.reset`)
+///
+/// Without `lg_prof_sample` the current rate is kept.
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+pub(crate) async fn handle_reset_sampling(query: Option<&str>) -> Response<Body> {
+    let lg_prof_sample = match query_param(query, "lg_prof_sample") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(lg) if lg <= MAX_LG_PROF_SAMPLE => Some(lg),
+            _ => {
+                return bad_request(format!(
+                    "invalid lg_prof_sample: {} (expected 0-{})",
+                    value, MAX_LG_PROF_SAMPLE
+                ))
+            }
+        },
+        None => None,
+    };
+
+    let prof_ctl_guard = match lock_prof_ctl().await {
+        Ok(guard) => guard,
+        Err(response) => return response,
+    };
+
+    let lg_prof_sample = lg_prof_sample.unwrap_or_else(|| prof_ctl_guard.lg_sample());
+    // SAFETY: "prof.reset" is documented as being writable and taking a size_t:
+    // http://jemalloc.net/jemalloc.3.html#prof.reset
+    let result = unsafe { tikv_jemalloc_ctl::raw::write(b"prof.reset\0", lg_prof_sample) };
+    if let Err(e) = result {
+        tracing::warn!("Failed to reset jemalloc profile: {}", e);
+        return error_response(format!("Failed to reset jemalloc profile: {}", e));
+    }
+
+    tracing::info!(
+        "Jemalloc heap profile reset, sampling every 2^{} bytes",
+        lg_prof_sample
+    );
+    sampling_response(&prof_ctl_guard)
+}
+
+#[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+fn sampling_response(prof_ctl: &jemalloc_pprof::JemallocProfCtl) -> Response<Body> {
+    let lg_prof_sample = prof_ctl.lg_sample();
+    json_response(&SamplingState {
+        active: prof_ctl.activated(),
+        lg_prof_sample,
+        sample_interval_bytes: 1u64 << lg_prof_sample,
+    })
+}
+
+/// Sampling control - Windows/MSVC fallback (jemalloc not available)
+#[cfg(any(target_env = "msvc", target_os = "windows"))]
+pub(crate) async fn handle_get_sampling() -> Response<Body> {
+    error_response("Heap profiling is not available on Windows/MSVC targets.".to_string())
+}
+
+/// Sampling control - Windows/MSVC fallback (jemalloc not available)
+#[cfg(any(target_env = "msvc", target_os = "windows"))]
+pub(crate) async fn handle_set_sampling(_query: Option<&str>) -> Response<Body> {
+    handle_get_sampling().await
+}
+
+/// Sampling control - Windows/MSVC fallback (jemalloc not available)
+#[cfg(any(target_env = "msvc", target_os = "windows"))]
+pub(crate) async fn handle_reset_sampling(_query: Option<&str>) -> Response<Body> {
+    handle_get_sampling().await
+}
@@ -0,0 +1,322 @@
+//! Process-wide CPU profiling session manager
+//!
+//! pprof-rs allows only one active profiler per process, so a second
+//! `ProfilerGuard::new` fails while another request is still sampling. Every
+//! CPU profile goes through [`CPU_SESSIONS`] instead, which either queues the
+//! caller or reports how long the active session has left, depending on the
+//! configured [`BusyPolicy`].
+
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use hyper::{Response, StatusCode};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::http::Body;
+
+/// What to do with a CPU profile request while another session is active
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BusyPolicy {
+    /// Answer `429 Too Many Requests` with a `Retry-After` header set to the
+    /// remaining seconds of the active session
+    #[default]
+    Reject,
+    /// Queue the request (FIFO) until the active session finishes
+    Wait,
+}
+
+/// Who started a CPU profiling session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SessionSource {
+    /// An HTTP profile or flamegraph request
+    Request,
+    /// The background continuous profiler
+    Continuous,
+}
+
+/// The single CPU profiling slot of this process
+pub(crate) static CPU_SESSIONS: CpuSessions = CpuSessions::new();
+
+/// Serializes CPU profiling sessions and tracks the active one
+pub(crate) struct CpuSessions {
+    slot: Mutex<()>,
+    active: StdMutex<Option<ActiveSession>>,
+    stats: StdMutex<SessionStats>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveSession {
+    started: Instant,
+    duration: Duration,
+    source: SessionSource,
+}
+
+/// Finished sessions of one source
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SessionTotals {
+    pub(crate) count: u64,
+    pub(crate) seconds: f64,
+}
+
+/// Cumulative session counters, exported by `/metrics`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SessionStats {
+    pub(crate) request: SessionTotals,
+    pub(crate) continuous: SessionTotals,
+    /// Requests answered with `429` under [`BusyPolicy::Reject`]
+    pub(crate) rejected: u64,
+    pub(crate) active: bool,
+}
+
+/// Proof of holding the profiling slot; the slot is released on drop
+pub(crate) struct CpuSession {
+    sessions: &'static CpuSessions,
+    _slot: MutexGuard<'static, ()>,
+}
+
+/// The slot is taken; `remaining` is how long the active session has left
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Busy {
+    pub(crate) remaining: Duration,
+}
+
+impl CpuSessions {
+    const fn new() -> Self {
+        Self {
+            slot: Mutex::const_new(()),
+            active: StdMutex::new(None),
+            stats: StdMutex::new(SessionStats {
+                request: SessionTotals {
+                    count: 0,
+                    seconds: 0.0,
+                },
+                continuous: SessionTotals {
+                    count: 0,
+                    seconds: 0.0,
+                },
+                rejected: 0,
+                active: false,
+            }),
+        }
+    }
+
+    /// Start a session of `duration` now, or report the active one
+    pub(crate) fn try_begin(
+        &'static self,
+        duration: Duration,
+        source: SessionSource,
+    ) -> Result<CpuSession, Busy> {
+        match self.slot.try_lock() {
+            Ok(slot) => Ok(self.started(slot, duration, source)),
+            Err(_) => Err(Busy {
+                remaining: self.remaining(),
+            }),
+        }
+    }
+
+    /// Wait for the slot, then start a session of `duration`
+    pub(crate) async fn begin(
+        &'static self,
+        duration: Duration,
+        source: SessionSource,
+    ) -> CpuSession {
+        let slot = self.slot.lock().await;
+        self.started(slot, duration, source)
+    }
+
+    /// Acquire the slot for an HTTP request according to `policy`
+    pub(crate) async fn acquire(
+        &'static self,
+        policy: BusyPolicy,
+        duration: Duration,
+    ) -> Result<CpuSession, Busy> {
+        match policy {
+            BusyPolicy::Reject => self
+                .try_begin(duration, SessionSource::Request)
+                .inspect_err(|_| self.stats.lock().unwrap().rejected += 1),
+            BusyPolicy::Wait => {
+                if let Ok(session) = self.try_begin(duration, SessionSource::Request) {
+                    return Ok(session);
+                }
+                tracing::info!(
+                    "CPU profiler busy ({}s remaining), queueing request...",
+                    self.remaining().as_secs()
+                );
+                Ok(self.begin(duration, SessionSource::Request).await)
+            }
+        }
+    }
+
+    /// Time left in the active session (zero when idle or overrunning)
+    pub(crate) fn remaining(&self) -> Duration {
+        self.active
+            .lock()
+            .unwrap()
+            .map(|session| session.duration.saturating_sub(session.started.elapsed()))
+            .unwrap_or_default()
+    }
+
+    /// Cumulative counters of finished sessions
+    pub(crate) fn stats(&self) -> SessionStats {
+        let mut stats = *self.stats.lock().unwrap();
+        stats.active = self.active.lock().unwrap().is_some();
+        stats
+    }
+
+    fn started(
+        &'static self,
+        slot: MutexGuard<'static, ()>,
+        duration: Duration,
+        source: SessionSource,
+    ) -> CpuSession {
+        *self.active.lock().unwrap() = Some(ActiveSession {
+            started: Instant::now(),
+            duration,
+            source,
+        });
+        CpuSession {
+            sessions: self,
+            _slot: slot,
+        }
+    }
+}
+
+impl Drop for CpuSession {
+    fn drop(&mut self) {
+        // Still holding the slot here, so nobody else has started a session yet
+        let Some(session) = self.sessions.active.lock().unwrap().take() else {
+            return;
+        };
+
+        let mut stats = self.sessions.stats.lock().unwrap();
+        let totals = match session.source {
+            SessionSource::Request => &mut stats.request,
+            SessionSource::Continuous => &mut stats.continuous,
+        };
+        totals.count += 1;
+        totals.seconds += session.started.elapsed().as_secs_f64();
+    }
+}
+
+impl Busy {
+    /// `429 Too Many Requests` with `Retry-After` set to the remaining seconds
+    pub(crate) fn into_response(self) -> Response<Body> {
+        // Round up so clients never retry before the session has ended
+        let retry_after = self.remaining.as_secs() + u64::from(self.remaining.subsec_nanos() > 0);
+        let retry_after = retry_after.max(1);
+
+        Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header("Content-Type", "text/plain")
+            .header("Retry-After", retry_after.to_string())
+            .body(Body::from(format!(
+                "Error: A CPU profiling session is already in progress ({} seconds remaining).\n\
+                 Only one CPU profile can be collected at a time; retry after {} seconds.\n",
+                retry_after, retry_after
+            )))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::{pin, Pin};
+    use std::task::{Context, Poll, Waker};
+
+    use super::*;
+
+    fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn try_begin_is_busy_while_the_slot_is_held() {
+        static SESSIONS: CpuSessions = CpuSessions::new();
+
+        let session = SESSIONS
+            .try_begin(Duration::from_secs(30), SessionSource::Request)
+            .unwrap();
+        let busy = SESSIONS
+            .try_begin(Duration::from_secs(5), SessionSource::Request)
+            .err()
+            .unwrap();
+        assert!(busy.remaining > Duration::from_secs(29), "{:?}", busy);
+        assert!(busy.remaining <= Duration::from_secs(30), "{:?}", busy);
+        assert!(SESSIONS.stats().active);
+
+        drop(session);
+        let stats = SESSIONS.stats();
+        assert!(!stats.active);
+        assert_eq!(stats.request.count, 1);
+        assert_eq!(SESSIONS.remaining(), Duration::ZERO);
+        assert!(SESSIONS
+            .try_begin(Duration::from_secs(5), SessionSource::Request)
+            .is_ok());
+    }
+
+    #[test]
+    fn reject_policy_counts_rejections() {
+        static SESSIONS: CpuSessions = CpuSessions::new();
+
+        let _session = SESSIONS
+            .try_begin(Duration::from_secs(30), SessionSource::Continuous)
+            .unwrap();
+        let acquire = pin!(SESSIONS.acquire(BusyPolicy::Reject, Duration::from_secs(5)));
+        assert!(matches!(poll_once(acquire), Poll::Ready(Err(_))));
+        assert_eq!(SESSIONS.stats().rejected, 1);
+    }
+
+    #[test]
+    fn wait_policy_acquires_the_slot_after_release() {
+        static SESSIONS: CpuSessions = CpuSessions::new();
+
+        let session = SESSIONS
+            .try_begin(Duration::from_secs(30), SessionSource::Continuous)
+            .unwrap();
+        let mut acquire = pin!(SESSIONS.acquire(BusyPolicy::Wait, Duration::from_secs(5)));
+        assert!(poll_once(acquire.as_mut()).is_pending());
+        assert!(poll_once(acquire.as_mut()).is_pending());
+
+        drop(session);
+        let Poll::Ready(Ok(queued)) = poll_once(acquire.as_mut()) else {
+            panic!("queued request did not get the slot");
+        };
+        assert!(SESSIONS.remaining() <= Duration::from_secs(5));
+        assert!(SESSIONS.remaining() > Duration::from_secs(4));
+
+        drop(queued);
+        let stats = SESSIONS.stats();
+        assert_eq!(stats.continuous.count, 1);
+        assert_eq!(stats.request.count, 1);
+        assert_eq!(stats.rejected, 0);
+    }
+
+    fn retry_after(remaining: Duration) -> (StatusCode, String) {
+        let response = Busy { remaining }.into_response();
+        let retry_after = response.headers()["Retry-After"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        (response.status(), retry_after)
+    }
+
+    #[test]
+    fn busy_response_rounds_retry_after_up() {
+        for (remaining, expected) in [
+            (Duration::from_secs(7), "7"),
+            (Duration::from_millis(7_001), "8"),
+            (Duration::from_millis(2_999), "3"),
+            (Duration::from_nanos(1), "1"),
+            // An overrunning session still asks for a retry later
+            (Duration::ZERO, "1"),
+        ] {
+            assert_eq!(
+                retry_after(remaining),
+                (StatusCode::TOO_MANY_REQUESTS, expected.to_string()),
+                "{:?}",
+                remaining
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Async task dumps: where every task of the runtime is currently waiting
+//!
+//! `GET /debug/tasks` returns the async backtrace of every task, as text by
+//! default or as JSON with `?format=json`. Hung tasks show up with the await
+//! point they are stuck on, without attaching tokio-console:
+//!
+//! ```bash
+//! curl http://localhost:8080/debug/tasks
+//! curl "http://localhost:8080/debug/tasks?format=json"
+//! ```
+//!
+//! Built on tokio's task dumps, which are unstable and Linux-only: enable the
+//! `tokio_unstable` cargo feature and build with `--cfg tokio_unstable`:
+//!
+//! ```bash
+//! RUSTFLAGS="--cfg tokio_unstable" cargo run --features tokio_unstable --example hanging_task
+//! ```
+
+use hyper::Response;
+
+use crate::http::Body;
+
+#[cfg(feature = "tokio_unstable")]
+use {
+    crate::http::{bad_request, json_response, query_param, text_response},
+    hyper::StatusCode,
+    serde::Serialize,
+    std::fmt::Write,
+    std::time::Duration,
+};
+
+/// Give up on a dump after this long
+///
+/// Dumping pauses every worker; a worker blocked in synchronous code never
+/// pauses, and the dump would otherwise wait forever.
+#[cfg(feature = "tokio_unstable")]
+const DUMP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(feature = "tokio_unstable")]
+#[derive(Serialize)]
+struct TaskDump {
+    tasks: Vec<TaskTrace>,
+}
+
+#[cfg(feature = "tokio_unstable")]
+#[derive(Serialize)]
+struct TaskTrace {
+    id: String,
+    /// Async backtrace, one frame per line, outermost first
+    trace: Vec<String>,
+}
+
+/// Dump every task of the current runtime
+#[cfg(feature = "tokio_unstable")]
+pub(crate) async fn handle_task_dump(query: Option<&str>) -> Response<Body> {
+    let json = match query_param(query, "format") {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(format) => {
+            return bad_request(format!(
+                "unknown format: {} (expected text or json)",
+                format
+            ))
+        }
+    };
+
+    let handle = tokio::runtime::Handle::current();
+    let dump = match tokio::time::timeout(DUMP_TIMEOUT, handle.dump()).await {
+        Ok(dump) => dump,
+        Err(_) => {
+            tracing::warn!("Task dump timed out");
+            return text_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!(
+                    "Error: task dump did not complete within {}s.\n\
+                     A runtime worker is probably blocked in synchronous code \
+                     (see the bad_blocking example); a CPU profile will show where.\n",
+                    DUMP_TIMEOUT.as_secs()
+                ),
+            );
+        }
+    };
+
+    let tasks: Vec<_> = dump
+        .tasks()
+        .iter()
+        .map(|task| TaskTrace {
+            id: task.id().to_string(),
+            trace: task
+                .trace()
+                .to_string()
+                .lines()
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect();
+    tracing::info!("Task dump: {} tasks", tasks.len());
+
+    if json {
+        return json_response(&TaskDump { tasks });
+    }
+
+    let mut body = format!("Task dump: {} tasks\n", tasks.len());
+    for task in &tasks {
+        let _ = writeln!(body, "\nTask {}:", task.id);
+        for line in &task.trace {
+            let _ = writeln!(body, "{}", line);
+        }
+    }
+    text_response(StatusCode::OK, body)
+}
+
+/// Task dump endpoint - fallback when built without the `tokio_unstable` feature
+#[cfg(not(feature = "tokio_unstable"))]
+pub(crate) async fn handle_task_dump(_query: Option<&str>) -> Response<Body> {
+    crate::http::error_response(
+        "Task dumps are not enabled in this build. Rebuild on Linux with:\n\
+         RUSTFLAGS=\"--cfg tokio_unstable\" cargo build --features tokio_unstable"
+            .to_string(),
+    )
+}
@@ -0,0 +1,230 @@
+//! Named demo scenarios and the command line the examples share
+//!
+//! Each example registers its scenarios, BAD and GOOD variants alike, in a
+//! [`Registry`] instead of commenting blocks in and out. [`Registry::main`]
+//! then picks what to run from the command line:
+//!
+//! ```bash
+//! cargo run --example lost_waker -- --list
+//! cargo run --example lost_waker -- --scenario lost-waker/timeout,lost-waker/select
+//! cargo run --example large_future -- --all --duration 30
+//! ```
+//!
+//! Without `--scenario` or `--all`, the scenarios marked as defaults run.
+//!
+//! Scenarios are spawned through [`diagnostics::spawn`], so a scenario
+//! blocking its worker is reported in the logs with `RUST_LOG=warn`.
+//!
+//! tokio-console can only watch them in builds with
+//! `RUSTFLAGS="--cfg tokio_unstable"`; see [`init_subscriber`].
+
+use std::future::Future;
+use std::pin::Pin;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Parser;
+use tokio::task::JoinHandle;
+
+use crate::diagnostics;
+
+/// Future driving one scenario
+pub type ScenarioFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A named, runnable demo
+///
+/// `run` is spawned as its own task, so scenarios whose point is the size of
+/// the spawned future must `tokio::spawn` it themselves rather than return it.
+#[derive(Clone, Copy)]
+pub struct Scenario {
+    /// `group/name`, e.g. `lost-waker/timeout`
+    pub name: &'static str,
+    /// One line shown by `--list`
+    pub description: &'static str,
+    /// Run when no scenario is selected explicitly
+    pub default: bool,
+    /// Start the scenario; spawned on the example's runtime
+    pub run: fn() -> ScenarioFuture,
+}
+
+impl Scenario {
+    pub const fn new(
+        name: &'static str,
+        description: &'static str,
+        run: fn() -> ScenarioFuture,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            default: false,
+            run,
+        }
+    }
+
+    /// Also run this scenario when none is selected
+    pub const fn by_default(mut self) -> Self {
+        self.default = true;
+        self
+    }
+
+    /// Spawn the scenario as an instrumented task named after it, so the
+    /// diagnostics, tokio-console and console-lint show which scenario a
+    /// task belongs to
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn spawn(&self) -> JoinHandle<()> {
+        diagnostics::spawn(self.name, (self.run)())
+    }
+}
+
+/// Scenarios of one example, in `--list` order
+pub struct Registry {
+    about: &'static str,
+    scenarios: Vec<Scenario>,
+}
+
+#[derive(Parser)]
+struct Args {
+    /// Scenarios to run (repeatable, or comma-separated)
+    #[arg(long = "scenario", value_delimiter = ',')]
+    scenarios: Vec<String>,
+
+    /// List the scenarios and exit
+    #[arg(long)]
+    list: bool,
+
+    /// Run every scenario
+    #[arg(long, conflicts_with = "scenarios")]
+    all: bool,
+
+    /// Exit after this many seconds instead of running until Ctrl-C
+    #[arg(long)]
+    duration: Option<f64>,
+}
+
+impl Registry {
+    /// `about` is printed when the example starts
+    pub fn new(about: &'static str) -> Self {
+        Self {
+            about,
+            scenarios: Vec::new(),
+        }
+    }
+
+    pub fn scenario(mut self, scenario: Scenario) -> Self {
+        self.scenarios.push(scenario);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Scenario> {
+        self.scenarios.iter().find(|s| s.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Scenario> {
+        self.scenarios.iter()
+    }
+
+    /// Resolve scenario names, failing on the first unknown one
+    pub fn select<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<&Scenario>, String> {
+        names
+            .iter()
+            .map(|name| {
+                let name = name.as_ref();
+                self.get(name)
+                    .ok_or_else(|| format!("unknown scenario: {} (see --list)", name))
+            })
+            .collect()
+    }
+
+    /// Parse the command line, install the subscriber (see
+    /// [`init_subscriber`]) and run the selected scenarios
+    pub fn main(self) -> ExitCode {
+        let args = Args::parse();
+
+        if args.list {
+            print!("{}", self.list());
+            return ExitCode::SUCCESS;
+        }
+
+        let selected = if args.all {
+            self.scenarios.iter().collect()
+        } else if args.scenarios.is_empty() {
+            self.scenarios.iter().filter(|s| s.default).collect()
+        } else {
+            match self.select(&args.scenarios) {
+                Ok(selected) => selected,
+                Err(message) => {
+                    eprintln!("Error: {}", message);
+                    return ExitCode::from(2);
+                }
+            }
+        };
+
+        let duration = match args.duration.map(Duration::try_from_secs_f64) {
+            None => None,
+            Some(Ok(duration)) => Some(duration),
+            Some(Err(_)) => {
+                eprintln!("Error: --duration must be a non-negative number of seconds");
+                return ExitCode::from(2);
+            }
+        };
+
+        init_subscriber();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        {
+            let _runtime = runtime.enter();
+            println!("{}", self.about);
+            for scenario in &selected {
+                println!("[{}] {}", scenario.name, scenario.description);
+                scenario.spawn();
+            }
+            println!();
+        }
+
+        // Wait on this thread rather than on a runtime timer or signal:
+        // scenarios that hog every worker would starve those
+        match duration {
+            Some(duration) => std::thread::sleep(duration),
+            None => loop {
+                std::thread::park();
+            },
+        }
+        // Scenarios that never finish are the point; don't wait for them
+        runtime.shutdown_background();
+
+        ExitCode::SUCCESS
+    }
+
+    /// One line per scenario, defaults marked with `*`
+    fn list(&self) -> String {
+        let width = self
+            .scenarios
+            .iter()
+            .map(|s| s.name.len())
+            .max()
+            .unwrap_or(0);
+        self.scenarios
+            .iter()
+            .map(|s| {
+                let marker = if s.default { '*' } else { ' ' };
+                format!("{} {:width$}  {}\n", marker, s.name, s.description)
+            })
+            .collect()
+    }
+}
+
+/// Install console-subscriber when tokio is built with `--cfg
+/// tokio_unstable`, a log subscriber filtered by `RUST_LOG` otherwise
+///
+/// console-subscriber panics without tokio's task instrumentation. Either
+/// way the diagnostics reports are printed with `RUST_LOG=warn`.
+pub fn init_subscriber() {
+    #[cfg(tokio_unstable)]
+    console_subscriber::init();
+
+    #[cfg(not(tokio_unstable))]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+}
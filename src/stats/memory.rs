@@ -0,0 +1,58 @@
+//! jemalloc allocator statistics via tikv-jemalloc-ctl
+
+use serde::Serialize;
+
+/// Allocator-wide byte counts reported by jemalloc
+///
+/// Each field mirrors the `stats.<name>` mallctl; see the jemalloc manual for
+/// the exact definitions. Roughly, from smallest to largest:
+/// `allocated <= active <= resident`, and `active <= mapped`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MemoryStats {
+    /// Bytes allocated by the application
+    pub allocated: u64,
+    /// Bytes in active pages (a multiple of the page size, >= `allocated`)
+    pub active: u64,
+    /// Bytes in physically resident data pages mapped by the allocator
+    pub resident: u64,
+    /// Bytes in active extents mapped by the allocator
+    pub mapped: u64,
+    /// Bytes in virtual memory mappings retained for reuse, not returned to the OS
+    pub retained: u64,
+    /// Bytes dedicated to jemalloc's own metadata
+    pub metadata: u64,
+}
+
+impl MemoryStats {
+    /// Advance the stats epoch and read fresh values
+    ///
+    /// jemalloc caches its statistics until the epoch is advanced, so reading
+    /// without doing so would keep returning the first snapshot.
+    #[cfg(all(not(target_env = "msvc"), not(target_os = "windows")))]
+    pub fn read() -> Result<Self, String> {
+        use tikv_jemalloc_ctl::{epoch, stats};
+
+        let read = |result: tikv_jemalloc_ctl::Result<usize>, name: &str| {
+            result
+                .map(|bytes| bytes as u64)
+                .map_err(|e| format!("Failed to read jemalloc stats.{}: {}", name, e))
+        };
+
+        epoch::advance().map_err(|e| format!("Failed to advance jemalloc epoch: {}", e))?;
+
+        Ok(Self {
+            allocated: read(stats::allocated::read(), "allocated")?,
+            active: read(stats::active::read(), "active")?,
+            resident: read(stats::resident::read(), "resident")?,
+            mapped: read(stats::mapped::read(), "mapped")?,
+            retained: read(stats::retained::read(), "retained")?,
+            metadata: read(stats::metadata::read(), "metadata")?,
+        })
+    }
+
+    /// jemalloc statistics - Windows/MSVC fallback (jemalloc not available)
+    #[cfg(any(target_env = "msvc", target_os = "windows"))]
+    pub fn read() -> Result<Self, String> {
+        Err("jemalloc statistics are not available on Windows/MSVC targets".to_string())
+    }
+}
@@ -0,0 +1,15 @@
+//! Live process statistics, complementing the sampled profiles
+//!
+//! Profiles answer "where"; these counters answer "how much, right now" and
+//! are cheap enough to poll. [`ProfilingService`] serves them as JSON:
+//!
+//! - `GET /stats/memory` - jemalloc allocator statistics, see [`MemoryStats`]
+//! - `GET /stats/runtime` - tokio scheduler metrics, see [`RuntimeStats`]
+//!
+//! [`ProfilingService`]: crate::profiling::ProfilingService
+
+mod memory;
+mod runtime;
+
+pub use memory::MemoryStats;
+pub use runtime::{RuntimeStats, WorkerStats};
@@ -0,0 +1,100 @@
+//! Tokio scheduler metrics from `RuntimeMetrics`
+
+/// `Some(expr)` when built with `--cfg tokio_unstable`, `None` otherwise
+///
+/// A macro rather than a function so the unstable methods are not even
+/// named in stable builds, where they do not exist.
+macro_rules! unstable {
+    ($metric:expr) => {{
+        #[cfg(tokio_unstable)]
+        let value = Some($metric);
+        #[cfg(not(tokio_unstable))]
+        let value = None;
+        value
+    }};
+}
+
+use serde::Serialize;
+use tokio::runtime::{Handle, RuntimeMetrics};
+
+/// Point-in-time view of the tokio scheduler
+///
+/// Counters are cumulative since the runtime started; take two readings to
+/// get rates. Fields that tokio only exposes with `--cfg tokio_unstable` are
+/// `None` (`null` in JSON) otherwise, as reported by `unstable_metrics`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeStats {
+    /// Whether the `tokio_unstable` metrics below were available
+    pub unstable_metrics: bool,
+    pub num_workers: usize,
+    pub num_alive_tasks: usize,
+    /// Tasks waiting in the shared injection queue
+    pub global_queue_depth: usize,
+    pub num_blocking_threads: Option<usize>,
+    pub num_idle_blocking_threads: Option<usize>,
+    /// Tasks waiting for a blocking thread
+    pub blocking_queue_depth: Option<usize>,
+    pub spawned_tasks_count: Option<u64>,
+    pub workers: Vec<WorkerStats>,
+}
+
+/// Per worker thread counters
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerStats {
+    pub worker: usize,
+    /// Times the worker went idle
+    pub park_count: u64,
+    /// Time spent running tasks, in milliseconds
+    pub busy_duration_ms: f64,
+    /// Tasks waiting in this worker's run queue
+    pub local_queue_depth: Option<usize>,
+    /// Tasks stolen from other workers
+    pub steal_count: Option<u64>,
+    pub poll_count: Option<u64>,
+    pub mean_poll_time_us: Option<f64>,
+}
+
+impl RuntimeStats {
+    /// Metrics of the runtime the caller is running on
+    ///
+    /// Panics outside of a tokio runtime, like [`Handle::current`].
+    pub fn current() -> Self {
+        Self::capture(&Handle::current())
+    }
+
+    /// Metrics of the runtime behind `handle`
+    pub fn capture(handle: &Handle) -> Self {
+        let metrics = handle.metrics();
+        let workers = (0..metrics.num_workers())
+            .map(|worker| WorkerStats::capture(&metrics, worker))
+            .collect();
+
+        Self {
+            unstable_metrics: cfg!(tokio_unstable),
+            num_workers: metrics.num_workers(),
+            num_alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            num_blocking_threads: unstable!(metrics.num_blocking_threads()),
+            num_idle_blocking_threads: unstable!(metrics.num_idle_blocking_threads()),
+            blocking_queue_depth: unstable!(metrics.blocking_queue_depth()),
+            spawned_tasks_count: unstable!(metrics.spawned_tasks_count()),
+            workers,
+        }
+    }
+}
+
+impl WorkerStats {
+    fn capture(metrics: &RuntimeMetrics, worker: usize) -> Self {
+        Self {
+            worker,
+            park_count: metrics.worker_park_count(worker),
+            busy_duration_ms: metrics.worker_total_busy_duration(worker).as_secs_f64() * 1000.0,
+            local_queue_depth: unstable!(metrics.worker_local_queue_depth(worker)),
+            steal_count: unstable!(metrics.worker_steal_count(worker)),
+            poll_count: unstable!(metrics.worker_poll_count(worker)),
+            mean_poll_time_us: unstable!(
+                metrics.worker_mean_poll_time(worker).as_secs_f64() * 1_000_000.0
+            ),
+        }
+    }
+}
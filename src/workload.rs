@@ -0,0 +1,181 @@
+//! Synthetic CPU and memory workloads
+//!
+//! These exist purely to give the profilers something to look at in demos.
+//! Each pattern lives in its own function so it shows up as a distinct stack
+//! in CPU and heap profiles.
+
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+// ============================================================================
+// CPU-intensive workload functions for testing profiling
+// ============================================================================
+
+/// Spawn four tasks mixing the CPU workloads below
+///
+/// Each task yields between iterations so the runtime stays responsive. The
+/// tasks stop on their own after enough iterations, or when aborted.
+pub fn spawn_cpu_load() -> Vec<JoinHandle<()>> {
+    (0..4)
+        .map(|i| {
+            tokio::spawn(async move {
+                let iterations = if i % 2 == 0 { 100000 } else { 50000 };
+                for _ in 0..iterations {
+                    // Mix of different workload patterns
+                    match i % 3 {
+                        0 => {
+                            let _ = fibonacci_work(30);
+                        }
+                        1 => {
+                            let _ = prime_number_work(10000);
+                        }
+                        _ => {
+                            let _ = hash_work(50000);
+                        }
+                    }
+                    // Small yield to let profiler sample
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect()
+}
+
+/// Generate CPU load for `duration`, then abort whatever is still running
+pub async fn run_cpu_load(duration: Duration) {
+    let handles = spawn_cpu_load();
+    tokio::time::sleep(duration).await;
+    for handle in handles {
+        handle.abort();
+    }
+}
+
+/// Compute Fibonacci number (recursive, inefficient on purpose)
+pub fn fibonacci_work(n: u64) -> u64 {
+    match n {
+        0 => 0,
+        1 => 1,
+        n => fibonacci_work(n - 1) + fibonacci_work(n - 2),
+    }
+}
+
+/// Find prime numbers up to n
+pub fn prime_number_work(n: u64) -> Vec<u64> {
+    let mut primes = Vec::new();
+    for num in 2..=n {
+        if is_prime(num) {
+            primes.push(num);
+        }
+    }
+    primes
+}
+
+/// Check if a number is prime
+fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for i in 2..=(n as f64).sqrt() as u64 {
+        if n.is_multiple_of(i) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Hash computation work
+pub fn hash_work(iterations: u64) -> u64 {
+    let mut hash = 0u64;
+    for i in 0..iterations {
+        hash = hash.wrapping_mul(31).wrapping_add(i);
+        hash ^= hash >> 16;
+        hash = hash.wrapping_mul(0x85ebca6b);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(0xc2b2ae35);
+        hash ^= hash >> 16;
+    }
+    hash
+}
+
+// ============================================================================
+// Memory allocation helpers
+// ============================================================================
+
+/// Keep the demo allocations below alive for `duration`
+pub async fn run_memory_load(duration: Duration) {
+    let allocations = create_demo_allocations().await;
+    let megabytes = allocations.iter().map(|v| v.len()).sum::<usize>() as f64 / 1024.0 / 1024.0;
+    tracing::info!("Synthetic allocations: {:.2} MB", megabytes);
+    tokio::time::sleep(duration).await;
+    drop(allocations);
+}
+
+/// Create demo allocations with different patterns for heap profiling
+pub async fn create_demo_allocations() -> Vec<Vec<u8>> {
+    let mut allocations = Vec::new();
+
+    // Pattern 1: Large blocks from different call sites
+    allocations.extend(allocate_large_blocks());
+
+    // Pattern 2: Many small allocations
+    allocations.extend(allocate_small_blocks());
+
+    // Pattern 3: String allocations
+    allocations.extend(allocate_strings());
+
+    // Pattern 4: Async task allocations
+    let mut handles = Vec::new();
+    for i in 0..4 {
+        let handle = tokio::spawn(async move { allocate_from_task(i) });
+        handles.push(handle);
+    }
+
+    for handle in handles {
+        if let Ok(allocs) = handle.await {
+            allocations.extend(allocs);
+        }
+    }
+
+    allocations
+}
+
+/// Allocate large blocks (separate function for distinct stack trace)
+fn allocate_large_blocks() -> Vec<Vec<u8>> {
+    let mut blocks = Vec::new();
+    for i in 0..10 {
+        blocks.push(vec![0xAA; (i + 1) * 1024 * 1024]);
+    }
+    blocks
+}
+
+/// Allocate many small blocks (separate function for distinct stack trace)
+fn allocate_small_blocks() -> Vec<Vec<u8>> {
+    let mut blocks = Vec::new();
+    for i in 0..1000 {
+        blocks.push(vec![0xBB; (i % 100 + 1) * 1024]);
+    }
+    blocks
+}
+
+/// Allocate string data (separate function for distinct stack trace)
+fn allocate_strings() -> Vec<Vec<u8>> {
+    let mut strings = Vec::new();
+    for i in 0..500 {
+        let s = format!(
+            "String allocation {} - This is a demo string for heap profiling visualization",
+            i
+        );
+        strings.push(s.into_bytes());
+    }
+    strings
+}
+
+/// Allocate from async task (separate function for distinct stack trace)
+fn allocate_from_task(task_id: usize) -> Vec<Vec<u8>> {
+    let mut allocations = Vec::new();
+    for i in 0..100 {
+        let size = (i + 1) * 10000 * (task_id + 1);
+        allocations.push(vec![(task_id % 256) as u8; size]);
+    }
+    allocations
+}
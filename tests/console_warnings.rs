@@ -0,0 +1,176 @@
+//! Each example's BAD scenario must trigger its tokio-console warning, and
+//! its GOOD counterpart must not
+//!
+//! The examples' own scenarios run in-process, taken from their
+//! [`Registry`], under a console-subscriber serving on an ephemeral port,
+//! and [`ConsoleCheck`] evaluates the lints from its instrument stream.
+//! tokio only emits task instrumentation when built with `tokio_unstable`,
+//! so the suite is compiled out otherwise; CI runs it with:
+//!
+//! ```bash
+//! RUSTFLAGS="--cfg tokio_unstable" cargo test --test console_warnings
+//! ```
+
+#![cfg(tokio_unstable)]
+
+#[path = "../examples/auto_boxed_future.rs"]
+#[allow(dead_code)]
+mod auto_boxed_future;
+#[path = "../examples/large_future.rs"]
+#[allow(dead_code)]
+mod large_future;
+#[path = "../examples/lost_waker.rs"]
+#[allow(dead_code)]
+mod lost_waker;
+#[path = "../examples/self_wakes.rs"]
+#[allow(dead_code)]
+mod self_wakes;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{mpsc, OnceLock};
+use std::time::Duration;
+
+use console_subscriber::{ConsoleLayer, ServerParts};
+use tokio_console_demo::console_lint::{ConsoleCheck, Lint, Report};
+use tokio_console_demo::scenario::Registry;
+use tracing_subscriber::prelude::*;
+
+/// Long enough for several console publish intervals
+const WATCH: Duration = Duration::from_secs(2);
+
+/// Endpoint of the console server shared by every test in this binary
+///
+/// The console layer has to be the global subscriber, so it is installed
+/// once; tests tell their tasks apart by name.
+fn console_endpoint() -> &'static str {
+    static ENDPOINT: OnceLock<String> = OnceLock::new();
+    ENDPOINT.get_or_init(|| {
+        let (layer, server) = ConsoleLayer::builder()
+            .publish_interval(Duration::from_millis(100))
+            .build();
+        tracing_subscriber::registry().with(layer).init();
+
+        let (addr_tx, addr_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let listener =
+                    tokio::net::TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+                        .await
+                        .unwrap();
+                addr_tx.send(listener.local_addr().unwrap()).unwrap();
+
+                let ServerParts {
+                    instrument_server,
+                    aggregator,
+                    ..
+                } = server.into_parts();
+                tokio::spawn(aggregator.run());
+                let incoming =
+                    tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+                        .unwrap();
+                tonic::transport::Server::builder()
+                    .add_service(instrument_server)
+                    .serve_with_incoming(incoming)
+                    .await
+                    .unwrap();
+            });
+        });
+
+        format!("http://{}", addr_rx.recv().unwrap())
+    })
+}
+
+/// Run the `scenarios` of `registry` and watch the console for `lint`
+///
+/// The scenarios get a runtime of their own: some block their workers,
+/// which must not starve the watch.
+fn check(lint: Lint, registry: Registry, scenarios: &[&str]) -> Report {
+    let endpoint = console_endpoint();
+    let scenarios = registry.select(scenarios).unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    {
+        let _runtime = runtime.enter();
+        for scenario in scenarios {
+            scenario.spawn();
+        }
+    }
+
+    let report = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(
+            ConsoleCheck::new(endpoint)
+                .duration(WATCH)
+                .lints([lint])
+                .run(),
+        )
+        .unwrap();
+    runtime.shutdown_background();
+    report
+}
+
+/// Whether `lint` fired for the task of `scenario` or one it named after
+/// itself, `<scenario>/...`
+fn fired(report: &Report, lint: Lint, scenario: &str) -> bool {
+    report.warnings_for(lint).any(|w| {
+        w.task_name.as_deref().is_some_and(|name| {
+            name == scenario
+                || name
+                    .strip_prefix(scenario)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    })
+}
+
+/// Check that the lint fires for `bad` and not for `good`
+fn assert_bad_and_good(lint: Lint, registry: Registry, bad: &str, good: &str) {
+    let report = check(lint, registry, &[bad, good]);
+    assert!(fired(&report, lint, bad), "{}", report.to_text());
+    assert!(!fired(&report, lint, good), "{}", report.to_text());
+}
+
+#[test]
+fn self_wakes() {
+    assert_bad_and_good(
+        Lint::SelfWakes,
+        self_wakes::registry(),
+        "self-wakes/wake-by-ref",
+        "self-wakes/sleep",
+    );
+}
+
+#[test]
+fn lost_waker() {
+    assert_bad_and_good(
+        Lint::LostWaker,
+        lost_waker::registry(),
+        "lost-waker/custom-future",
+        "lost-waker/healthy",
+    );
+}
+
+#[test]
+fn large_future() {
+    assert_bad_and_good(
+        Lint::LargeFuture,
+        large_future::registry(),
+        "large-future/many-buffers",
+        "large-future/prompt-drop",
+    );
+}
+
+#[test]
+fn auto_boxed_future() {
+    assert_bad_and_good(
+        Lint::AutoBoxedFuture,
+        auto_boxed_future::registry(),
+        "auto-boxed-future/large-state",
+        "auto-boxed-future/explicit-box",
+    );
+}